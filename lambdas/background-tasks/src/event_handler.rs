@@ -0,0 +1,468 @@
+use ::tracing::Instrument;
+use aws_lambda_events::event::sqs::{BatchItemFailure, SqsBatchResponse, SqsEvent, SqsMessage};
+use docbox_core::{
+    aws::{SqsClient, aws_config},
+    tenant::tenant_cache::TenantCache,
+};
+use docbox_database::{
+    DatabasePoolCache, DatabasePoolCacheConfig, DbPool,
+    models::{
+        document_box::DocumentBox,
+        tasks::{Task, TaskId, TaskStatus},
+        tenant::Tenant,
+    },
+};
+use docbox_processing::{
+    ProcessingLayer, ProcessingLayerConfig,
+    office::{OfficeConverter, OfficeConverterConfig, OfficeProcessingLayer},
+};
+use docbox_search::{SearchIndexFactory, SearchIndexFactoryConfig};
+use docbox_secrets::{SecretManager, SecretsManagerConfig};
+use docbox_serverless_shared::{
+    background_task::{
+        BackgroundTaskKind, BackgroundTaskMessage, BackgroundTaskQueue, BackgroundTaskTarget,
+    },
+    reprocess::{ReprocessFilesProgress, reprocess_octet_stream_files_page},
+    search_index::{RebuildSearchIndexProgress, rebuild_document_box_index_page},
+};
+use docbox_storage::{StorageLayerFactory, StorageLayerFactoryConfig};
+use futures::future::join_all;
+use lambda_runtime::{Error, LambdaEvent, tracing};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::OnceCell;
+
+static DEPENDENCIES: OnceCell<Dependencies> = OnceCell::const_new();
+
+/// Number of document boxes to create tasks for within a single message when
+/// fanning out a tenant task
+const FAN_OUT_PAGE_SIZE: u64 = 100;
+
+pub struct Dependencies {
+    pub db_cache: Arc<DatabasePoolCache>,
+    pub tenant_cache: TenantCache,
+    pub search: SearchIndexFactory,
+    pub storage: StorageLayerFactory,
+    pub processing: ProcessingLayer,
+    pub background_tasks: BackgroundTaskQueue,
+}
+
+async fn dependencies() -> Result<Dependencies, Box<dyn std::error::Error + Send + Sync>> {
+    // Create the converter
+    let converter_config = OfficeConverterConfig::from_env();
+    let converter = OfficeConverter::from_config(converter_config)?;
+
+    // Load the config for the processing layer
+    let processing_layer_config = ProcessingLayerConfig::from_env()?;
+
+    // Setup processing layer
+    let processing = ProcessingLayer {
+        office: OfficeProcessingLayer { converter },
+        config: processing_layer_config,
+    };
+
+    let aws_config = aws_config().await;
+
+    // Create secrets manager
+    let secrets_config = SecretsManagerConfig::from_env()?;
+    let secrets = SecretManager::from_config(&aws_config, secrets_config);
+
+    // Load database credentials
+    let db_pool_config = DatabasePoolCacheConfig::from_env()?;
+
+    // Setup database cache / connector
+    let db_cache = Arc::new(DatabasePoolCache::from_config(
+        db_pool_config,
+        secrets.clone(),
+    ));
+
+    // Setup search index factory
+    let search_config = SearchIndexFactoryConfig::from_env()?;
+    let search =
+        SearchIndexFactory::from_config(&aws_config, secrets, db_cache.clone(), search_config)?;
+
+    // Setup storage factory
+    let storage_factory_config = StorageLayerFactoryConfig::from_env()?;
+    let storage = StorageLayerFactory::from_config(&aws_config, storage_factory_config);
+
+    // Create tenant cache
+    let tenant_cache = TenantCache::new();
+
+    // Setup the queue for continuing tasks that don't complete within a single message
+    // Warning: Will panic if the configuration provided is invalid
+    let sqs_client = SqsClient::new(&aws_config);
+    let background_tasks = BackgroundTaskQueue::from_env(sqs_client);
+
+    Ok(Dependencies {
+        db_cache,
+        tenant_cache,
+        search,
+        storage,
+        processing,
+        background_tasks,
+    })
+}
+
+pub(crate) async fn outer_function_handler(
+    event: LambdaEvent<SqsEvent>,
+) -> Result<SqsBatchResponse, Error> {
+    let dependencies = DEPENDENCIES.get_or_try_init(dependencies).await?;
+    function_handler(event, dependencies).await
+}
+
+async fn function_handler(
+    event: LambdaEvent<SqsEvent>,
+    dependencies: &Dependencies,
+) -> Result<SqsBatchResponse, Error> {
+    let results = join_all(
+        event
+            .payload
+            .records
+            .into_iter()
+            .map(|message| handle_message(dependencies, message)),
+    )
+    .await;
+
+    // Report the failed messages so that only they are retried, the
+    // response types are non-exhaustive so they're built from defaults
+    let mut response = SqsBatchResponse::default();
+    response.batch_item_failures = results
+        .into_iter()
+        .filter_map(|result| result.err())
+        .map(|item_identifier| {
+            let mut failure = BatchItemFailure::default();
+            failure.item_identifier = item_identifier;
+            failure
+        })
+        .collect();
+
+    Ok(response)
+}
+
+/// Handles a single message from the queue, provides back the message ID
+/// as the error when the message should be retried
+async fn handle_message(data: &Dependencies, message: SqsMessage) -> Result<(), String> {
+    let message_id = message.message_id.unwrap_or_default();
+
+    let body = match message.body {
+        Some(value) => value,
+        None => {
+            tracing::warn!(%message_id, "background task message missing body");
+            return Ok(());
+        }
+    };
+
+    let task: BackgroundTaskMessage = match serde_json::from_str(&body) {
+        Ok(value) => value,
+        Err(error) => {
+            // Malformed messages will never succeed, don't retry them
+            tracing::error!(?error, %message_id, "invalid background task message");
+            return Ok(());
+        }
+    };
+
+    handle_background_task(data, task).await.map_err(|error| {
+        tracing::error!(?error, %message_id, "failed to handle background task");
+        message_id
+    })
+}
+
+#[derive(Debug, Error)]
+pub enum BackgroundTaskError {
+    #[error("failed to connect to database")]
+    ConnectDatabase,
+
+    #[error("failed to query tenant")]
+    QueryTenant,
+
+    #[error("failed to query task")]
+    QueryTask,
+
+    #[error("failed to query document boxes")]
+    QueryDocumentBoxes,
+
+    #[error("failed to update task")]
+    UpdateTask,
+
+    #[error("failed to publish task continuation")]
+    PublishTask,
+}
+
+/// Handle a background task, errors returned are transient and the
+/// task should be retried
+#[tracing::instrument(skip(data))]
+async fn handle_background_task(
+    data: &Dependencies,
+    message: BackgroundTaskMessage,
+) -> Result<(), BackgroundTaskError> {
+    let tenant = {
+        let db = data.db_cache.get_root_pool().await.map_err(|error| {
+            tracing::error!(?error, "failed to acquire root database pool");
+            BackgroundTaskError::ConnectDatabase
+        })?;
+
+        let tenant = data
+            .tenant_cache
+            .get_tenant(&db, message.tenant_env.clone(), message.tenant_id)
+            .await
+            .map_err(|error| {
+                tracing::error!(?error, "failed to query tenant");
+                BackgroundTaskError::QueryTenant
+            })?;
+
+        match tenant {
+            Some(value) => value,
+            None => {
+                tracing::warn!("background task tenant no longer exists");
+                return Ok(());
+            }
+        }
+    };
+
+    // Provide a span that contains the tenant metadata
+    let span = tracing::info_span!("tenant", tenant_id = %tenant.id, tenant_env = %tenant.env);
+
+    handle_background_task_tenant(data, tenant, message)
+        .instrument(span)
+        .await
+}
+
+/// Handle a background task once the tenant has been identified
+async fn handle_background_task_tenant(
+    data: &Dependencies,
+    tenant: Tenant,
+    message: BackgroundTaskMessage,
+) -> Result<(), BackgroundTaskError> {
+    let db = data
+        .db_cache
+        .get_tenant_pool(&tenant)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to get tenant database pool");
+            BackgroundTaskError::ConnectDatabase
+        })?;
+
+    match message.target {
+        BackgroundTaskTarget::Tenant { offset } => {
+            fan_out_tenant_task(data, &db, &tenant, offset, message.kind).await
+        }
+        BackgroundTaskTarget::DocumentBox { scope, task_id } => {
+            handle_document_box_task(data, &db, &tenant, scope, task_id, message.kind).await
+        }
+    }
+}
+
+/// Creates and publishes a document box task for the next page of document boxes
+/// within the tenant, publishing a message to continue from the next page when
+/// there are more document boxes remaining
+///
+/// Tasks that can't be created or published are failed and skipped rather than
+/// retrying the whole page, retrying would create duplicate tasks for the
+/// document boxes that were already published
+async fn fan_out_tenant_task(
+    data: &Dependencies,
+    db: &DbPool,
+    tenant: &Tenant,
+    offset: u64,
+    kind: BackgroundTaskKind,
+) -> Result<(), BackgroundTaskError> {
+    let document_boxes = DocumentBox::query(db, offset, FAN_OUT_PAGE_SIZE)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to query document boxes");
+            BackgroundTaskError::QueryDocumentBoxes
+        })?;
+
+    let next_offset = offset + document_boxes.len() as u64;
+    let is_last_page = (document_boxes.len() as u64) < FAN_OUT_PAGE_SIZE;
+
+    // Publish the continuation first, when it can't be published the current
+    // message is retried before any tasks have been created for this page
+    if !is_last_page {
+        data.background_tasks
+            .publish(
+                tenant,
+                BackgroundTaskTarget::Tenant {
+                    offset: next_offset,
+                },
+                kind.clone(),
+            )
+            .await
+            .map_err(|error| {
+                tracing::error!(?error, "failed to publish task continuation");
+                BackgroundTaskError::PublishTask
+            })?;
+    }
+
+    for document_box in document_boxes {
+        let scope = document_box.scope;
+
+        let mut task = match Task::create(db, scope.clone()).await {
+            Ok(value) => value,
+            Err(error) => {
+                tracing::error!(?error, %scope, "failed to create document box task");
+                continue;
+            }
+        };
+
+        let target = BackgroundTaskTarget::DocumentBox {
+            scope: scope.clone(),
+            task_id: task.id,
+        };
+
+        if let Err(error) = data
+            .background_tasks
+            .publish(tenant, target, kind.clone())
+            .await
+        {
+            tracing::error!(?error, %scope, "failed to publish document box task");
+
+            // Fail the task rather than leaving a pending task that will never run
+            if let Err(error) = task
+                .complete_task(
+                    db,
+                    TaskStatus::Failed,
+                    Some(serde_json::json!({ "error": error.to_string() })),
+                )
+                .await
+            {
+                tracing::error!(?error, %scope, "failed to mark document box task as failed");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Handle the next message of a task for a single document box
+async fn handle_document_box_task(
+    data: &Dependencies,
+    db: &DbPool,
+    tenant: &Tenant,
+    scope: String,
+    task_id: TaskId,
+    kind: BackgroundTaskKind,
+) -> Result<(), BackgroundTaskError> {
+    let task = Task::find(db, task_id, &scope).await.map_err(|error| {
+        tracing::error!(?error, "failed to query task");
+        BackgroundTaskError::QueryTask
+    })?;
+
+    let mut task = match task {
+        Some(value) => value,
+        None => {
+            tracing::warn!("background task no longer exists");
+            return Ok(());
+        }
+    };
+
+    let outcome = match kind {
+        BackgroundTaskKind::RebuildSearchIndex { progress } => {
+            rebuild_search_index(data, db, tenant, &scope, progress).await
+        }
+        BackgroundTaskKind::ReprocessOctetStreamFiles { progress } => {
+            reprocess_files(data, db, tenant, &scope, progress).await
+        }
+    };
+
+    let (status, output) = match outcome {
+        TaskOutcome::Complete(status, output) => (status, output),
+        // Publish the progress for the next message to continue from, the task is left
+        // pending and the current message is retried if the continuation can't be published
+        TaskOutcome::Continue(kind) => {
+            data.background_tasks
+                .publish(
+                    tenant,
+                    BackgroundTaskTarget::DocumentBox { scope, task_id },
+                    kind,
+                )
+                .await
+                .map_err(|error| {
+                    tracing::error!(?error, "failed to publish task continuation");
+                    BackgroundTaskError::PublishTask
+                })?;
+
+            return Ok(());
+        }
+    };
+
+    task.complete_task(db, status, Some(output))
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to update task");
+            BackgroundTaskError::UpdateTask
+        })?;
+
+    Ok(())
+}
+
+/// Outcome of handling a single message for a task
+enum TaskOutcome {
+    /// Task has finished with the provided status and output
+    Complete(TaskStatus, serde_json::Value),
+    /// Task has more work remaining which is continued by another message
+    Continue(BackgroundTaskKind),
+}
+
+/// Rebuild the next page of the search index for a document box
+async fn rebuild_search_index(
+    data: &Dependencies,
+    db: &DbPool,
+    tenant: &Tenant,
+    scope: &str,
+    mut progress: RebuildSearchIndexProgress,
+) -> TaskOutcome {
+    let search = data.search.create_search_index(tenant);
+    let storage = data.storage.create_storage_layer(tenant);
+
+    match rebuild_document_box_index_page(db, &search, &storage, scope, &mut progress).await {
+        Ok(()) if progress.is_complete() => TaskOutcome::Complete(
+            TaskStatus::Completed,
+            serde_json::to_value(progress.output).unwrap_or_default(),
+        ),
+        Ok(()) => TaskOutcome::Continue(BackgroundTaskKind::RebuildSearchIndex { progress }),
+        Err(error) => {
+            tracing::error!(?error, "failed to rebuild document box search index");
+            TaskOutcome::Complete(
+                TaskStatus::Failed,
+                serde_json::json!({ "error": error.to_string() }),
+            )
+        }
+    }
+}
+
+/// Reprocess the application/octet-stream files within the next page of a document box
+async fn reprocess_files(
+    data: &Dependencies,
+    db: &DbPool,
+    tenant: &Tenant,
+    scope: &str,
+    mut progress: ReprocessFilesProgress,
+) -> TaskOutcome {
+    let search = data.search.create_search_index(tenant);
+    let storage = data.storage.create_storage_layer(tenant);
+
+    match reprocess_octet_stream_files_page(
+        db,
+        &search,
+        &storage,
+        &data.processing,
+        scope,
+        &mut progress,
+    )
+    .await
+    {
+        Ok(()) if progress.is_complete() => TaskOutcome::Complete(
+            TaskStatus::Completed,
+            serde_json::to_value(progress.output).unwrap_or_default(),
+        ),
+        Ok(()) => TaskOutcome::Continue(BackgroundTaskKind::ReprocessOctetStreamFiles { progress }),
+        Err(error) => {
+            tracing::error!(?error, "failed to reprocess octet-stream files");
+            TaskOutcome::Complete(
+                TaskStatus::Failed,
+                serde_json::json!({ "error": error.to_string() }),
+            )
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Builder for Content-Disposition header values (RFC 6266)
+//!
+//! File names are provided as an ASCII `filename` fallback for older clients
+//! along with the full UTF-8 name as an RFC 5987 encoded `filename*`
+
+use axum::http::{HeaderValue, header::InvalidHeaderValue};
+use std::fmt::Write;
+
+/// How the browser should present the file
+#[derive(Debug, Clone, Copy)]
+pub enum DispositionType {
+    /// Display the file within the browser
+    Inline,
+    /// Download the file
+    Attachment,
+}
+
+impl DispositionType {
+    /// Attachment when `download` is true otherwise inline
+    pub fn from_download(download: bool) -> Self {
+        if download {
+            DispositionType::Attachment
+        } else {
+            DispositionType::Inline
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            DispositionType::Inline => "inline",
+            DispositionType::Attachment => "attachment",
+        }
+    }
+}
+
+/// Creates the Content-Disposition value for a file with the provided `file_name`
+pub fn content_disposition_value(ty: DispositionType, file_name: &str) -> String {
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        ty.as_str(),
+        ascii_file_name(file_name),
+        encode_ext_value(file_name)
+    )
+}
+
+/// Creates the Content-Disposition header for a file with the provided `file_name`
+pub fn content_disposition(
+    ty: DispositionType,
+    file_name: &str,
+) -> Result<HeaderValue, InvalidHeaderValue> {
+    HeaderValue::from_str(&content_disposition_value(ty, file_name))
+}
+
+/// Creates the ASCII fallback file name as the contents of a quoted string, non-ASCII
+/// and control characters are replaced and quotes and backslashes are escaped
+fn ascii_file_name(file_name: &str) -> String {
+    let mut output = String::with_capacity(file_name.len());
+
+    for char in file_name.chars() {
+        match char {
+            '"' | '\\' => {
+                output.push('\\');
+                output.push(char);
+            }
+            char if char.is_ascii() && !char.is_ascii_control() => output.push(char),
+            _ => output.push('_'),
+        }
+    }
+
+    output
+}
+
+/// Percent encodes the `value` for use as an RFC 5987 ext-value
+fn encode_ext_value(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        if is_attr_char(byte) {
+            output.push(byte as char);
+        } else {
+            _ = write!(output, "%{byte:02X}");
+        }
+    }
+
+    output
+}
+
+/// Characters allowed unencoded within an ext-value (RFC 5987 attr-char)
+fn is_attr_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric()
+        || matches!(
+            byte,
+            b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~'
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DispositionType, content_disposition, content_disposition_value};
+
+    fn attachment(file_name: &str) -> String {
+        content_disposition_value(DispositionType::Attachment, file_name)
+    }
+
+    #[test]
+    fn plain_file_name() {
+        assert_eq!(
+            attachment("report-2025_final.pdf"),
+            "attachment; filename=\"report-2025_final.pdf\"; filename*=UTF-8''report-2025_final.pdf"
+        );
+        assert_eq!(
+            content_disposition_value(DispositionType::Inline, "a.txt"),
+            "inline; filename=\"a.txt\"; filename*=UTF-8''a.txt"
+        );
+    }
+
+    #[test]
+    fn spaces_are_encoded() {
+        assert_eq!(
+            attachment("my file.txt"),
+            "attachment; filename=\"my file.txt\"; filename*=UTF-8''my%20file.txt"
+        );
+    }
+
+    #[test]
+    fn quotes_and_backslashes_are_escaped() {
+        assert_eq!(
+            attachment("say \"hi\".txt"),
+            "attachment; filename=\"say \\\"hi\\\".txt\"; filename*=UTF-8''say%20%22hi%22.txt"
+        );
+        assert_eq!(
+            attachment("a\\b.txt"),
+            "attachment; filename=\"a\\\\b.txt\"; filename*=UTF-8''a%5Cb.txt"
+        );
+    }
+
+    #[test]
+    fn separators_are_contained() {
+        // Parameters cannot be injected through the file name
+        assert_eq!(
+            attachment("a.txt; filename=evil.exe"),
+            "attachment; filename=\"a.txt; filename=evil.exe\"; filename*=UTF-8''a.txt%3B%20filename%3Devil.exe"
+        );
+        assert_eq!(
+            attachment("a\"; filename=\"evil.exe"),
+            "attachment; filename=\"a\\\"; filename=\\\"evil.exe\"; filename*=UTF-8''a%22%3B%20filename%3D%22evil.exe"
+        );
+    }
+
+    #[test]
+    fn control_characters_are_replaced() {
+        assert_eq!(
+            attachment("a\r\nb\t\u{7f}.txt"),
+            "attachment; filename=\"a__b__.txt\"; filename*=UTF-8''a%0D%0Ab%09%7F.txt"
+        );
+
+        // Header values cannot contain new lines so the header must still be valid
+        assert!(content_disposition(DispositionType::Attachment, "a\r\nSet-Cookie: b").is_ok());
+    }
+
+    #[test]
+    fn non_ascii_file_names() {
+        assert_eq!(
+            attachment("résumé.pdf"),
+            "attachment; filename=\"r_sum_.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+        );
+        assert_eq!(
+            attachment("日本.pdf"),
+            "attachment; filename=\"__.pdf\"; filename*=UTF-8''%E6%97%A5%E6%9C%AC.pdf"
+        );
+        assert_eq!(
+            attachment("😀.png"),
+            "attachment; filename=\"_.png\"; filename*=UTF-8''%F0%9F%98%80.png"
+        );
+    }
+
+    #[test]
+    fn empty_file_name() {
+        assert_eq!(
+            attachment(""),
+            "attachment; filename=\"\"; filename*=UTF-8''"
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use axum::Json;
+use std::sync::LazyLock;
 use utoipa::OpenApi;
 
 use crate::{
@@ -9,6 +11,7 @@ use crate::{
         folder::{self, FOLDER_TAG},
         link::{self, LINK_TAG},
         task::{self, TASK_TAG},
+        trash::{self, TRASH_TAG},
         utils::{self, UTILS_TAG},
     },
 };
@@ -29,6 +32,7 @@ use crate::{
         (name = LINK_TAG, description = "Link related APIs"),
         (name = FOLDER_TAG, description = "Folder related APIs"),
         (name = TASK_TAG, description = "Background task related APIs"),
+        (name = TRASH_TAG, description = "Trash related APIs"),
         (name = ADMIN_TAG, description = "Administrator and higher privilege APIs"),
         (name = UTILS_TAG, description = "Utility APIs")
     ),
@@ -45,6 +49,10 @@ use crate::{
         admin::flush_database_pool_cache,
         admin::flush_tenant_cache,
         admin::http_purge_expired_presigned_tasks,
+        admin::http_migrate_tenants,
+        admin::get_tenant_api_keys,
+        admin::create_tenant_api_key_tenant,
+        admin::delete_tenant_api_key_tenant,
         // Document box routes
         document_box::create,
         document_box::get,
@@ -55,6 +63,13 @@ use crate::{
         file::upload,
         file::create_presigned,
         file::get_presigned,
+        file::list_presigned,
+        file::cancel_presigned,
+        file::get_duplicates,
+        file::create_presigned_multipart,
+        file::get_presigned_multipart_parts,
+        file::complete_presigned_multipart,
+        file::abort_presigned_multipart,
         file::get,
         file::get_children,
         file::get_edit_history,
@@ -85,6 +100,11 @@ use crate::{
         link::delete,
         // Task routes
         task::get,
+        // Trash routes
+        trash::get,
+        trash::restore_file,
+        trash::restore_folder,
+        trash::restore_link,
         // Utils routes
         utils::get_options,
         utils::health,
@@ -94,6 +114,16 @@ use crate::{
 #[allow(unused)]
 pub struct ApiDoc;
 
+/// Generated OpenAPI document, generated once on first access
+static OPENAPI: LazyLock<utoipa::openapi::OpenApi> = LazyLock::new(ApiDoc::openapi);
+
+/// OpenAPI document
+///
+/// Serves the generated OpenAPI document for the API
+pub async fn openapi_json() -> Json<&'static utoipa::openapi::OpenApi> {
+    Json(&OPENAPI)
+}
+
 #[test]
 #[ignore = "generates api documentation"]
 fn generate_api_docs() {
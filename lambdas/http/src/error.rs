@@ -9,7 +9,6 @@ use std::{
     fmt::{Debug, Display},
 };
 use thiserror::Error;
-use tracing::error;
 use utoipa::ToSchema;
 
 /// Type alias for dynamic error handling and JSON responses
@@ -0,0 +1,2 @@
+#[derive(Clone, Copy)]
+pub struct MaxDirectUploadSizeBytes(pub i32);
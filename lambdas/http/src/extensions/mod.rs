@@ -1 +1,3 @@
+pub mod max_direct_upload_size;
 pub mod max_file_size;
+pub mod presigned_download_expiry;
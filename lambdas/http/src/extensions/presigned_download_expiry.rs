@@ -0,0 +1,31 @@
+use std::time::Duration;
+
+/// Bounds for the expiry of presigned download URLs
+#[derive(Clone, Copy)]
+pub struct PresignedDownloadExpiry {
+    /// Minimum expiry time in seconds
+    pub min_seconds: i64,
+    /// Maximum expiry time in seconds
+    pub max_seconds: i64,
+}
+
+impl PresignedDownloadExpiry {
+    /// Expiry used when the request does not specify one
+    pub const DEFAULT_SECONDS: i64 = 900;
+
+    /// Longest expiry supported by presigned URLs (SigV4 limits URLs to 7 days)
+    pub const LIMIT_SECONDS: i64 = 7 * 24 * 60 * 60;
+
+    /// Provides the duration for the requested `expires_at` in seconds, provides
+    /// back [None] when the requested expiry is outside of the bounds
+    pub fn duration(&self, expires_at: Option<i64>) -> Option<Duration> {
+        let expires_at = expires_at
+            .unwrap_or_else(|| Self::DEFAULT_SECONDS.clamp(self.min_seconds, self.max_seconds));
+
+        if expires_at < self.min_seconds || expires_at > self.max_seconds {
+            return None;
+        }
+
+        u64::try_from(expires_at).ok().map(Duration::from_secs)
+    }
+}
@@ -1,5 +1,15 @@
+#![recursion_limit = "256"]
+
 use crate::{
-    extensions::max_file_size::MaxFileSizeBytes, middleware::api_key::ApiKeyLayer, routes::router,
+    extensions::{
+        max_direct_upload_size::MaxDirectUploadSizeBytes, max_file_size::MaxFileSizeBytes,
+        presigned_download_expiry::PresignedDownloadExpiry,
+    },
+    middleware::{
+        api_key::{ApiKeyLayer, load_api_keys},
+        jwt::{JwtAuth, JwtAuthLayer},
+    },
+    routes::{docs_router, router},
 };
 use axum::{Extension, Router};
 use docbox_core::{
@@ -8,20 +18,30 @@ use docbox_core::{
     tenant::tenant_cache::TenantCache,
 };
 use docbox_database::{DatabasePoolCache, DatabasePoolCacheConfig};
+use docbox_processing::{
+    ProcessingLayer, ProcessingLayerConfig,
+    office::{OfficeConverter, OfficeConverterConfig, OfficeProcessingLayer},
+};
 use docbox_search::{SearchIndexFactory, SearchIndexFactoryConfig};
 use docbox_secrets::{SecretManager, SecretsManagerConfig};
+use docbox_serverless_shared::{background_task::BackgroundTaskQueue, s3::S3StorageFactory};
 use docbox_storage::{StorageLayerFactory, StorageLayerFactoryConfig};
 use docbox_web_scraper::{WebsiteMetaService, WebsiteMetaServiceConfig};
-use lambda_http::{Error, run_with_streaming_response, tracing};
+use lambda_http::{Error, tracing};
 use std::sync::Arc;
 use tower_http::trace::TraceLayer;
 
+mod content_disposition;
 pub mod docs;
 mod error;
 mod extensions;
 mod middleware;
 mod models;
+mod multipart;
+mod raw_file;
 mod routes;
+#[cfg(feature = "local-server")]
+mod server;
 
 /// The server version extracted from the Cargo.toml
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -37,7 +57,16 @@ async fn main() -> Result<(), Error> {
 
     let app = app().await?;
 
-    run_with_streaming_response(app).await
+    // Serve the router directly when running as a standalone server
+    #[cfg(feature = "local-server")]
+    {
+        server::serve(app).await
+    }
+
+    #[cfg(not(feature = "local-server"))]
+    {
+        lambda_http::run_with_streaming_response(app).await
+    }
 }
 
 // TODO: Needs a db_cache.close_all() cleanup logic when the program exits
@@ -48,6 +77,51 @@ async fn app() -> Result<Router, Box<dyn std::error::Error + Send + Sync>> {
         Err(_) => 100 * 1000 * 1024,
     };
 
+    let max_direct_upload_size_bytes = match std::env::var("DOCBOX_MAX_DIRECT_UPLOAD_SIZE_BYTES") {
+        Ok(value) => value.parse::<i32>()?,
+        // Default max direct upload size in bytes (4MB), lambda request payloads are limited
+        // to 6MB and binary request bodies are base64 encoded by the gateway
+        Err(_) => 4 * 1000 * 1024,
+    };
+
+    // Bounds for the expiry of presigned download URLs (1 minute to 12 hours by default)
+    let presigned_download_min_expiry =
+        match std::env::var("DOCBOX_PRESIGNED_DOWNLOAD_MIN_EXPIRY_SECONDS") {
+            Ok(value) => value
+                .parse::<i64>()?
+                .clamp(1, PresignedDownloadExpiry::LIMIT_SECONDS),
+            Err(_) => 60,
+        };
+
+    let presigned_download_max_expiry = match std::env::var(
+        "DOCBOX_PRESIGNED_DOWNLOAD_MAX_EXPIRY_SECONDS",
+    ) {
+        Ok(value) => {
+            let value = value.parse::<i64>()?;
+            if value > PresignedDownloadExpiry::LIMIT_SECONDS {
+                tracing::warn!(
+                    "DOCBOX_PRESIGNED_DOWNLOAD_MAX_EXPIRY_SECONDS exceeds the 7 day limit of presigned URLs, using 7 days"
+                );
+            }
+
+            value.min(PresignedDownloadExpiry::LIMIT_SECONDS)
+        }
+        Err(_) => 12 * 60 * 60,
+    };
+
+    // Create the converter
+    let converter_config = OfficeConverterConfig::from_env();
+    let converter = OfficeConverter::from_config(converter_config)?;
+
+    // Load the config for the processing layer
+    let processing_layer_config = ProcessingLayerConfig::from_env()?;
+
+    // Setup processing layer
+    let processing = ProcessingLayer {
+        office: OfficeProcessingLayer { converter },
+        config: processing_layer_config,
+    };
+
     // Create website scraping service
     let website_meta_service_config = WebsiteMetaServiceConfig::from_env()?;
     let website_meta_service = Arc::new(WebsiteMetaService::from_config(
@@ -63,8 +137,23 @@ async fn app() -> Result<Router, Box<dyn std::error::Error + Send + Sync>> {
     // Load database credentials
     let db_pool_config = DatabasePoolCacheConfig::from_env()?;
 
-    // API key
-    let api_key = std::env::var("DOCBOX_API_KEY").ok();
+    // API keys
+    let api_keys = load_api_keys(&secrets).await?;
+
+    // Optional JWT bearer authentication
+    let jwt_auth = JwtAuth::from_env()?;
+
+    // Requests without a bearer token fall through to the API key layer, without
+    // any API keys those requests would not be authenticated at all
+    if jwt_auth.is_some() && api_keys.is_empty() {
+        return Err("JWT authentication requires an API key to be configured for requests without a bearer token".into());
+    }
+
+    // Whether to serve the OpenAPI document and explorer (Enabled by default for debug builds)
+    let api_docs_enabled = match std::env::var("DOCBOX_API_DOCS_ENABLED") {
+        Ok(value) => value.parse::<bool>()?,
+        Err(_) => cfg!(debug_assertions),
+    };
 
     // Setup database cache / connector
     let db_cache = Arc::new(DatabasePoolCache::from_config(
@@ -80,6 +169,9 @@ async fn app() -> Result<Router, Box<dyn std::error::Error + Send + Sync>> {
     let sqs_publisher_factory = SqsEventPublisherFactory::new(sqs_client.clone());
     let events = EventPublisherFactory::new(sqs_publisher_factory);
 
+    // Setup background task queue
+    let background_tasks = BackgroundTaskQueue::from_env(sqs_client.clone());
+
     // Setup search index factory
     let search_config = SearchIndexFactoryConfig::from_env()?;
     let search =
@@ -89,6 +181,9 @@ async fn app() -> Result<Router, Box<dyn std::error::Error + Send + Sync>> {
     let storage_factory_config = StorageLayerFactoryConfig::from_env()?;
     let storage = StorageLayerFactory::from_config(&aws_config, storage_factory_config);
 
+    // Setup direct S3 access for multipart and checksummed presigned uploads
+    let s3 = S3StorageFactory::from_config(&aws_config);
+
     // Create tenant cache
     let tenant_cache = Arc::new(TenantCache::new());
 
@@ -96,21 +191,43 @@ async fn app() -> Result<Router, Box<dyn std::error::Error + Send + Sync>> {
     let mut app = router()
         .layer(Extension(search))
         .layer(Extension(storage))
+        .layer(Extension(s3))
         .layer(Extension(db_cache.clone()))
         .layer(Extension(website_meta_service))
         .layer(Extension(events))
+        .layer(Extension(background_tasks))
         .layer(Extension(tenant_cache))
+        .layer(Extension(processing))
         .layer(Extension(MaxFileSizeBytes(max_file_size_bytes)))
+        .layer(Extension(MaxDirectUploadSizeBytes(
+            max_direct_upload_size_bytes.min(max_file_size_bytes),
+        )))
+        .layer(Extension(PresignedDownloadExpiry {
+            min_seconds: presigned_download_min_expiry,
+            max_seconds: presigned_download_max_expiry.max(presigned_download_min_expiry),
+        }))
         .layer(TraceLayer::new_for_http());
 
-    if let Some(api_key) = api_key {
-        app = app.layer(ApiKeyLayer::new(api_key));
+    if !api_keys.is_empty() {
+        app = app.layer(ApiKeyLayer::new(api_keys).with_tenant_keys(db_cache.clone()));
     } else {
         tracing::warn!(
             "DOCBOX_API_KEY not specified, its recommended you set one for security reasons"
         )
     }
 
+    // Bearer tokens are verified before the API key layer so that
+    // token authenticated requests don't require an API key
+    if let Some(jwt_auth) = jwt_auth {
+        app = app.layer(JwtAuthLayer::new(jwt_auth));
+    }
+
+    // API documentation is public and is added after the API key layer so it
+    // can be accessed directly from the browser
+    if api_docs_enabled {
+        app = app.merge(docs_router());
+    }
+
     // Development mode CORS access for local browser testing
     #[cfg(debug_assertions)]
     let app = app.layer(tower_http::cors::CorsLayer::very_permissive());
@@ -1,5 +1,6 @@
 //! Extractor for getting the user details from the headers set by the API
 
+use super::jwt::JwtClaims;
 use crate::error::{DynHttpError, HttpCommonError, HttpError};
 use axum::{
     extract::FromRequestParts,
@@ -74,6 +75,15 @@ where
     type Rejection = DynHttpError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Requests authenticated using a token act as the user from the token claims
+        if let Some(claims) = parts.extensions.get::<JwtClaims>() {
+            return Ok(ActionUser(Some(ActionUserData {
+                id: claims.sub.clone(),
+                name: claims.name.clone(),
+                image_id: claims.image_id.clone(),
+            })));
+        }
+
         let id = match parts.headers.get(USER_ID_HEADER) {
             Some(value) => {
                 let value_str = value.to_str().map_err(|_| InvalidUserId)?;
@@ -0,0 +1,27 @@
+//! Middleware restricting access to the admin routes
+
+use super::{
+    api_key::{ApiKeyAccess, AuthenticatedApiKey},
+    jwt::{HttpAuthError, JwtClaims},
+};
+use crate::error::DynHttpError;
+use axum::{extract::Request, middleware::Next, response::Response};
+
+/// Restricts access to requests authenticated using an admin API key, token
+/// authenticated requests are never allowed to access the admin routes
+///
+/// When no API keys are configured the API is unauthenticated and the
+/// admin routes are accessible
+pub async fn admin_auth_middleware(request: Request, next: Next) -> Result<Response, DynHttpError> {
+    if request.extensions().get::<JwtClaims>().is_some() {
+        return Err(HttpAuthError::Forbidden.into());
+    }
+
+    if let Some(key) = request.extensions().get::<AuthenticatedApiKey>()
+        && key.access != ApiKeyAccess::Admin
+    {
+        return Err(HttpAuthError::Forbidden.into());
+    }
+
+    Ok(next.run(request).await)
+}
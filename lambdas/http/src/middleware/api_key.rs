@@ -1,22 +1,231 @@
+use super::jwt::JwtClaims;
 use axum::{
     extract::Request,
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use docbox_database::DatabasePoolCache;
+use docbox_secrets::{Secret, SecretManager};
+use docbox_serverless_shared::database::tenant_api_key::{
+    find_tenant_api_key_by_hash, hash_tenant_api_key,
+};
+use serde::Deserialize;
 use std::{
+    collections::{BTreeMap, BTreeSet},
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
 };
+use subtle::ConstantTimeEq;
+use thiserror::Error;
 use tower::{Layer, Service};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const API_KEY_HEADER: &str = "x-docbox-api-key";
+
+/// Name given to the key loaded from the `DOCBOX_API_KEY` variable
+const DEFAULT_KEY_NAME: &str = "default";
+
+/// Name given to the key loaded from the `DOCBOX_ADMIN_API_KEY` variable
+const DEFAULT_ADMIN_KEY_NAME: &str = "admin";
+
+/// Access granted by an API key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApiKeyAccess {
+    /// Access to all tenants and the admin routes
+    Admin,
+    /// Access to all tenants
+    Standard,
+    /// Access to a single tenant, these keys are stored with the tenant
+    /// in the root database rather than configured
+    Tenant { tenant_id: Uuid, tenant_env: String },
+}
+
+/// Named API key
+pub struct ApiKey {
+    pub name: String,
+    pub key: String,
+    pub access: ApiKeyAccess,
+}
+
+/// API key the request was authenticated with, available as a request extension
+#[derive(Debug, Clone)]
+pub struct AuthenticatedApiKey {
+    pub name: String,
+    pub access: ApiKeyAccess,
+}
+
+/// API key within the JSON configuration, either just the key or the
+/// key along with the access it grants
+///
+/// Unknown fields are rejected so keys from older configurations that
+/// specify a tenant are not loaded as keys for all tenants
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum ApiKeyConfig {
+    Key(String),
+    Detailed {
+        key: String,
+        #[serde(default)]
+        admin: bool,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum LoadApiKeysError {
+    #[error("{0} must be a JSON object of key names to keys")]
+    InvalidKeys(&'static str, serde_json::Error),
+
+    #[error("failed to load api keys secret")]
+    LoadSecret,
+
+    #[error("api keys secret not found")]
+    MissingSecret,
+
+    #[error("api keys secret must be a JSON object of key names to keys")]
+    InvalidSecret(serde_json::Error),
+
+    #[error("api key name {0} is used by more than one key")]
+    DuplicateName(String),
+}
+
+/// Loads the API keys from the environment and secrets manager
+///
+/// Keys are loaded from all of the following:
+/// - `DOCBOX_API_KEY` - Single key named "default"
+/// - `DOCBOX_API_KEYS` - JSON object of key names to keys
+/// - `DOCBOX_ADMIN_API_KEY` - Single admin key named "admin"
+/// - `DOCBOX_ADMIN_API_KEYS` - JSON object of key names to admin keys
+/// - `DOCBOX_API_KEYS_SECRET_NAME` - Name of a secret containing a JSON object of key names to keys
+///
+/// Multiple keys allow rotating keys without downtime, the new key is added along
+/// side the old key until all clients have switched to the new key
+///
+/// Keys within the JSON objects can either be the key itself or an object specifying
+/// the key along with `"admin": true` for admin keys
+///
+/// Keys restricted to a single tenant are not configured here, they are stored with
+/// the tenant and managed through the `/admin/tenant-api-keys` routes
+pub async fn load_api_keys(secrets: &SecretManager) -> Result<Vec<ApiKey>, LoadApiKeysError> {
+    let mut keys = Vec::new();
+
+    if let Ok(key) = std::env::var("DOCBOX_API_KEY") {
+        keys.push(ApiKey {
+            name: DEFAULT_KEY_NAME.to_string(),
+            key,
+            access: ApiKeyAccess::Standard,
+        });
+    }
+
+    if let Ok(key) = std::env::var("DOCBOX_ADMIN_API_KEY") {
+        keys.push(ApiKey {
+            name: DEFAULT_ADMIN_KEY_NAME.to_string(),
+            key,
+            access: ApiKeyAccess::Admin,
+        });
+    }
+
+    if let Ok(value) = std::env::var("DOCBOX_API_KEYS") {
+        let named_keys: BTreeMap<String, ApiKeyConfig> = serde_json::from_str(&value)
+            .map_err(|error| LoadApiKeysError::InvalidKeys("DOCBOX_API_KEYS", error))?;
+        keys.extend(parse_api_keys(named_keys, false));
+    }
+
+    if let Ok(value) = std::env::var("DOCBOX_ADMIN_API_KEYS") {
+        let named_keys: BTreeMap<String, ApiKeyConfig> = serde_json::from_str(&value)
+            .map_err(|error| LoadApiKeysError::InvalidKeys("DOCBOX_ADMIN_API_KEYS", error))?;
+        keys.extend(parse_api_keys(named_keys, true));
+    }
+
+    if let Ok(secret_name) = std::env::var("DOCBOX_API_KEYS_SECRET_NAME") {
+        let secret = secrets
+            .get_secret(&secret_name)
+            .await
+            .map_err(|error| {
+                tracing::error!(?error, "failed to load api keys secret");
+                LoadApiKeysError::LoadSecret
+            })?
+            .ok_or(LoadApiKeysError::MissingSecret)?;
+
+        let named_keys: BTreeMap<String, ApiKeyConfig> = match secret {
+            Secret::String(value) => serde_json::from_str(&value),
+            Secret::Binary(value) => serde_json::from_slice(&value),
+        }
+        .map_err(LoadApiKeysError::InvalidSecret)?;
+
+        keys.extend(parse_api_keys(named_keys, false));
+    }
+
+    let keys = validate_api_keys(keys)?;
+
+    // Standard keys are never given admin access, without an admin key nothing
+    // can access the admin routes
+    if !keys.is_empty() && !keys.iter().any(|key| key.access == ApiKeyAccess::Admin) {
+        tracing::error!(
+            "DOCBOX_ADMIN_API_KEY not specified, admin routes are disabled until an admin api key is configured"
+        );
+    }
+
+    Ok(keys)
+}
+
+/// Removes keys with an empty value and ensures each key name is only used once
+/// across all the sources the keys are loaded from
+fn validate_api_keys(mut keys: Vec<ApiKey>) -> Result<Vec<ApiKey>, LoadApiKeysError> {
+    // Empty keys would allow requests with an empty header
+    keys.retain(|key| !key.key.is_empty());
+
+    // Names identify the key in logs so must be unique
+    let mut names = BTreeSet::new();
+    if let Some(key) = keys.iter().find(|key| !names.insert(key.name.as_str())) {
+        return Err(LoadApiKeysError::DuplicateName(key.name.clone()));
+    }
+
+    Ok(keys)
+}
+
+/// Parses the named API keys from the configuration, keys are admin
+/// keys when `admin` is true
+fn parse_api_keys(named_keys: BTreeMap<String, ApiKeyConfig>, admin: bool) -> Vec<ApiKey> {
+    named_keys
+        .into_iter()
+        .map(|(name, config)| {
+            let (key, key_admin) = match config {
+                ApiKeyConfig::Key(key) => (key, false),
+                ApiKeyConfig::Detailed { key, admin } => (key, admin),
+            };
+
+            let access = if admin || key_admin {
+                ApiKeyAccess::Admin
+            } else {
+                ApiKeyAccess::Standard
+            };
+
+            ApiKey { name, key, access }
+        })
+        .collect()
+}
 
 #[derive(Clone)]
 pub struct ApiKeyLayer {
-    key: String,
+    keys: Arc<[ApiKey]>,
+    tenant_keys: Option<Arc<DatabasePoolCache>>,
 }
 
 impl ApiKeyLayer {
-    pub fn new(key: String) -> Self {
-        Self { key }
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        Self {
+            keys: keys.into(),
+            tenant_keys: None,
+        }
+    }
+
+    /// Also accept the tenant API keys stored in the root database of the
+    /// `db_cache` for keys that don't match a configured key
+    pub fn with_tenant_keys(mut self, db_cache: Arc<DatabasePoolCache>) -> Self {
+        self.tenant_keys = Some(db_cache);
+        self
     }
 }
 
@@ -26,7 +235,8 @@ impl<S> Layer<S> for ApiKeyLayer {
     fn layer(&self, inner: S) -> Self::Service {
         ApiKeyMiddleware {
             inner,
-            key: self.key.clone(),
+            keys: self.keys.clone(),
+            tenant_keys: self.tenant_keys.clone(),
         }
     }
 }
@@ -34,12 +244,58 @@ impl<S> Layer<S> for ApiKeyLayer {
 #[derive(Clone)]
 pub struct ApiKeyMiddleware<S> {
     inner: S,
-    key: String,
+    keys: Arc<[ApiKey]>,
+    tenant_keys: Option<Arc<DatabasePoolCache>>,
+}
+
+impl<S> ApiKeyMiddleware<S> {
+    /// Finds the key matching the provided `value`, all keys are compared in
+    /// constant time so the time taken does not reveal which keys partially match
+    fn find_key(&self, value: &[u8]) -> Option<&ApiKey> {
+        self.keys.iter().fold(None, |matched, key| {
+            let is_match: bool = key.key.as_bytes().ct_eq(value).into();
+            if is_match { Some(key) } else { matched }
+        })
+    }
+}
+
+/// Finds the tenant API key matching the provided `value` within the root database
+async fn find_tenant_key(
+    db_cache: &DatabasePoolCache,
+    value: &str,
+) -> Result<Option<AuthenticatedApiKey>, Response> {
+    let db = db_cache.get_root_pool().await.map_err(|cause| {
+        tracing::error!(?cause, "failed to connect to root database");
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })?;
+
+    let key = find_tenant_api_key_by_hash(&db, &hash_tenant_api_key(value))
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query tenant api key");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        })?;
+
+    Ok(key.map(|key| AuthenticatedApiKey {
+        name: key.name,
+        access: ApiKeyAccess::Tenant {
+            tenant_id: key.tenant_id,
+            tenant_env: key.tenant_env,
+        },
+    }))
+}
+
+fn invalid_key_response() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        "Missing or invalid x-docbox-api-key",
+    )
+        .into_response()
 }
 
 impl<S> Service<Request> for ApiKeyMiddleware<S>
 where
-    S: Service<Request, Response = Response> + Send + 'static,
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
     S::Future: Send + 'static,
 {
     type Response = S::Response;
@@ -51,8 +307,13 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, request: Request) -> Self::Future {
-        let header = match request.headers().get("x-docbox-api-key") {
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        // Requests authenticated using a bearer token don't require an API key
+        if request.extensions().get::<JwtClaims>().is_some() {
+            return Box::pin(self.inner.call(request));
+        }
+
+        let header = match request.headers().get(API_KEY_HEADER) {
             Some(value) => value,
             None => {
                 return Box::pin(async move {
@@ -61,16 +322,170 @@ where
             }
         };
 
-        if header.to_str().is_ok_and(|value| value.ne(&self.key)) {
-            return Box::pin(async move {
-                Ok((
-                    StatusCode::UNAUTHORIZED,
-                    "Missing or invalid x-docbox-api-key",
-                )
-                    .into_response())
-            });
+        // Reject malformed keys rather than comparing the raw header bytes
+        let value = match header.to_str() {
+            Ok(value) => value,
+            Err(_) => return Box::pin(async move { Ok(invalid_key_response()) }),
+        };
+
+        if let Some(key) = self.find_key(value.as_bytes()) {
+            let key = AuthenticatedApiKey {
+                name: key.name.clone(),
+                access: key.access.clone(),
+            };
+
+            let span = tracing::info_span!("api_key", api_key = %key.name);
+            request.extensions_mut().insert(key);
+
+            return Box::pin(self.inner.call(request).instrument(span));
+        }
+
+        let Some(db_cache) = self.tenant_keys.clone() else {
+            return Box::pin(async move { Ok(invalid_key_response()) });
+        };
+
+        // The inner service was driven to readiness, take it and leave a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let value = value.to_string();
+
+        Box::pin(async move {
+            let key = match find_tenant_key(&db_cache, &value).await {
+                Ok(Some(key)) => key,
+                Ok(None) => return Ok(invalid_key_response()),
+                Err(response) => return Ok(response),
+            };
+
+            let span = tracing::info_span!(
+                "api_key",
+                api_key = %key.name,
+                tenant_key = true
+            );
+            request.extensions_mut().insert(key);
+
+            inner.call(request).instrument(span).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ApiKey, ApiKeyAccess, ApiKeyConfig, ApiKeyLayer, LoadApiKeysError, parse_api_keys,
+        validate_api_keys,
+    };
+    use std::collections::BTreeMap;
+    use tower::Layer;
+
+    fn parse(value: &str, admin: bool) -> Vec<ApiKey> {
+        let named_keys: BTreeMap<String, ApiKeyConfig> = serde_json::from_str(value).unwrap();
+        parse_api_keys(named_keys, admin)
+    }
+
+    fn key(name: &str, key: &str) -> ApiKey {
+        ApiKey {
+            name: name.to_string(),
+            key: key.to_string(),
+            access: ApiKeyAccess::Standard,
+        }
+    }
+
+    #[test]
+    fn parse_plain_keys() {
+        let keys = parse(r#"{"first": "key-1", "second": "key-2"}"#, false);
+
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].name, "first");
+        assert_eq!(keys[0].key, "key-1");
+        assert_eq!(keys[0].access, ApiKeyAccess::Standard);
+
+        let keys = parse(r#"{"first": "key-1"}"#, true);
+        assert_eq!(keys[0].access, ApiKeyAccess::Admin);
+    }
+
+    #[test]
+    fn parse_detailed_keys() {
+        let value = r#"{
+            "admin": { "key": "key-1", "admin": true },
+            "standard": { "key": "key-2" }
+        }"#;
+
+        let keys = parse(value, false);
+
+        assert_eq!(keys[0].access, ApiKeyAccess::Admin);
+        assert_eq!(keys[1].access, ApiKeyAccess::Standard);
+
+        // Keys within the admin keys are always admin keys
+        let keys = parse(value, true);
+        assert!(keys.iter().all(|key| key.access == ApiKeyAccess::Admin));
+    }
+
+    #[test]
+    fn parse_malformed_entries() {
+        for value in [
+            r#"["key-1"]"#,
+            r#"{"first": 1}"#,
+            r#"{"first": null}"#,
+            r#"{"first": { "admin": true }}"#,
+            r#"{"first": { "key": "key-1", "tenant_id": "00000000-0000-0000-0000-000000000000" }}"#,
+        ] {
+            let result = serde_json::from_str::<BTreeMap<String, ApiKeyConfig>>(value);
+            assert!(result.is_err(), "{value} should not parse");
+        }
+    }
+
+    #[test]
+    fn validate_removes_empty_keys() {
+        let keys = validate_api_keys(vec![key("empty", ""), key("first", "key-1")]).unwrap();
+
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].name, "first");
+
+        let keys = parse(r#"{"empty": "", "detailed": { "key": "" }}"#, false);
+        assert!(validate_api_keys(keys).unwrap().is_empty());
+    }
+
+    #[test]
+    fn validate_duplicate_names() {
+        let result = validate_api_keys(vec![key("default", "key-1"), key("default", "key-2")]);
+        assert!(matches!(result, Err(LoadApiKeysError::DuplicateName(name)) if name == "default"));
+
+        // Empty keys are removed before checking names
+        let result = validate_api_keys(vec![key("default", ""), key("default", "key-2")]);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn find_matching_key() {
+        let middleware =
+            ApiKeyLayer::new(vec![key("first", "key-1"), key("second", "key-2")]).layer(());
+
+        assert_eq!(
+            middleware.find_key(b"key-1").map(|key| key.name.as_str()),
+            Some("first")
+        );
+        assert_eq!(
+            middleware.find_key(b"key-2").map(|key| key.name.as_str()),
+            Some("second")
+        );
+    }
+
+    #[test]
+    fn find_non_matching_key() {
+        let middleware = ApiKeyLayer::new(vec![key("first", "key-1")]).layer(());
+
+        for value in [
+            b"".as_slice(),
+            b"key-",
+            b"key-12",
+            b"KEY-1",
+            b" key-1",
+            b"key-2",
+        ] {
+            assert!(middleware.find_key(value).is_none());
         }
 
-        Box::pin(self.inner.call(request))
+        let middleware = ApiKeyLayer::new(Vec::new()).layer(());
+        assert!(middleware.find_key(b"").is_none());
     }
 }
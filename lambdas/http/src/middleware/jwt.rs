@@ -0,0 +1,530 @@
+//! Optional JWT bearer authentication, allows clients such as a frontend to call the
+//! API directly using a signed token instead of going through a trusted proxy that
+//! holds the API key
+//!
+//! The tenant and acting user are derived from the token claims, and the document
+//! boxes the token can access are restricted to the scopes matching the claims
+
+use crate::error::{DynHttpError, HttpError};
+use axum::{
+    extract::{Path, Request},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, decode, decode_header, jwk::JwkSet};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use thiserror::Error;
+use tower::{Layer, Service};
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Claims provided by a verified token, available as a request extension
+#[derive(Debug, Clone, Deserialize)]
+pub struct JwtClaims {
+    /// ID of the user the token was issued to
+    pub sub: String,
+    /// ID of the tenant the token can access
+    pub tenant_id: Uuid,
+    /// Environment of the tenant the token can access
+    pub tenant_env: String,
+    /// Optional name of the user
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Optional image ID of the user
+    #[serde(default)]
+    pub image_id: Option<String>,
+    /// Patterns for the document box scopes the token can access, patterns
+    /// can use `*` to match any sequence of characters
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+impl JwtClaims {
+    /// Checks if the claims allow access to the document box `scope`
+    pub fn can_access_scope(&self, scope: &str) -> bool {
+        self.scopes
+            .iter()
+            .any(|pattern| scope_matches(pattern, scope))
+    }
+}
+
+/// Checks if `scope` matches the `pattern` where `*` matches any
+/// sequence of characters
+fn scope_matches(pattern: &str, scope: &str) -> bool {
+    let mut parts = pattern.split('*');
+
+    // Pattern without any wildcards must match exactly
+    let first = parts.next().unwrap_or_default();
+    let mut remaining = match scope.strip_prefix(first) {
+        Some(value) => value,
+        None => return false,
+    };
+
+    let mut parts = parts.peekable();
+    if parts.peek().is_none() {
+        return remaining.is_empty();
+    }
+
+    while let Some(part) = parts.next() {
+        // Last part must match the end of the scope
+        if parts.peek().is_none() {
+            return remaining.ends_with(part);
+        }
+
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
+
+#[derive(Debug, Error)]
+pub enum HttpAuthError {
+    #[error("access to this resource is not allowed")]
+    Forbidden,
+}
+
+impl HttpError for HttpAuthError {
+    fn status(&self) -> StatusCode {
+        match self {
+            HttpAuthError::Forbidden => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LoadJwtAuthError {
+    #[error("failed to read DOCBOX_JWT_JWKS_FILE")]
+    ReadJwksFile(std::io::Error),
+
+    #[error("invalid JWKS")]
+    InvalidJwks(serde_json::Error),
+
+    #[error("invalid JWK in JWKS")]
+    InvalidJwk(jsonwebtoken::errors::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyJwtError {
+    #[error("token algorithm is not supported")]
+    UnsupportedAlgorithm,
+
+    #[error("token signing key is unknown")]
+    UnknownKey,
+
+    #[error(transparent)]
+    Invalid(#[from] jsonwebtoken::errors::Error),
+}
+
+/// Verifier for JWT bearer tokens
+pub struct JwtAuth {
+    /// Key for verifying HS256 tokens
+    hs256: Option<DecodingKey>,
+    /// Keys for verifying RS256 tokens along with their key ID
+    rs256: Vec<(Option<String>, DecodingKey)>,
+    /// Required token issuer
+    issuer: Option<String>,
+    /// Required token audience
+    audience: Option<String>,
+}
+
+impl JwtAuth {
+    /// Load the JWT configuration from the environment, provides back [None]
+    /// when no verification keys are configured
+    ///
+    /// - `DOCBOX_JWT_HS256_SECRET` - Shared secret for HS256 tokens
+    /// - `DOCBOX_JWT_JWKS` - JWKS JSON containing the keys for RS256 tokens
+    /// - `DOCBOX_JWT_JWKS_FILE` - Path to a file containing the JWKS JSON
+    /// - `DOCBOX_JWT_ISSUER` - Optional required issuer
+    /// - `DOCBOX_JWT_AUDIENCE` - Optional required audience
+    pub fn from_env() -> Result<Option<JwtAuth>, LoadJwtAuthError> {
+        let hs256 = std::env::var("DOCBOX_JWT_HS256_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty())
+            .map(|secret| DecodingKey::from_secret(secret.as_bytes()));
+
+        let jwks = match (
+            std::env::var("DOCBOX_JWT_JWKS"),
+            std::env::var("DOCBOX_JWT_JWKS_FILE"),
+        ) {
+            (Ok(value), _) => Some(value),
+            (_, Ok(path)) => {
+                Some(std::fs::read_to_string(path).map_err(LoadJwtAuthError::ReadJwksFile)?)
+            }
+            _ => None,
+        };
+
+        let rs256 = match jwks {
+            Some(jwks) => {
+                let jwks: JwkSet =
+                    serde_json::from_str(&jwks).map_err(LoadJwtAuthError::InvalidJwks)?;
+                jwks.keys
+                    .iter()
+                    .map(|jwk| {
+                        let key =
+                            DecodingKey::from_jwk(jwk).map_err(LoadJwtAuthError::InvalidJwk)?;
+                        Ok((jwk.common.key_id.clone(), key))
+                    })
+                    .collect::<Result<Vec<_>, LoadJwtAuthError>>()?
+            }
+            None => Vec::new(),
+        };
+
+        if hs256.is_none() && rs256.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(JwtAuth {
+            hs256,
+            rs256,
+            issuer: std::env::var("DOCBOX_JWT_ISSUER").ok(),
+            audience: std::env::var("DOCBOX_JWT_AUDIENCE").ok(),
+        }))
+    }
+
+    /// Verifies the `token` providing back its claims
+    pub fn verify(&self, token: &str) -> Result<JwtClaims, VerifyJwtError> {
+        let header = decode_header(token)?;
+
+        let key = match header.alg {
+            Algorithm::HS256 => self.hs256.as_ref().ok_or(VerifyJwtError::UnknownKey)?,
+            Algorithm::RS256 => self.find_rs256_key(header.kid.as_deref())?,
+            _ => return Err(VerifyJwtError::UnsupportedAlgorithm),
+        };
+
+        let mut validation = Validation::new(header.alg);
+
+        // Configured claims must be present, otherwise they are only
+        // checked when the token happens to include them
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+            validation.required_spec_claims.insert("iss".to_string());
+        }
+
+        match &self.audience {
+            Some(audience) => {
+                validation.set_audience(&[audience]);
+                validation.required_spec_claims.insert("aud".to_string());
+            }
+            None => validation.validate_aud = false,
+        }
+
+        let data = decode::<JwtClaims>(token, key, &validation)?;
+        Ok(data.claims)
+    }
+
+    /// Finds the RS256 key for the `key_id`, tokens without a key ID
+    /// can only be used when there is a single key
+    fn find_rs256_key(&self, key_id: Option<&str>) -> Result<&DecodingKey, VerifyJwtError> {
+        let key = match key_id {
+            Some(key_id) => self
+                .rs256
+                .iter()
+                .find(|(id, _)| id.as_deref() == Some(key_id)),
+            None if self.rs256.len() == 1 => self.rs256.first(),
+            None => None,
+        };
+
+        key.map(|(_, key)| key).ok_or(VerifyJwtError::UnknownKey)
+    }
+}
+
+#[derive(Clone)]
+pub struct JwtAuthLayer {
+    auth: Arc<JwtAuth>,
+}
+
+impl JwtAuthLayer {
+    pub fn new(auth: JwtAuth) -> Self {
+        Self {
+            auth: Arc::new(auth),
+        }
+    }
+}
+
+impl<S> Layer<S> for JwtAuthLayer {
+    type Service = JwtAuthMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        JwtAuthMiddleware {
+            inner,
+            auth: self.auth.clone(),
+        }
+    }
+}
+
+/// Middleware that verifies bearer tokens, requests with a valid token have their
+/// [JwtClaims] added to the request extensions and don't require an API key
+#[derive(Clone)]
+pub struct JwtAuthMiddleware<S> {
+    inner: S,
+    auth: Arc<JwtAuth>,
+}
+
+impl<S> Service<Request> for JwtAuthMiddleware<S>
+where
+    S: Service<Request, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request) -> Self::Future {
+        let token = match request.headers().get(header::AUTHORIZATION) {
+            Some(value) => match value.to_str() {
+                Ok(value) => value.strip_prefix("Bearer "),
+                Err(_) => return Box::pin(async move { Ok(invalid_token_response()) }),
+            },
+            None => None,
+        };
+
+        // Requests without a bearer token are authenticated by other means
+        let token = match token {
+            Some(value) => value,
+            None => return Box::pin(self.inner.call(request)),
+        };
+
+        let claims = match self.auth.verify(token) {
+            Ok(value) => value,
+            Err(error) => {
+                tracing::debug!(?error, "invalid bearer token");
+                return Box::pin(async move { Ok(invalid_token_response()) });
+            }
+        };
+
+        let span = tracing::info_span!("jwt", user_id = %claims.sub);
+        request.extensions_mut().insert(claims);
+
+        Box::pin(self.inner.call(request).instrument(span))
+    }
+}
+
+fn invalid_token_response() -> Response {
+    (StatusCode::UNAUTHORIZED, "Invalid bearer token").into_response()
+}
+
+/// Restricts access to the `{scope}` of the request to tokens with
+/// claims matching the scope
+pub async fn jwt_scope_middleware(
+    Path(params): Path<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    if let Some(claims) = request.extensions().get::<JwtClaims>() {
+        let allowed = params
+            .get("scope")
+            .is_some_and(|scope| claims.can_access_scope(scope));
+
+        if !allowed {
+            return Err(HttpAuthError::Forbidden.into());
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{JwtAuth, JwtClaims, VerifyJwtError, scope_matches};
+    use jsonwebtoken::{
+        Algorithm, DecodingKey, EncodingKey, Header, encode, get_current_timestamp,
+    };
+    use serde_json::{Value, json};
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn auth() -> JwtAuth {
+        JwtAuth {
+            hs256: Some(DecodingKey::from_secret(SECRET)),
+            rs256: Vec::new(),
+            issuer: None,
+            audience: None,
+        }
+    }
+
+    fn claims() -> Value {
+        json!({
+            "sub": "user-1",
+            "tenant_id": "00000000-0000-0000-0000-000000000001",
+            "tenant_env": "Development",
+            "scopes": ["user:1:*"],
+            "exp": get_current_timestamp() + 3600,
+        })
+    }
+
+    fn token(algorithm: Algorithm, claims: &Value, secret: &[u8]) -> String {
+        encode(
+            &Header::new(algorithm),
+            claims,
+            &EncodingKey::from_secret(secret),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn verify_valid_token() {
+        let claims = auth()
+            .verify(&token(Algorithm::HS256, &claims(), SECRET))
+            .unwrap();
+
+        assert_eq!(claims.sub, "user-1");
+        assert_eq!(claims.tenant_env, "Development");
+        assert_eq!(claims.scopes, vec!["user:1:*".to_string()]);
+        assert!(claims.name.is_none());
+    }
+
+    #[test]
+    fn verify_invalid_signature() {
+        let result = auth().verify(&token(Algorithm::HS256, &claims(), b"other-secret"));
+        assert!(matches!(result, Err(VerifyJwtError::Invalid(_))));
+    }
+
+    #[test]
+    fn verify_malformed_token() {
+        for value in ["", "not-a-token", "a.b.c"] {
+            assert!(matches!(
+                auth().verify(value),
+                Err(VerifyJwtError::Invalid(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn verify_expired_token() {
+        let mut claims = claims();
+        claims["exp"] = json!(get_current_timestamp() - 3600);
+
+        let result = auth().verify(&token(Algorithm::HS256, &claims, SECRET));
+        assert!(matches!(result, Err(VerifyJwtError::Invalid(_))));
+
+        // Tokens must expire
+        claims.as_object_mut().unwrap().remove("exp");
+
+        let result = auth().verify(&token(Algorithm::HS256, &claims, SECRET));
+        assert!(matches!(result, Err(VerifyJwtError::Invalid(_))));
+    }
+
+    #[test]
+    fn verify_missing_claims() {
+        let mut claims = claims();
+        claims.as_object_mut().unwrap().remove("tenant_id");
+
+        let result = auth().verify(&token(Algorithm::HS256, &claims, SECRET));
+        assert!(matches!(result, Err(VerifyJwtError::Invalid(_))));
+    }
+
+    #[test]
+    fn verify_issuer_and_audience() {
+        let auth = JwtAuth {
+            issuer: Some("docbox-issuer".to_string()),
+            audience: Some("docbox".to_string()),
+            ..auth()
+        };
+
+        let mut claims = claims();
+        let result = auth.verify(&token(Algorithm::HS256, &claims, SECRET));
+        assert!(matches!(result, Err(VerifyJwtError::Invalid(_))));
+
+        claims["iss"] = json!("docbox-issuer");
+        claims["aud"] = json!("other");
+        let result = auth.verify(&token(Algorithm::HS256, &claims, SECRET));
+        assert!(matches!(result, Err(VerifyJwtError::Invalid(_))));
+
+        claims["aud"] = json!("docbox");
+        assert!(
+            auth.verify(&token(Algorithm::HS256, &claims, SECRET))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn verify_unsupported_algorithm() {
+        let result = auth().verify(&token(Algorithm::HS384, &claims(), SECRET));
+        assert!(matches!(result, Err(VerifyJwtError::UnsupportedAlgorithm)));
+    }
+
+    #[test]
+    fn verify_unknown_key() {
+        // Header of {"alg":"RS256","typ":"JWT"}, no RS256 keys are configured
+        let result = auth().verify("eyJhbGciOiJSUzI1NiIsInR5cCI6IkpXVCJ9.e30.c2ln");
+        assert!(matches!(result, Err(VerifyJwtError::UnknownKey)));
+
+        let auth = JwtAuth {
+            hs256: None,
+            ..auth()
+        };
+        let result = auth.verify(&token(Algorithm::HS256, &claims(), SECRET));
+        assert!(matches!(result, Err(VerifyJwtError::UnknownKey)));
+    }
+
+    #[test]
+    fn scope_exact_match() {
+        assert!(scope_matches("user:1", "user:1"));
+        assert!(!scope_matches("user:1", "user:10"));
+        assert!(!scope_matches("user:1", "user:"));
+        assert!(!scope_matches("user:1", ""));
+        assert!(scope_matches("", ""));
+        assert!(!scope_matches("", "user:1"));
+    }
+
+    #[test]
+    fn scope_wildcard_match() {
+        assert!(scope_matches("*", ""));
+        assert!(scope_matches("*", "user:1"));
+        assert!(scope_matches("**", "user:1"));
+
+        // Trailing wildcard
+        assert!(scope_matches("user:1:*", "user:1:"));
+        assert!(scope_matches("user:1:*", "user:1:docs"));
+        assert!(!scope_matches("user:1:*", "user:1"));
+        assert!(!scope_matches("user:1:*", "user:10:docs"));
+
+        // Leading wildcard
+        assert!(scope_matches("*:docs", "user:1:docs"));
+        assert!(!scope_matches("*:docs", "user:1:docs:other"));
+
+        // Wildcards in the middle
+        assert!(scope_matches("user:*:docs", "user:1:docs"));
+        assert!(scope_matches("user:*:docs", "user::docs"));
+        assert!(!scope_matches("user:*:docs", "user:1:files"));
+        assert!(scope_matches("a*b*c", "abc"));
+        assert!(scope_matches("a*b*c", "axxbyyc"));
+        assert!(!scope_matches("a*b*c", "acb"));
+    }
+
+    #[test]
+    fn scope_wildcard_parts_do_not_overlap() {
+        assert!(!scope_matches("a*a", "a"));
+        assert!(scope_matches("a*a", "aa"));
+        assert!(!scope_matches("ab*ba", "aba"));
+        assert!(scope_matches("ab*ba", "abba"));
+    }
+
+    #[test]
+    fn claims_scope_access() {
+        let claims: JwtClaims = serde_json::from_value(claims()).unwrap();
+        assert!(claims.can_access_scope("user:1:docs"));
+        assert!(!claims.can_access_scope("user:2:docs"));
+
+        let claims = JwtClaims {
+            scopes: Vec::new(),
+            ..claims
+        };
+        assert!(!claims.can_access_scope("user:1:docs"));
+    }
+}
@@ -1,3 +1,6 @@
 pub mod action_user;
+pub mod admin;
 pub mod api_key;
+pub mod jwt;
 pub mod tenant;
+pub mod upload_limit;
@@ -2,6 +2,10 @@
 
 use std::sync::Arc;
 
+use super::{
+    api_key::{ApiKeyAccess, AuthenticatedApiKey},
+    jwt::{HttpAuthError, JwtClaims},
+};
 use crate::error::{DynHttpError, HttpCommonError, HttpError};
 use axum::{
     Extension,
@@ -16,6 +20,7 @@ use docbox_core::{
 };
 use docbox_database::{DatabasePoolCache, DbPool, models::tenant::Tenant};
 use docbox_search::{SearchIndexFactory, TenantSearchIndex};
+use docbox_serverless_shared::s3::{S3StorageFactory, TenantS3Storage};
 use docbox_storage::{StorageLayerFactory, TenantStorageLayer};
 use thiserror::Error;
 use tracing::Instrument;
@@ -27,6 +32,19 @@ const TENANT_ID_HEADER: &str = "x-tenant-id";
 // Header for the tenant env
 const TENANT_ENV_HEADER: &str = "x-tenant-env";
 
+/// OpenAPI param for optionally specifying the tenant identifier headers
+#[derive(IntoParams)]
+#[into_params(parameter_in = Header)]
+#[allow(unused)]
+pub struct OptionalTenantParams {
+    /// Optional ID of the tenant you are targeting
+    #[param(rename = "x-tenant-id")]
+    pub tenant_id: Option<String>,
+    /// Optional environment of the tenant you are targeting
+    #[param(rename = "x-tenant-env")]
+    pub tenant_env: Option<String>,
+}
+
 /// OpenAPI param for requiring the tenant identifier headers
 #[derive(IntoParams)]
 #[into_params(parameter_in = Header)]
@@ -49,8 +67,19 @@ pub async fn tenant_auth_middleware(
     mut request: Request,
     next: Next,
 ) -> Result<Response, DynHttpError> {
-    // Extract the request tenant
-    let tenant = extract_tenant(&headers, &db_cache, &tenant_cache).await?;
+    // Extract the request tenant, requests authenticated using a token can
+    // only access the tenant from the token claims
+    let tenant = match request.extensions().get::<JwtClaims>() {
+        Some(claims) => extract_claims_tenant(&headers, claims, &db_cache, &tenant_cache).await?,
+        None => {
+            // Tenant API keys can only act on their own tenant
+            if let Some(key) = request.extensions().get::<AuthenticatedApiKey>() {
+                ensure_key_tenant(&headers, key)?;
+            }
+
+            extract_tenant(&headers, &db_cache, &tenant_cache).await?
+        }
+    };
 
     // Provide a request span that contains the tenant metadata
     let span = tracing::info_span!("tenant", tenant_id = %tenant.id, tenant_env = %tenant.env);
@@ -94,6 +123,22 @@ impl HttpError for ExtractTenantError {
     }
 }
 
+/// Extracts the target tenant for the provided request if the request
+/// specifies a tenant ID
+pub async fn extract_optional_tenant(
+    headers: &HeaderMap,
+    db_cache: &DatabasePoolCache,
+    tenant_cache: &TenantCache,
+) -> Result<Option<Tenant>, DynHttpError> {
+    if !headers.contains_key(TENANT_ID_HEADER) {
+        return Ok(None);
+    }
+
+    extract_tenant(headers, db_cache, tenant_cache)
+        .await
+        .map(Some)
+}
+
 /// Extracts the target tenant for the provided request
 pub async fn extract_tenant(
     headers: &HeaderMap,
@@ -117,6 +162,77 @@ pub async fn extract_tenant(
 
     let env = get_tenant_env(headers)?;
 
+    find_tenant(db_cache, tenant_cache, env, tenant_id).await
+}
+
+/// Ensures the API `key` is allowed to access the tenant requested by the `headers`
+pub fn ensure_key_tenant(
+    headers: &HeaderMap,
+    key: &AuthenticatedApiKey,
+) -> Result<(), HttpAuthError> {
+    match &key.access {
+        ApiKeyAccess::Tenant {
+            tenant_id,
+            tenant_env,
+        } => ensure_headers_tenant(headers, *tenant_id, tenant_env),
+        ApiKeyAccess::Admin | ApiKeyAccess::Standard => Ok(()),
+    }
+}
+
+/// Ensures any tenant specified by the `headers` matches the allowed tenant
+fn ensure_headers_tenant(
+    headers: &HeaderMap,
+    tenant_id: Uuid,
+    tenant_env: &str,
+) -> Result<(), HttpAuthError> {
+    let header_tenant_id = headers.get(TENANT_ID_HEADER).map(|value| {
+        value
+            .to_str()
+            .ok()
+            .and_then(|value| value.parse::<Uuid>().ok())
+    });
+
+    if header_tenant_id.is_some_and(|header_tenant_id| header_tenant_id != Some(tenant_id)) {
+        return Err(HttpAuthError::Forbidden);
+    }
+
+    let header_tenant_env = headers
+        .get(TENANT_ENV_HEADER)
+        .map(|value| value.to_str().ok());
+
+    if header_tenant_env.is_some_and(|header_tenant_env| header_tenant_env != Some(tenant_env)) {
+        return Err(HttpAuthError::Forbidden);
+    }
+
+    Ok(())
+}
+
+/// Extracts the tenant from the token `claims`, requests that also specify
+/// tenant headers for a different tenant are rejected
+async fn extract_claims_tenant(
+    headers: &HeaderMap,
+    claims: &JwtClaims,
+    db_cache: &DatabasePoolCache,
+    tenant_cache: &TenantCache,
+) -> Result<Tenant, DynHttpError> {
+    ensure_headers_tenant(headers, claims.tenant_id, &claims.tenant_env)?;
+
+    find_tenant(
+        db_cache,
+        tenant_cache,
+        claims.tenant_env.clone(),
+        claims.tenant_id,
+    )
+    .await
+}
+
+/// Finds the tenant with the provided `env` and `tenant_id`
+async fn find_tenant(
+    db_cache: &DatabasePoolCache,
+    tenant_cache: &TenantCache,
+    env: String,
+    tenant_id: Uuid,
+) -> Result<Tenant, DynHttpError> {
     let db = db_cache.get_root_pool().await.map_err(|cause| {
         tracing::error!(?cause, "failed to connect to root database");
         HttpCommonError::ServerError
@@ -224,6 +340,31 @@ where
     }
 }
 
+/// Tenant storage access for multipart uploads
+pub struct TenantS3(pub TenantS3Storage);
+
+impl<S> FromRequestParts<S> for TenantS3
+where
+    S: Send + Sync,
+{
+    type Rejection = DynHttpError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // Extract current tenant
+        let tenant: &Tenant = parts.extensions.get().ok_or_else(|| {
+            tracing::error!("tenant not available within this scope");
+            HttpCommonError::ServerError
+        })?;
+
+        let factory: &S3StorageFactory = parts.extensions.get().ok_or_else(|| {
+            tracing::error!("multipart storage is missing");
+            HttpCommonError::ServerError
+        })?;
+
+        Ok(TenantS3(factory.create_s3_storage(tenant)))
+    }
+}
+
 /// Tenant events access
 pub struct TenantEvents(pub TenantEventPublisher);
 
@@ -0,0 +1,57 @@
+//! Middleware for limiting the size of direct (non-presigned) file uploads
+
+use crate::{
+    error::{DynHttpError, HttpCommonError},
+    extensions::max_direct_upload_size::MaxDirectUploadSizeBytes,
+};
+use axum::{
+    Extension,
+    body::Body,
+    extract::Request,
+    http::{HeaderMap, header},
+    middleware::Next,
+    response::Response,
+};
+use http_body_util::{BodyExt, LengthLimitError, Limited};
+
+/// Rejects direct uploads that are larger than the allowed direct upload size. Lambda
+/// request payloads are limited in size so large files must use presigned uploads instead.
+///
+/// Requests that specify a content length above the limit are rejected as unsupported,
+/// the request body is also read through a limit to catch requests that don't specify
+/// a length (chunked bodies) so they are rejected with the same response
+pub async fn direct_upload_limit_middleware(
+    headers: HeaderMap,
+    Extension(MaxDirectUploadSizeBytes(max_upload_size)): Extension<MaxDirectUploadSizeBytes>,
+    request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    let max_upload_size = max_upload_size as usize;
+
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+
+    if content_length.is_some_and(|content_length| content_length > max_upload_size) {
+        return Err(HttpCommonError::Unsupported.into());
+    }
+
+    // Lambda request payloads are already held in memory so collecting the body
+    // here lets an overflow be reported before the multipart extractor sees it
+    let (parts, body) = request.into_parts();
+    let body = match Limited::new(body, max_upload_size).collect().await {
+        Ok(body) => body.to_bytes(),
+        Err(cause) if cause.downcast_ref::<LengthLimitError>().is_some() => {
+            return Err(HttpCommonError::Unsupported.into());
+        }
+        Err(cause) => {
+            tracing::error!(?cause, "failed to read upload request body");
+            return Err(HttpCommonError::ServerError.into());
+        }
+    };
+
+    let request = Request::from_parts(parts, Body::from(body));
+
+    Ok(next.run(request).await)
+}
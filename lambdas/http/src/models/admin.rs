@@ -1,7 +1,13 @@
+use crate::error::HttpError;
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
 use docbox_database::models::document_box::DocumentBox;
+use docbox_serverless_shared::database::tenant_api_key::TenantApiKey;
 use garde::Validate;
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use utoipa::ToSchema;
+use uuid::Uuid;
 
 #[derive(Default, Debug, Validate, Deserialize, Serialize, ToSchema)]
 #[serde(default)]
@@ -10,8 +16,9 @@ pub struct TenantDocumentBoxesRequest {
     #[garde(skip)]
     pub query: Option<String>,
 
-    /// Number of items to include in the response
+    /// Number of items to include in the response (Maximum 100)
     #[garde(skip)]
+    #[schema(maximum = 100)]
     pub size: Option<u16>,
 
     /// Offset to start results from
@@ -36,3 +43,84 @@ pub struct TenantStatsResponse {
     /// Total size of all files within the tenant
     pub file_size: i64,
 }
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PurgeExpiredPresignedTasksResponse {
+    /// Number of tenants that were purged successfully
+    pub tenants_processed: usize,
+    /// Number of tenants that failed to purge, details of the
+    /// failures are available in the server logs
+    pub tenants_failed: usize,
+    /// Number of expired presigned tasks that were deleted
+    pub tasks_deleted: usize,
+    /// Number of incomplete uploads that were deleted from storage
+    pub objects_deleted: usize,
+    /// Number of incomplete multipart uploads that were aborted
+    pub multipart_uploads_aborted: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MigrateTenantsResponse {
+    /// Number of tenants that were migrated successfully
+    pub tenants_processed: usize,
+    /// Number of tenants that failed to migrate, details of the
+    /// failures are available in the server logs
+    pub tenants_failed: usize,
+    /// Number of migrations that were applied across all tenants
+    pub migrations_applied: usize,
+}
+
+/// Request to create an API key for the tenant
+#[derive(Debug, Validate, Deserialize, ToSchema)]
+pub struct CreateTenantApiKeyRequest {
+    /// Name for the key, unique within the tenant
+    #[garde(length(min = 1, max = 255))]
+    #[schema(min_length = 1, max_length = 255)]
+    pub name: String,
+}
+
+/// API key that can only act on its own tenant
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TenantApiKeyResponse {
+    pub id: Uuid,
+    /// Name of the key
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<TenantApiKey> for TenantApiKeyResponse {
+    fn from(value: TenantApiKey) -> Self {
+        Self {
+            id: value.id,
+            name: value.name,
+            created_at: value.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateTenantApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: TenantApiKeyResponse,
+    /// The key to use in the x-docbox-api-key header, only the hash of the
+    /// key is stored so this cannot be retrieved again
+    pub key: String,
+}
+
+#[derive(Debug, Error)]
+pub enum HttpTenantApiKeyError {
+    #[error("unknown api key")]
+    UnknownApiKey,
+
+    #[error("an api key with this name already exists for the tenant")]
+    DuplicateName,
+}
+
+impl HttpError for HttpTenantApiKeyError {
+    fn status(&self) -> StatusCode {
+        match self {
+            HttpTenantApiKeyError::UnknownApiKey => StatusCode::NOT_FOUND,
+            HttpTenantApiKeyError::DuplicateName => StatusCode::CONFLICT,
+        }
+    }
+}
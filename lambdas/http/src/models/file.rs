@@ -1,20 +1,64 @@
 use crate::error::HttpError;
 use axum::http::StatusCode;
+use axum_typed_multipart::{FieldData, TryFromMultipart};
+use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use docbox_database::models::{
-    file::{FileId, FileWithExtra},
+    file::{File, FileId, FileWithExtra},
     folder::FolderId,
     generated_file::GeneratedFile,
     presigned_upload_task::PresignedUploadTaskId,
 };
 use docbox_processing::ProcessingConfig;
+use docbox_serverless_shared::checksum::{ChecksumAlgorithm, UploadChecksum};
 use garde::Validate;
 use mime::Mime;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use std::collections::HashMap;
 use thiserror::Error;
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
+
+/// Request to directly upload a file
+#[derive(Debug, TryFromMultipart, Validate, ToSchema)]
+pub struct UploadFileRequest {
+    /// Name of the file being uploaded
+    #[garde(length(min = 1, max = 255))]
+    #[schema(min_length = 1, max_length = 255)]
+    pub name: String,
+
+    /// ID of the folder to store the file in
+    #[garde(skip)]
+    #[schema(value_type = Uuid)]
+    pub folder_id: String,
+
+    /// Optional parent file ID
+    #[garde(skip)]
+    #[schema(value_type = Option<Uuid>)]
+    pub parent_id: Option<String>,
+
+    /// Mime type of the file, when not specified the mime type
+    /// of the file field is used
+    #[garde(skip)]
+    pub mime: Option<String>,
+
+    /// The file contents
+    #[garde(skip)]
+    #[form_data(limit = "unlimited")]
+    #[schema(value_type = Vec<u8>, format = Binary)]
+    pub file: FieldData<Bytes>,
+
+    /// Optional processing config encoded as JSON
+    #[garde(skip)]
+    #[schema(value_type = Option<ProcessingConfig>)]
+    pub processing_config: Option<String>,
+
+    /// Whether to disable mime sniffing for the file. When false/not specified
+    /// if a application/octet-stream mime type is provided the file name
+    /// will be used to attempt to determine the real mime type
+    #[garde(skip)]
+    pub disable_mime_sniffing: Option<bool>,
+}
 
 /// Request to create a new presigned file upload
 #[serde_as]
@@ -55,6 +99,175 @@ pub struct CreatePresignedRequest {
     /// will be used to attempt to determine the real mime type
     #[garde(skip)]
     pub disable_mime_sniffing: Option<bool>,
+
+    /// Optional checksum of the file contents, the checksum is included in the
+    /// required headers of the upload and the upload is rejected when the
+    /// uploaded contents do not match
+    #[garde(dive)]
+    pub checksum: Option<UploadChecksumRequest>,
+
+    /// How to handle the file being a duplicate of an existing file within
+    /// the document box, policies other than "allow" require a sha256 checksum
+    #[garde(skip)]
+    #[serde(default)]
+    pub on_duplicate: DuplicatePolicy,
+}
+
+/// Policy for uploading a file with the same contents as an existing file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// Upload the file regardless of existing files
+    #[default]
+    Allow,
+    /// Reject the upload with the ID of the existing file
+    Reject,
+    /// Skip the upload and respond with the existing file
+    ReturnExisting,
+}
+
+/// Query for listing the groups of duplicate files
+#[derive(Default, Debug, Deserialize, IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct ListDuplicatesQuery {
+    /// Number of groups to include in the response (Maximum 100)
+    #[param(maximum = 100)]
+    pub size: Option<u16>,
+
+    /// Offset of the group to start results from
+    pub offset: Option<u64>,
+}
+
+/// Files within a document box that have identical contents
+#[derive(Serialize, ToSchema)]
+pub struct DuplicateFilesResponse {
+    pub groups: Vec<DuplicateFileGroup>,
+    /// Total number of groups of duplicate files
+    pub total: i64,
+}
+
+/// Group of files with identical contents
+#[derive(Serialize, ToSchema)]
+pub struct DuplicateFileGroup {
+    /// Hash of the file contents
+    pub hash: String,
+    /// Size of each file in bytes
+    pub size: i32,
+    /// Files with the contents, oldest first
+    pub files: Vec<FileItem>,
+}
+
+/// File stored within a document box
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FileItem {
+    /// Unique identifier for the file
+    #[schema(value_type = Uuid)]
+    pub id: FileId,
+    /// Name of the file
+    pub name: String,
+    /// Mime type of the file content
+    pub mime: String,
+    /// Parent folder ID
+    #[schema(value_type = Uuid)]
+    pub folder_id: FolderId,
+    /// Hash of the file contents
+    pub hash: String,
+    /// Size of the file in bytes
+    pub size: i32,
+    /// Whether the file was determined to be encrypted when processing
+    pub encrypted: bool,
+    /// Whether the file is marked as pinned
+    pub pinned: bool,
+    /// When the file was created
+    pub created_at: DateTime<Utc>,
+    /// ID of the user who created the file
+    pub created_by: Option<String>,
+    /// Optional parent file ID if the file is a child of another file
+    #[schema(value_type = Option<Uuid>)]
+    pub parent_id: Option<FileId>,
+}
+
+impl From<File> for FileItem {
+    fn from(value: File) -> Self {
+        FileItem {
+            id: value.id,
+            name: value.name,
+            mime: value.mime,
+            folder_id: value.folder_id,
+            hash: value.hash,
+            size: value.size,
+            encrypted: value.encrypted,
+            pinned: value.pinned,
+            created_at: value.created_at,
+            created_by: value.created_by,
+            parent_id: value.parent_id,
+        }
+    }
+}
+
+/// Checksum of the contents of a file being uploaded
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+pub struct UploadChecksumRequest {
+    /// Algorithm used to compute the checksum
+    #[garde(skip)]
+    pub algorithm: UploadChecksumAlgorithm,
+
+    /// Base64 encoded checksum
+    #[garde(length(min = 1))]
+    #[schema(min_length = 1)]
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum UploadChecksumAlgorithm {
+    Sha256,
+    Crc32c,
+    Md5,
+}
+
+impl From<UploadChecksumRequest> for UploadChecksum {
+    fn from(value: UploadChecksumRequest) -> Self {
+        let algorithm = match value.algorithm {
+            UploadChecksumAlgorithm::Sha256 => ChecksumAlgorithm::Sha256,
+            UploadChecksumAlgorithm::Crc32c => ChecksumAlgorithm::Crc32c,
+            UploadChecksumAlgorithm::Md5 => ChecksumAlgorithm::Md5,
+        };
+
+        UploadChecksum {
+            algorithm,
+            value: value.value,
+        }
+    }
+}
+
+impl From<ChecksumAlgorithm> for UploadChecksumAlgorithm {
+    fn from(value: ChecksumAlgorithm) -> Self {
+        match value {
+            ChecksumAlgorithm::Sha256 => UploadChecksumAlgorithm::Sha256,
+            ChecksumAlgorithm::Crc32c => UploadChecksumAlgorithm::Crc32c,
+            ChecksumAlgorithm::Md5 => UploadChecksumAlgorithm::Md5,
+        }
+    }
+}
+
+/// Verified checksum of the contents of a file uploaded with a checksum
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FileChecksumResponse {
+    /// Algorithm used to compute the checksum
+    pub algorithm: UploadChecksumAlgorithm,
+    /// Base64 encoded checksum
+    pub value: String,
+}
+
+impl From<UploadChecksum> for FileChecksumResponse {
+    fn from(value: UploadChecksum) -> Self {
+        FileChecksumResponse {
+            algorithm: value.algorithm.into(),
+            value: value.value,
+        }
+    }
 }
 
 #[derive(Serialize, ToSchema)]
@@ -66,6 +279,109 @@ pub struct PresignedUploadResponse {
     pub headers: HashMap<String, String>,
 }
 
+/// Query for listing the outstanding presigned uploads
+#[derive(Default, Debug, Deserialize, IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct ListPresignedQuery {
+    /// Number of items to include in the response (Maximum 100)
+    #[param(maximum = 100)]
+    pub size: Option<u16>,
+
+    /// Offset to start results from
+    pub offset: Option<u64>,
+}
+
+/// Outstanding presigned uploads within a document box
+#[derive(Serialize, ToSchema)]
+pub struct ListPresignedResponse {
+    pub results: Vec<PresignedUploadTaskResponse>,
+    pub total: i64,
+}
+
+/// Presigned upload that is pending or has failed
+#[derive(Serialize, ToSchema)]
+pub struct PresignedUploadTaskResponse {
+    #[schema(value_type = Uuid)]
+    pub task_id: PresignedUploadTaskId,
+    /// Name of the file being uploaded
+    pub name: String,
+    /// Mime type of the file being uploaded
+    pub mime: String,
+    /// Size of the file being uploaded
+    pub size: i32,
+    /// Folder the file will be stored in
+    #[schema(value_type = Uuid)]
+    pub folder_id: FolderId,
+    /// Whether the upload is a multipart upload
+    pub multipart: bool,
+    #[serde(flatten)]
+    pub status: PresignedUploadTaskStatus,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Status of a presigned upload
+#[derive(Serialize, ToSchema)]
+#[serde(tag = "status")]
+pub enum PresignedUploadTaskStatus {
+    Pending,
+    Failed {
+        error: String,
+    },
+    Completed {
+        #[schema(value_type = Uuid)]
+        file_id: FileId,
+    },
+}
+
+/// Response for creating a multipart presigned upload
+#[derive(Serialize, ToSchema)]
+pub struct PresignedMultipartUploadResponse {
+    #[schema(value_type = Uuid)]
+    pub task_id: PresignedUploadTaskId,
+    /// ID of the multipart upload within storage
+    pub upload_id: String,
+    /// Size in bytes of each part, the last part may be smaller
+    pub part_size: i64,
+    /// Presigned requests for uploading each part
+    pub parts: Vec<PresignedUploadPart>,
+    /// When the upload and the part requests expire
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Presigned request for uploading a single part of a multipart upload
+#[derive(Serialize, ToSchema)]
+pub struct PresignedUploadPart {
+    pub part_number: i32,
+    pub method: String,
+    pub uri: String,
+    pub headers: HashMap<String, String>,
+}
+
+/// Current state of a multipart presigned upload, used to resume an upload
+#[derive(Serialize, ToSchema)]
+pub struct PresignedMultipartPartsResponse {
+    /// ID of the multipart upload within storage
+    pub upload_id: String,
+    /// Size in bytes of each part, the last part may be smaller
+    pub part_size: i64,
+    /// Parts that have already been uploaded
+    pub uploaded: Vec<UploadedPartResponse>,
+    /// Presigned requests for the parts that still need to be uploaded
+    pub pending: Vec<PresignedUploadPart>,
+    /// When the upload and the part requests expire
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Part of a multipart upload that has been uploaded
+#[derive(Serialize, ToSchema)]
+pub struct UploadedPartResponse {
+    pub part_number: i32,
+    pub etag: String,
+    pub size: i64,
+}
+
 #[derive(Serialize, ToSchema)]
 #[serde(tag = "status")]
 #[allow(clippy::large_enum_variant)]
@@ -106,6 +422,8 @@ pub struct FileResponse {
     pub file: FileWithExtra,
     /// Files generated from the file (thumbnails, pdf, etc)
     pub generated: Vec<GeneratedFile>,
+    /// Checksum of the file contents when the file was uploaded with a checksum
+    pub checksum: Option<FileChecksumResponse>,
 }
 
 #[derive(Default, Debug, Deserialize)]
@@ -114,13 +432,21 @@ pub struct RawFileQuery {
     pub download: bool,
 }
 
-/// Request to rename and or move a file
+/// Request to create a presigned download URL for a file
 #[derive(Debug, Validate, Deserialize, ToSchema)]
 pub struct GetPresignedRequest {
-    /// Expiry time in seconds for the presigned URL
-    #[garde(skip)]
-    #[schema(default = 900)]
+    /// Expiry time in seconds for the presigned URL, must be within the
+    /// configured minimum and maximum expiry
+    #[garde(inner(range(min = 1)))]
+    #[schema(default = 900, minimum = 1)]
     pub expires_at: Option<i64>,
+
+    /// Whether the file should be downloaded as an attachment rather
+    /// than displayed inline in the browser
+    #[garde(skip)]
+    #[serde(default)]
+    #[schema(default = false)]
+    pub download: bool,
 }
 
 #[derive(Serialize, ToSchema)]
@@ -141,22 +467,76 @@ pub enum HttpFileError {
 
     #[error("file size is larger than the maximum allowed size (requested: {0}, maximum: {1})")]
     FileTooLarge(i32, i32),
+
+    #[error("invalid folder id")]
+    InvalidFolderId,
+
+    #[error("invalid parent file id")]
+    InvalidParentId,
+
+    #[error("invalid mime type")]
+    InvalidMimeType,
+
+    #[error("invalid processing config")]
+    InvalidProcessingConfig,
+
     #[error("no matching generated file")]
     NoMatchingGenerated,
 
     #[allow(unused)]
     #[error("unsupported file type")]
     UnsupportedFileType,
+
+    #[error("presigned url expiry must be between {0} and {1} seconds")]
+    InvalidPresignedExpiry(i64, i64),
+
+    #[error("presigned upload is not a multipart upload")]
+    NotMultipartUpload,
+
+    #[error("presigned upload is no longer pending")]
+    PresignedUploadNotPending,
+
+    #[error("presigned upload has expired")]
+    PresignedUploadExpired,
+
+    #[error("multipart upload is missing {0} parts")]
+    MissingUploadParts(usize),
+
+    #[error("multipart upload parts do not match the upload size")]
+    InvalidUploadParts,
+
+    #[error("checksum must be the base64 encoded checksum for the algorithm")]
+    InvalidChecksum,
+
+    #[error("detecting duplicate files requires a sha256 checksum")]
+    DuplicatePolicyRequiresChecksum,
+
+    #[error("file is a duplicate of the existing file {0}")]
+    DuplicateFile(FileId),
 }
 
 impl HttpError for HttpFileError {
     fn status(&self) -> axum::http::StatusCode {
         match self {
-            HttpFileError::FileTooLarge(_, _) => StatusCode::BAD_REQUEST,
+            HttpFileError::FileTooLarge(_, _)
+            | HttpFileError::InvalidFolderId
+            | HttpFileError::InvalidParentId
+            | HttpFileError::InvalidMimeType
+            | HttpFileError::InvalidProcessingConfig => StatusCode::BAD_REQUEST,
             HttpFileError::UnknownFile
             | HttpFileError::NoMatchingGenerated
             | HttpFileError::UnknownTask => StatusCode::NOT_FOUND,
-            HttpFileError::UnsupportedFileType => StatusCode::BAD_REQUEST,
+            HttpFileError::UnsupportedFileType
+            | HttpFileError::InvalidPresignedExpiry(_, _)
+            | HttpFileError::NotMultipartUpload
+            | HttpFileError::MissingUploadParts(_)
+            | HttpFileError::InvalidUploadParts
+            | HttpFileError::InvalidChecksum
+            | HttpFileError::DuplicatePolicyRequiresChecksum => StatusCode::BAD_REQUEST,
+            HttpFileError::PresignedUploadNotPending | HttpFileError::DuplicateFile(_) => {
+                StatusCode::CONFLICT
+            }
+            HttpFileError::PresignedUploadExpired => StatusCode::GONE,
         }
     }
 }
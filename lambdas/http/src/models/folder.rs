@@ -1,7 +1,8 @@
 use crate::error::HttpError;
 use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
 use docbox_core::folders::create_folder::CreateFolderError;
-use docbox_database::models::folder::{FolderId, FolderWithExtra, ResolvedFolderWithExtra};
+use docbox_database::models::folder::{Folder, FolderId, FolderWithExtra, ResolvedFolderWithExtra};
 use garde::Validate;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -31,6 +32,41 @@ pub struct FolderResponse {
     pub children: ResolvedFolderWithExtra,
 }
 
+/// Folder stored within a document box
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FolderItem {
+    /// Unique identifier for the folder
+    #[schema(value_type = Uuid)]
+    pub id: FolderId,
+    /// Name of the folder
+    pub name: String,
+    /// Whether the folder is marked as pinned
+    pub pinned: bool,
+    /// Scope of the document box the folder belongs to
+    pub document_box: String,
+    /// Parent folder ID if the folder is a child
+    #[schema(value_type = Option<Uuid>)]
+    pub folder_id: Option<FolderId>,
+    /// When the folder was created
+    pub created_at: DateTime<Utc>,
+    /// ID of the user who created the folder
+    pub created_by: Option<String>,
+}
+
+impl From<Folder> for FolderItem {
+    fn from(value: Folder) -> Self {
+        FolderItem {
+            id: value.id,
+            name: value.name,
+            pinned: value.pinned,
+            document_box: value.document_box,
+            folder_id: value.folder_id,
+            created_at: value.created_at,
+            created_by: value.created_by,
+        }
+    }
+}
+
 /// Request to rename and or move a folder
 #[derive(Debug, Validate, Deserialize, ToSchema)]
 pub struct UpdateFolderRequest {
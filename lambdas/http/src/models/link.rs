@@ -1,7 +1,11 @@
 use crate::error::HttpError;
 use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
 use docbox_core::links::create_link::CreateLinkError;
-use docbox_database::models::folder::FolderId;
+use docbox_database::models::{
+    folder::FolderId,
+    link::{Link, LinkId},
+};
 use garde::Validate;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -26,6 +30,41 @@ pub struct CreateLink {
     pub folder_id: FolderId,
 }
 
+/// Link stored within a document box
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LinkItem {
+    /// Unique identifier for the link
+    #[schema(value_type = Uuid)]
+    pub id: LinkId,
+    /// Name of the link
+    pub name: String,
+    /// Value of the link
+    pub value: String,
+    /// Whether the link is pinned
+    pub pinned: bool,
+    /// Parent folder ID
+    #[schema(value_type = Uuid)]
+    pub folder_id: FolderId,
+    /// When the link was created
+    pub created_at: DateTime<Utc>,
+    /// ID of the user who created the link
+    pub created_by: Option<String>,
+}
+
+impl From<Link> for LinkItem {
+    fn from(value: Link) -> Self {
+        LinkItem {
+            id: value.id,
+            name: value.name,
+            value: value.value,
+            pinned: value.pinned,
+            folder_id: value.folder_id,
+            created_at: value.created_at,
+            created_by: value.created_by,
+        }
+    }
+}
+
 /// Request to rename a file
 #[derive(Debug, Validate, Deserialize, ToSchema)]
 pub struct UpdateLinkRequest {
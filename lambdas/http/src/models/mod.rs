@@ -4,4 +4,5 @@ pub mod file;
 pub mod folder;
 pub mod link;
 pub mod task;
+pub mod trash;
 pub mod utils;
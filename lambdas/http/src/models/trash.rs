@@ -0,0 +1,90 @@
+use crate::{
+    error::HttpError,
+    models::{file::FileItem, folder::FolderItem, link::LinkItem},
+};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use docbox_serverless_shared::database::trash::Trashed;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use utoipa::{IntoParams, ToSchema};
+
+/// Query for listing the trash, the page applies to each type of item
+#[derive(Default, Debug, Deserialize, IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct TrashQuery {
+    /// Number of each type of item to include in the response (Maximum 100)
+    #[param(maximum = 100)]
+    pub size: Option<u16>,
+
+    /// Offset of the items to start results from
+    pub offset: Option<u64>,
+}
+
+/// Items within the trash of a document box, most recently deleted first
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrashResponse {
+    /// Folders in the trash, the contents of these folders are
+    /// restored along with the folder
+    pub folders: Vec<TrashedItem<FolderItem>>,
+    /// Files in the trash
+    pub files: Vec<TrashedItem<FileItem>>,
+    /// Links in the trash
+    pub links: Vec<TrashedItem<LinkItem>>,
+    /// Total number of folders in the trash
+    pub total_folders: i64,
+    /// Total number of files in the trash
+    pub total_files: i64,
+    /// Total number of links in the trash
+    pub total_links: i64,
+}
+
+/// Item within the trash
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrashedItem<T> {
+    #[serde(flatten)]
+    pub item: T,
+    /// When the item was moved to the trash
+    pub deleted_at: DateTime<Utc>,
+    /// ID of the user that moved the item to the trash
+    pub deleted_by: Option<String>,
+}
+
+impl<T, I: From<T>> From<Trashed<T>> for TrashedItem<I> {
+    fn from(value: Trashed<T>) -> Self {
+        TrashedItem {
+            item: value.item.into(),
+            deleted_at: value.deleted_at,
+            deleted_by: value.deleted_by,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum HttpTrashError {
+    #[error("unknown file")]
+    UnknownFile,
+
+    #[error("unknown folder")]
+    UnknownFolder,
+
+    #[error("unknown link")]
+    UnknownLink,
+
+    /// Items cannot be restored into a folder that no longer exists or is
+    /// also in the trash, the parent folder must be restored first
+    #[error("parent folder does not exist or is in the trash")]
+    ParentFolderUnavailable,
+}
+
+impl HttpError for HttpTrashError {
+    fn status(&self) -> axum::http::StatusCode {
+        match self {
+            HttpTrashError::UnknownFile
+            | HttpTrashError::UnknownFolder
+            | HttpTrashError::UnknownLink => StatusCode::NOT_FOUND,
+            HttpTrashError::ParentFolderUnavailable => StatusCode::CONFLICT,
+        }
+    }
+}
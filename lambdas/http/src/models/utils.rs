@@ -5,3 +5,15 @@ use utoipa::ToSchema;
 pub struct DocboxServerResponse {
     pub version: &'static str,
 }
+
+/// Number of items in a page of results when the size is not specified
+const DEFAULT_PAGE_SIZE: u16 = 100;
+
+/// Maximum number of items allowed in a page of results
+pub const MAX_PAGE_SIZE: u16 = 100;
+
+/// Number of items to query for a page of the requested `size`, sizes
+/// larger than [MAX_PAGE_SIZE] are clamped to the maximum
+pub fn page_size(size: Option<u16>) -> u64 {
+    u64::from(size.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE))
+}
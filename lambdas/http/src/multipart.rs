@@ -0,0 +1,84 @@
+//! Helpers for multipart presigned uploads, large files are uploaded as
+//! multiple parts which can be retried and resumed individually
+
+use crate::{
+    error::{DynHttpError, HttpCommonError},
+    models::file::{HttpFileError, PresignedUploadPart},
+};
+use chrono::{DateTime, Utc};
+use docbox_serverless_shared::s3::TenantS3Storage;
+use std::ops::RangeInclusive;
+
+/// Default size of each part (8MB), storage requires all parts except
+/// the last part to be at least 5MB
+const DEFAULT_PART_SIZE: i64 = 8 * 1024 * 1024;
+
+/// Maximum number of parts allowed within a single upload
+const MAX_PARTS: i64 = 10_000;
+
+/// Layout of the parts for a multipart upload
+pub struct MultipartPlan {
+    /// Size of each part in bytes, the last part may be smaller
+    pub part_size: i64,
+    /// Total number of parts
+    pub part_count: i32,
+}
+
+impl MultipartPlan {
+    /// Plan the parts for a file of `size` bytes, the plan only depends on
+    /// the size so the same plan is used when resuming an upload
+    pub fn for_size(size: i64) -> Self {
+        // Sizes are always positive so the division is rounded up manually
+        // (div_ceil is unstable for signed integers)
+        let part_size = DEFAULT_PART_SIZE.max((size + MAX_PARTS - 1) / MAX_PARTS);
+        let part_count = ((size + part_size - 1) / part_size).max(1);
+
+        Self {
+            part_size,
+            part_count: part_count as i32,
+        }
+    }
+
+    /// Numbers of all the parts (Part numbers start at 1)
+    pub fn part_numbers(&self) -> RangeInclusive<i32> {
+        1..=self.part_count
+    }
+}
+
+/// Create presigned requests for uploading each of the `part_numbers`, the
+/// requests expire along with the upload task at `expires_at`
+pub async fn presign_upload_parts(
+    storage: &TenantS3Storage,
+    file_key: &str,
+    upload_id: &str,
+    part_numbers: impl IntoIterator<Item = i32>,
+    expires_at: DateTime<Utc>,
+) -> Result<Vec<PresignedUploadPart>, DynHttpError> {
+    let expires_in = (expires_at - Utc::now())
+        .to_std()
+        .map_err(|_| HttpFileError::PresignedUploadExpired)?;
+
+    let mut parts = Vec::new();
+
+    for part_number in part_numbers {
+        let signed_request = storage
+            .create_presigned_upload_part(file_key, upload_id, part_number, expires_in)
+            .await
+            .map_err(|cause| {
+                tracing::error!(?cause, "failed to create presigned upload part");
+                HttpCommonError::ServerError
+            })?;
+
+        parts.push(PresignedUploadPart {
+            part_number,
+            method: signed_request.method().to_string(),
+            uri: signed_request.uri().to_string(),
+            headers: signed_request
+                .headers()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        });
+    }
+
+    Ok(parts)
+}
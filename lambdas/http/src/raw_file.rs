@@ -0,0 +1,446 @@
+//! Helpers for serving the raw contents of files with support for
+//! range requests and conditional requests
+
+use crate::error::{DynHttpError, HttpCommonError};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode, header, response::Builder},
+};
+use chrono::{DateTime, Utc};
+use docbox_serverless_shared::{
+    checksum::{UploadChecksum, repr_digest},
+    s3::TenantS3Storage,
+};
+use docbox_storage::TenantStorageLayer;
+
+/// Header containing the digest of the file contents (RFC 9530)
+const REPR_DIGEST: HeaderName = HeaderName::from_static("repr-digest");
+
+/// Format for HTTP dates (IMF-fixdate)
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Metadata about a raw file used to answer range and conditional requests
+pub struct RawFileMetadata<'a> {
+    /// Storage key of the file
+    pub file_key: &'a str,
+    /// Size of the file in bytes when known, range requests are only
+    /// supported when the size is known
+    pub size: Option<u64>,
+    /// Hash of the file contents used as the entity tag
+    pub hash: &'a str,
+    /// Date the file contents were last modified
+    pub last_modified: DateTime<Utc>,
+    /// Checksum provided when the file was uploaded, sent as the digest
+    /// of the file contents
+    pub checksum: Option<&'a UploadChecksum>,
+}
+
+/// Byte range within a file (Inclusive)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ByteRange {
+    start: u64,
+    end: u64,
+}
+
+impl ByteRange {
+    fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// Outcome of evaluating the request headers
+#[derive(Debug, PartialEq, Eq)]
+enum RawFileRequest {
+    /// Client already has the current file
+    NotModified,
+    /// Whole file should be sent
+    Full,
+    /// Part of the file should be sent
+    Partial(ByteRange),
+    /// Requested range is outside of the file
+    Unsatisfiable,
+}
+
+/// Creates the response for the raw contents of a file, `builder` should contain the
+/// file specific headers (content type, disposition, etc) which are included on all
+/// responses
+pub async fn raw_file_response(
+    request_headers: &HeaderMap,
+    storage: &TenantStorageLayer,
+    s3: &TenantS3Storage,
+    metadata: RawFileMetadata<'_>,
+    builder: Builder,
+) -> Result<Response<Body>, DynHttpError> {
+    let etag = format!("\"{}\"", metadata.hash);
+    let last_modified = metadata.last_modified.format(HTTP_DATE_FORMAT).to_string();
+
+    let mut builder = builder
+        .header(header::ETAG, HeaderValue::from_str(&etag)?)
+        .header(
+            header::LAST_MODIFIED,
+            HeaderValue::from_str(&last_modified)?,
+        )
+        .header(
+            header::ACCEPT_RANGES,
+            if metadata.size.is_some() {
+                "bytes"
+            } else {
+                "none"
+            },
+        );
+
+    if let Some(checksum) = metadata.checksum {
+        builder = builder.header(REPR_DIGEST, HeaderValue::from_str(&repr_digest(checksum))?);
+    }
+
+    match evaluate_request(request_headers, &metadata, &etag) {
+        RawFileRequest::NotModified => Ok(builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())?),
+
+        RawFileRequest::Unsatisfiable => {
+            if let Some(size) = metadata.size {
+                builder = builder.header(header::CONTENT_RANGE, format!("bytes */{size}"));
+            }
+
+            Ok(builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .body(Body::empty())?)
+        }
+
+        RawFileRequest::Full => {
+            let byte_stream = storage.get_file(metadata.file_key).await.map_err(|cause| {
+                tracing::error!(?cause, "failed to get file from storage");
+                HttpCommonError::ServerError
+            })?;
+
+            if let Some(size) = metadata.size {
+                builder = builder.header(header::CONTENT_LENGTH, size);
+            }
+
+            Ok(builder.body(Body::from_stream(byte_stream))?)
+        }
+
+        RawFileRequest::Partial(range) => {
+            // Only the requested range is read from storage
+            let byte_stream = s3
+                .get_file_range(metadata.file_key, range.start, range.end)
+                .await
+                .map_err(|cause| {
+                    tracing::error!(?cause, "failed to get file range from storage");
+                    HttpCommonError::ServerError
+                })?;
+
+            let size = metadata.size.unwrap_or_default();
+
+            Ok(builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(header::CONTENT_LENGTH, range.length())
+                .header(
+                    header::CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", range.start, range.end, size),
+                )
+                .body(Body::from_stream(byte_stream))?)
+        }
+    }
+}
+
+/// Evaluates the conditional and range headers of the request
+fn evaluate_request(
+    headers: &HeaderMap,
+    metadata: &RawFileMetadata<'_>,
+    etag: &str,
+) -> RawFileRequest {
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        if etag_matches(if_none_match, etag) {
+            return RawFileRequest::NotModified;
+        }
+    } else if let Some(if_modified_since) = header_str(headers, header::IF_MODIFIED_SINCE)
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        && metadata.last_modified.timestamp() <= if_modified_since.timestamp()
+    {
+        return RawFileRequest::NotModified;
+    }
+
+    let (Some(size), Some(range)) = (metadata.size, header_str(headers, header::RANGE)) else {
+        return RawFileRequest::Full;
+    };
+
+    // Ranges only apply if the client has the current version of the file
+    if let Some(if_range) = header_str(headers, header::IF_RANGE)
+        && if_range != etag
+    {
+        return RawFileRequest::Full;
+    }
+
+    match parse_range(range, size) {
+        Some(Some(range)) => RawFileRequest::Partial(range),
+        Some(None) => RawFileRequest::Unsatisfiable,
+        // Invalid or multiple ranges are ignored and the whole file is sent
+        None => RawFileRequest::Full,
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+/// Checks if the If-None-Match `value` matches the `etag`
+fn etag_matches(value: &str, etag: &str) -> bool {
+    value.split(',').map(str::trim).any(|candidate| {
+        // Weak comparison is used for If-None-Match
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+/// Parses a single range from the Range header `value` for a file of `size` bytes
+///
+/// Provides back [None] when the range is invalid or specifies multiple ranges,
+/// and `Some(None)` when the range is not satisfiable
+fn parse_range(value: &str, size: u64) -> Option<Option<ByteRange>> {
+    let range = value.trim().strip_prefix("bytes=")?;
+
+    // Multiple ranges are not supported
+    if range.contains(',') {
+        return None;
+    }
+
+    let (start, end) = range.trim().split_once('-')?;
+
+    let range = match (start.is_empty(), end.is_empty()) {
+        // Suffix range of the last N bytes
+        (true, false) => {
+            let length: u64 = end.parse().ok()?;
+            if length == 0 || size == 0 {
+                return Some(None);
+            }
+
+            ByteRange {
+                start: size.saturating_sub(length),
+                end: size - 1,
+            }
+        }
+
+        // Range from a start position to the end of the file
+        (false, true) => {
+            let start: u64 = start.parse().ok()?;
+            if start >= size {
+                return Some(None);
+            }
+
+            ByteRange {
+                start,
+                end: size - 1,
+            }
+        }
+
+        (false, false) => {
+            let start: u64 = start.parse().ok()?;
+            let end: u64 = end.parse().ok()?;
+            if end < start {
+                return None;
+            }
+
+            if start >= size {
+                return Some(None);
+            }
+
+            ByteRange {
+                start,
+                end: end.min(size - 1),
+            }
+        }
+
+        (true, true) => return None,
+    };
+
+    Some(Some(range))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ByteRange, RawFileMetadata, RawFileRequest, etag_matches, evaluate_request, parse_range,
+    };
+    use axum::http::{HeaderMap, HeaderValue, header};
+    use chrono::{TimeZone, Utc};
+
+    const ETAG: &str = "\"abc123\"";
+
+    fn range(start: u64, end: u64) -> Option<Option<ByteRange>> {
+        Some(Some(ByteRange { start, end }))
+    }
+
+    fn metadata(size: Option<u64>) -> RawFileMetadata<'static> {
+        RawFileMetadata {
+            file_key: "file",
+            size,
+            hash: "abc123",
+            last_modified: Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap(),
+            checksum: None,
+        }
+    }
+
+    fn request_headers(values: &[(header::HeaderName, &'static str)]) -> HeaderMap {
+        values
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn parse_bounded_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), range(0, 99));
+        assert_eq!(parse_range("bytes=100-199", 1000), range(100, 199));
+        assert_eq!(parse_range(" bytes=5-5 ", 1000), range(5, 5));
+
+        // End is clamped to the end of the file
+        assert_eq!(parse_range("bytes=900-2000", 1000), range(900, 999));
+    }
+
+    #[test]
+    fn parse_open_ended_range() {
+        assert_eq!(parse_range("bytes=0-", 1000), range(0, 999));
+        assert_eq!(parse_range("bytes=999-", 1000), range(999, 999));
+    }
+
+    #[test]
+    fn parse_suffix_range() {
+        assert_eq!(parse_range("bytes=-100", 1000), range(900, 999));
+        assert_eq!(parse_range("bytes=-1", 1000), range(999, 999));
+
+        // Suffix longer than the file is the whole file
+        assert_eq!(parse_range("bytes=-5000", 1000), range(0, 999));
+    }
+
+    #[test]
+    fn parse_unsatisfiable_range() {
+        assert_eq!(parse_range("bytes=1000-", 1000), Some(None));
+        assert_eq!(parse_range("bytes=1000-1100", 1000), Some(None));
+        assert_eq!(parse_range("bytes=-0", 1000), Some(None));
+        assert_eq!(parse_range("bytes=-10", 0), Some(None));
+        assert_eq!(parse_range("bytes=0-", 0), Some(None));
+    }
+
+    #[test]
+    fn parse_invalid_range() {
+        for value in [
+            "",
+            "bytes=",
+            "bytes=-",
+            "bytes=abc-",
+            "bytes=10-abc",
+            "bytes=10-5",
+            "bytes=10",
+            "items=0-10",
+            "bytes=--10",
+        ] {
+            assert_eq!(parse_range(value, 1000), None, "{value} should be invalid");
+        }
+    }
+
+    #[test]
+    fn parse_multiple_ranges() {
+        assert_eq!(parse_range("bytes=0-10,20-30", 1000), None);
+        assert_eq!(parse_range("bytes=0-10, -10", 1000), None);
+    }
+
+    #[test]
+    fn etag_matching() {
+        assert!(etag_matches(ETAG, ETAG));
+        assert!(etag_matches("*", ETAG));
+        assert!(etag_matches("W/\"abc123\"", ETAG));
+        assert!(etag_matches("\"other\", \"abc123\"", ETAG));
+        assert!(etag_matches("\"other\",W/\"abc123\"", ETAG));
+
+        assert!(!etag_matches("\"other\"", ETAG));
+        assert!(!etag_matches("abc123", ETAG));
+        assert!(!etag_matches("", ETAG));
+    }
+
+    #[test]
+    fn evaluate_without_headers() {
+        let request = evaluate_request(&HeaderMap::new(), &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::Full);
+    }
+
+    #[test]
+    fn evaluate_if_none_match() {
+        let headers = request_headers(&[(header::IF_NONE_MATCH, "\"abc123\"")]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::NotModified);
+
+        let headers = request_headers(&[(header::IF_NONE_MATCH, "\"other\"")]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::Full);
+    }
+
+    #[test]
+    fn evaluate_if_modified_since() {
+        let headers =
+            request_headers(&[(header::IF_MODIFIED_SINCE, "Wed, 01 Jan 2025 12:00:00 GMT")]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::NotModified);
+
+        let headers =
+            request_headers(&[(header::IF_MODIFIED_SINCE, "Tue, 31 Dec 2024 12:00:00 GMT")]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::Full);
+
+        // If-None-Match takes precedence
+        let headers = request_headers(&[
+            (header::IF_NONE_MATCH, "\"other\""),
+            (header::IF_MODIFIED_SINCE, "Wed, 01 Jan 2025 12:00:00 GMT"),
+        ]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::Full);
+    }
+
+    #[test]
+    fn evaluate_range() {
+        let headers = request_headers(&[(header::RANGE, "bytes=0-99")]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(
+            request,
+            RawFileRequest::Partial(ByteRange { start: 0, end: 99 })
+        );
+
+        let headers = request_headers(&[(header::RANGE, "bytes=1000-")]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::Unsatisfiable);
+
+        // Multiple ranges send the whole file
+        let headers = request_headers(&[(header::RANGE, "bytes=0-10,20-30")]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::Full);
+    }
+
+    #[test]
+    fn evaluate_range_unknown_size() {
+        let headers = request_headers(&[(header::RANGE, "bytes=0-99")]);
+        let request = evaluate_request(&headers, &metadata(None), ETAG);
+        assert_eq!(request, RawFileRequest::Full);
+    }
+
+    #[test]
+    fn evaluate_if_range() {
+        let headers = request_headers(&[
+            (header::RANGE, "bytes=0-99"),
+            (header::IF_RANGE, "\"abc123\""),
+        ]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(
+            request,
+            RawFileRequest::Partial(ByteRange { start: 0, end: 99 })
+        );
+
+        // Outdated copy receives the whole file
+        let headers = request_headers(&[
+            (header::RANGE, "bytes=0-99"),
+            (header::IF_RANGE, "\"other\""),
+        ]);
+        let request = evaluate_request(&headers, &metadata(Some(1000)), ETAG);
+        assert_eq!(request, RawFileRequest::Full);
+    }
+}
@@ -1,11 +1,24 @@
 //! Admin related access and routes for managing tenants and document boxes
 
 use crate::{
-    error::{HttpCommonError, HttpErrorResponse, HttpResult, HttpStatusResult},
-    middleware::tenant::{TenantDb, TenantParams, TenantSearch},
-    models::admin::{TenantDocumentBoxesRequest, TenantDocumentBoxesResponse, TenantStatsResponse},
+    error::{DynHttpError, HttpCommonError, HttpErrorResponse, HttpResult, HttpStatusResult},
+    middleware::tenant::{
+        OptionalTenantParams, TenantDb, TenantParams, TenantSearch, extract_optional_tenant,
+    },
+    models::{
+        admin::{
+            CreateTenantApiKeyRequest, CreateTenantApiKeyResponse, HttpTenantApiKeyError,
+            MigrateTenantsResponse, PurgeExpiredPresignedTasksResponse, TenantApiKeyResponse,
+            TenantDocumentBoxesRequest, TenantDocumentBoxesResponse, TenantStatsResponse,
+        },
+        utils::page_size,
+    },
+};
+use axum::{
+    Extension, Json,
+    extract::Path,
+    http::{HeaderMap, StatusCode},
 };
-use axum::{Extension, Json, http::StatusCode};
 use axum_valid::Garde;
 use docbox_core::{
     document_box::search_document_box::{ResolvedSearchResult, search_document_boxes_admin},
@@ -18,9 +31,24 @@ use docbox_database::{
         file::File,
         folder::Folder,
         link::Link,
+        tenant::Tenant,
     },
 };
 use docbox_search::models::{AdminSearchRequest, AdminSearchResultResponse, SearchResultItem};
+use docbox_serverless_shared::{
+    background_task::{
+        BackgroundTaskKind, BackgroundTaskQueue, BackgroundTaskTarget, PublishBackgroundTaskError,
+    },
+    database::tenant_api_key::{
+        CreateTenantApiKeyError, create_tenant_api_key, delete_tenant_api_key, tenant_api_keys,
+    },
+    migrations::{apply_root_migrations, apply_tenant_migrations, migrate_tenants},
+    presigned::{
+        PurgeExpiredPresignedOptions, PurgeTenantOptions, purge_expired_presigned_tasks,
+        purge_expired_presigned_tasks_tenant,
+    },
+    s3::S3StorageFactory,
+};
 use docbox_storage::StorageLayerFactory;
 use std::sync::Arc;
 use tokio::join;
@@ -48,7 +76,7 @@ pub async fn tenant_boxes(
     Garde(Json(req)): Garde<Json<TenantDocumentBoxesRequest>>,
 ) -> HttpResult<TenantDocumentBoxesResponse> {
     let offset = req.offset.unwrap_or(0);
-    let limit = req.size.unwrap_or(100);
+    let limit = page_size(req.size);
 
     let (document_boxes, total) = match req.query {
         Some(query) if !query.is_empty() => {
@@ -66,7 +94,7 @@ pub async fn tenant_boxes(
                 query.push('%');
             }
 
-            let document_boxes = DocumentBox::search_query(&db, &query, offset, limit as u64)
+            let document_boxes = DocumentBox::search_query(&db, &query, offset, limit)
                 .await
                 .map_err(|error| {
                     tracing::error!(?error, "failed to query document boxes");
@@ -83,7 +111,7 @@ pub async fn tenant_boxes(
             (document_boxes, total)
         }
         _ => {
-            let document_boxes = DocumentBox::query(&db, offset, limit as u64)
+            let document_boxes = DocumentBox::query(&db, offset, limit)
                 .await
                 .map_err(|error| {
                     tracing::error!(?error, "failed to query document boxes");
@@ -229,41 +257,98 @@ pub async fn search_tenant(
 /// file type (or ingested through a source that was unable to get the correct mime).
 ///
 /// Will reprocess files that have this unknown file type mime to see if a different
-/// type can be obtained
+/// type can be obtained.
+///
+/// The reprocessing is performed in the background, a task is created for each
+/// document box within the tenant by the background tasks lambda which can be
+/// polled to get the number of files reprocessed within that document box (and
+/// a sample of the files that failed)
 #[utoipa::path(
     post,
     operation_id = "admin_reprocess_octet_stream_files",
     tag = ADMIN_TAG,
     path = "/admin/reprocess-octet-stream-files",
     responses(
-        (status = 204, description = "Reprocessed successfully", body = AdminSearchResultResponse),
-        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+        (status = 202, description = "Reprocessing started successfully"),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse),
+        (status = 501, description = "Background tasks are not configured", body = HttpErrorResponse)
     ),
     params(TenantParams)
 )]
 #[tracing::instrument(skip_all)]
-pub async fn reprocess_octet_stream_files_tenant() -> HttpStatusResult {
-    Err(HttpCommonError::Unsupported.into())
+pub async fn reprocess_octet_stream_files_tenant(
+    Extension(tenant): Extension<Tenant>,
+    Extension(background_tasks): Extension<BackgroundTaskQueue>,
+) -> HttpStatusResult {
+    publish_tenant_task(
+        &tenant,
+        &background_tasks,
+        BackgroundTaskKind::ReprocessOctetStreamFiles {
+            progress: Default::default(),
+        },
+    )
+    .await?;
+
+    Ok(StatusCode::ACCEPTED)
 }
 
 /// Rebuild search index
 ///
 /// Rebuild the tenant search index from the data stored in the database
-/// and in storage
+/// and in storage.
+///
+/// The rebuild is performed in the background, a task is created for each
+/// document box within the tenant by the background tasks lambda which can be
+/// polled to track the progress of the rebuild for that document box
 #[utoipa::path(
     post,
     operation_id = "admin_rebuild_search_index",
     tag = ADMIN_TAG,
     path = "/admin/rebuild-search-index",
     responses(
-        (status = 204, description = "Rebuilt successfully", body = AdminSearchResultResponse),
-        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+        (status = 202, description = "Rebuild started successfully"),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse),
+        (status = 501, description = "Background tasks are not configured", body = HttpErrorResponse)
     ),
     params(TenantParams)
 )]
 #[tracing::instrument(skip_all)]
-pub async fn rebuild_search_index_tenant() -> HttpStatusResult {
-    Err(HttpCommonError::Unsupported.into())
+pub async fn rebuild_search_index_tenant(
+    Extension(tenant): Extension<Tenant>,
+    Extension(background_tasks): Extension<BackgroundTaskQueue>,
+) -> HttpStatusResult {
+    publish_tenant_task(
+        &tenant,
+        &background_tasks,
+        BackgroundTaskKind::RebuildSearchIndex {
+            progress: Default::default(),
+        },
+    )
+    .await?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Publishes a single background task of the provided `kind` for the tenant,
+/// the background tasks lambda creates a task for each document box within the
+/// tenant so that the request doesn't need to walk every document box
+async fn publish_tenant_task(
+    tenant: &Tenant,
+    background_tasks: &BackgroundTaskQueue,
+    kind: BackgroundTaskKind,
+) -> Result<(), DynHttpError> {
+    background_tasks
+        .publish(tenant, BackgroundTaskTarget::Tenant { offset: 0 }, kind)
+        .await
+        .map_err(|error| match error {
+            PublishBackgroundTaskError::NotConfigured => HttpCommonError::Unsupported,
+            error => {
+                tracing::error!(?error, "failed to publish background task");
+                HttpCommonError::ServerError
+            }
+        })?;
+
+    Ok(())
 }
 
 /// Flush database cache
@@ -310,25 +395,292 @@ pub async fn flush_tenant_cache(
 
 /// Purge Presigned Tasks
 ///
-/// Purges all expired presigned tasks
+/// Purges all expired presigned tasks and deletes any incomplete uploads
+/// from storage. When the tenant headers are provided only the presigned
+/// tasks for that tenant are purged
 #[utoipa::path(
     post,
     operation_id = "admin_purge_expired_presigned_tasks",
     tag = ADMIN_TAG,
     path = "/admin/purge-expired-presigned-tasks",
     responses(
-        (status = 204, description = "Database cache flushed"),
+        (status = 200, description = "Purged presigned tasks successfully", body = PurgeExpiredPresignedTasksResponse),
+        (status = 400, description = "Invalid or unknown tenant", body = HttpErrorResponse),
         (status = 500, description = "Failed to purge presigned cache", body = HttpErrorResponse),
-    )
+    ),
+    params(OptionalTenantParams)
 )]
+#[tracing::instrument(skip_all)]
 pub async fn http_purge_expired_presigned_tasks(
-    Extension(_db_cache): Extension<Arc<DatabasePoolCache>>,
-    Extension(_storage_factory): Extension<StorageLayerFactory>,
+    headers: HeaderMap,
+    Extension(db_cache): Extension<Arc<DatabasePoolCache>>,
+    Extension(tenant_cache): Extension<Arc<TenantCache>>,
+    Extension(storage_factory): Extension<StorageLayerFactory>,
+    Extension(s3_factory): Extension<S3StorageFactory>,
+) -> HttpResult<PurgeExpiredPresignedTasksResponse> {
+    let response = match extract_optional_tenant(&headers, &db_cache, &tenant_cache).await? {
+        // Purge only the requested tenant
+        Some(tenant) => {
+            let db = db_cache.get_tenant_pool(&tenant).await.map_err(|cause| {
+                tracing::error!(?cause, "failed to connect to tenant database");
+                HttpCommonError::ServerError
+            })?;
+
+            let storage = storage_factory.create_storage_layer(&tenant);
+            let s3 = s3_factory.create_s3_storage(&tenant);
+
+            let output = purge_expired_presigned_tasks_tenant(
+                &db,
+                &storage,
+                &s3,
+                PurgeTenantOptions::default(),
+            )
+            .await
+            .map_err(|cause| {
+                tracing::error!(?cause, "failed to purge presigned tasks for tenant");
+                HttpCommonError::ServerError
+            })?;
+
+            PurgeExpiredPresignedTasksResponse {
+                tenants_processed: 1,
+                tenants_failed: 0,
+                tasks_deleted: output.tasks_deleted,
+                objects_deleted: output.objects_deleted,
+                multipart_uploads_aborted: output.multipart_uploads_aborted,
+            }
+        }
+
+        // Purge all tenants
+        None => {
+            let summary = purge_expired_presigned_tasks(
+                &db_cache,
+                &storage_factory,
+                &s3_factory,
+                PurgeExpiredPresignedOptions::default(),
+            )
+            .await
+            .map_err(|cause| {
+                tracing::error!(?cause, "failed to purge presigned tasks");
+                HttpCommonError::ServerError
+            })?;
+
+            PurgeExpiredPresignedTasksResponse {
+                tenants_processed: summary.tenants_processed,
+                tenants_failed: summary.failures.len(),
+                tasks_deleted: summary.tasks_deleted,
+                objects_deleted: summary.objects_deleted,
+                multipart_uploads_aborted: summary.multipart_uploads_aborted,
+            }
+        }
+    };
+
+    Ok(Json(response))
+}
+
+/// Migrate tenants
+///
+/// Applies the migrations for the tables used by the serverless lambdas to the
+/// root database and the tenant databases, this must be run after deploying a
+/// version that adds new migrations. When the tenant headers are provided only
+/// that tenant is migrated
+#[utoipa::path(
+    post,
+    operation_id = "admin_migrate_tenants",
+    tag = ADMIN_TAG,
+    path = "/admin/migrate-tenants",
+    responses(
+        (status = 200, description = "Migrated tenants successfully", body = MigrateTenantsResponse),
+        (status = 400, description = "Invalid or unknown tenant", body = HttpErrorResponse),
+        (status = 500, description = "Failed to migrate tenants", body = HttpErrorResponse),
+    ),
+    params(OptionalTenantParams)
+)]
+#[tracing::instrument(skip_all)]
+pub async fn http_migrate_tenants(
+    headers: HeaderMap,
+    Extension(db_cache): Extension<Arc<DatabasePoolCache>>,
+    Extension(tenant_cache): Extension<Arc<TenantCache>>,
+) -> HttpResult<MigrateTenantsResponse> {
+    let root_db = db_cache.get_root_pool().await.map_err(|cause| {
+        tracing::error!(?cause, "failed to connect to root database");
+        HttpCommonError::ServerError
+    })?;
+
+    let root_applied = apply_root_migrations(&root_db).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to migrate root database");
+        HttpCommonError::ServerError
+    })?;
+
+    let mut response = match extract_optional_tenant(&headers, &db_cache, &tenant_cache).await? {
+        // Migrate only the requested tenant
+        Some(tenant) => {
+            let db = db_cache.get_tenant_pool(&tenant).await.map_err(|cause| {
+                tracing::error!(?cause, "failed to connect to tenant database");
+                HttpCommonError::ServerError
+            })?;
+
+            let applied = apply_tenant_migrations(&db).await.map_err(|cause| {
+                tracing::error!(?cause, "failed to migrate tenant");
+                HttpCommonError::ServerError
+            })?;
+
+            MigrateTenantsResponse {
+                tenants_processed: 1,
+                tenants_failed: 0,
+                migrations_applied: applied.len(),
+            }
+        }
+
+        // Migrate all tenants
+        None => {
+            let summary = migrate_tenants(&db_cache, &Default::default())
+                .await
+                .map_err(|cause| {
+                    tracing::error!(?cause, "failed to migrate tenants");
+                    HttpCommonError::ServerError
+                })?;
+
+            MigrateTenantsResponse {
+                tenants_processed: summary.tenants_processed,
+                tenants_failed: summary.failures.len(),
+                migrations_applied: summary.migrations_applied,
+            }
+        }
+    };
+
+    response.migrations_applied += root_applied.len();
+
+    Ok(Json(response))
+}
+
+/// List tenant API keys
+///
+/// Lists the API keys that can only act on the tenant, the keys themselves
+/// are never provided back after they are created
+#[utoipa::path(
+    get,
+    operation_id = "admin_tenant_api_keys",
+    tag = ADMIN_TAG,
+    path = "/admin/tenant-api-keys",
+    responses(
+        (status = 200, description = "Got tenant API keys successfully", body = [TenantApiKeyResponse]),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(TenantParams)
+)]
+#[tracing::instrument(skip_all)]
+pub async fn get_tenant_api_keys(
+    Extension(tenant): Extension<Tenant>,
+    Extension(db_cache): Extension<Arc<DatabasePoolCache>>,
+) -> HttpResult<Vec<TenantApiKeyResponse>> {
+    let db = db_cache.get_root_pool().await.map_err(|cause| {
+        tracing::error!(?cause, "failed to connect to root database");
+        HttpCommonError::ServerError
+    })?;
+
+    let api_keys = tenant_api_keys(&db, &tenant).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to query tenant api keys");
+        HttpCommonError::ServerError
+    })?;
+
+    Ok(Json(
+        api_keys
+            .into_iter()
+            .map(TenantApiKeyResponse::from)
+            .collect(),
+    ))
+}
+
+/// Create tenant API key
+///
+/// Creates an API key that can only act on the tenant, requests using the key
+/// that specify a different tenant are rejected. The key is only provided in
+/// this response and cannot be retrieved again
+#[utoipa::path(
+    post,
+    operation_id = "admin_create_tenant_api_key",
+    tag = ADMIN_TAG,
+    path = "/admin/tenant-api-keys",
+    responses(
+        (status = 201, description = "Created tenant API key successfully", body = CreateTenantApiKeyResponse),
+        (status = 400, description = "Malformed or invalid request not meeting validation requirements", body = HttpErrorResponse),
+        (status = 409, description = "API key with the same name already exists", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(TenantParams)
+)]
+#[tracing::instrument(skip_all, fields(req = ?req))]
+pub async fn create_tenant_api_key_tenant(
+    Extension(tenant): Extension<Tenant>,
+    Extension(db_cache): Extension<Arc<DatabasePoolCache>>,
+    Garde(Json(req)): Garde<Json<CreateTenantApiKeyRequest>>,
+) -> Result<(StatusCode, Json<CreateTenantApiKeyResponse>), DynHttpError> {
+    let db = db_cache.get_root_pool().await.map_err(|cause| {
+        tracing::error!(?cause, "failed to connect to root database");
+        HttpCommonError::ServerError
+    })?;
+
+    let (api_key, key) = create_tenant_api_key(&db, &tenant, req.name)
+        .await
+        .map_err(|cause| match cause {
+            CreateTenantApiKeyError::DuplicateName => {
+                DynHttpError::from(HttpTenantApiKeyError::DuplicateName)
+            }
+            cause => {
+                tracing::error!(?cause, "failed to create tenant api key");
+                DynHttpError::from(HttpCommonError::ServerError)
+            }
+        })?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateTenantApiKeyResponse {
+            api_key: api_key.into(),
+            key,
+        }),
+    ))
+}
+
+/// Delete tenant API key
+///
+/// Deletes the API key with the provided name from the tenant, requests
+/// using the key are rejected immediately
+#[utoipa::path(
+    delete,
+    operation_id = "admin_delete_tenant_api_key",
+    tag = ADMIN_TAG,
+    path = "/admin/tenant-api-keys/{name}",
+    responses(
+        (status = 204, description = "Deleted tenant API key successfully"),
+        (status = 404, description = "API key not found", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("name" = String, Path, description = "Name of the API key to delete"),
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(%name))]
+pub async fn delete_tenant_api_key_tenant(
+    Extension(tenant): Extension<Tenant>,
+    Extension(db_cache): Extension<Arc<DatabasePoolCache>>,
+    Path(name): Path<String>,
 ) -> HttpStatusResult {
-    // TODO: Implement this
-    // purge_expired_presigned_tasks(db_cache, storage_factory)
-    //     .await
-    //     .map_err(|_| HttpCommonError::ServerError)?;
+    let db = db_cache.get_root_pool().await.map_err(|cause| {
+        tracing::error!(?cause, "failed to connect to root database");
+        HttpCommonError::ServerError
+    })?;
+
+    let deleted = delete_tenant_api_key(&db, &tenant, &name)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to delete tenant api key");
+            HttpCommonError::ServerError
+        })?;
+
+    if !deleted {
+        return Err(HttpTenantApiKeyError::UnknownApiKey.into());
+    }
 
     Ok(StatusCode::NO_CONTENT)
 }
@@ -4,6 +4,7 @@ use crate::{
     error::{DynHttpError, HttpCommonError, HttpErrorResponse, HttpResult, HttpStatusResult},
     middleware::{
         action_user::{ActionUser, UserParams},
+        jwt::{HttpAuthError, JwtClaims},
         tenant::{TenantDb, TenantEvents, TenantParams, TenantSearch, TenantStorage},
     },
     models::document_box::{
@@ -11,7 +12,7 @@ use crate::{
         HttpDocumentBoxError,
     },
 };
-use axum::{Json, extract::Path, http::StatusCode};
+use axum::{Extension, Json, extract::Path, http::StatusCode};
 use axum_valid::Garde;
 use docbox_core::document_box::{
     create_document_box::{CreateDocumentBox, CreateDocumentBoxError, create_document_box},
@@ -38,6 +39,7 @@ pub const DOCUMENT_BOX_TAG: &str = "Document Box";
     path = "/box",
     responses(
         (status = 201, description = "Document box created successfully", body = DocumentBoxResponse),
+        (status = 403, description = "Token does not have access to the scope", body = HttpErrorResponse),
         (status = 409, description = "Scope already exists", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
@@ -49,10 +51,16 @@ pub const DOCUMENT_BOX_TAG: &str = "Document Box";
 #[tracing::instrument(skip_all, fields(req = ?req))]
 pub async fn create(
     action_user: ActionUser,
+    claims: Option<Extension<JwtClaims>>,
     TenantDb(db): TenantDb,
     TenantEvents(events): TenantEvents,
     Garde(Json(req)): Garde<Json<CreateDocumentBoxRequest>>,
 ) -> Result<(StatusCode, Json<DocumentBoxResponse>), DynHttpError> {
+    // Tokens can only create document boxes within their allowed scopes
+    if claims.is_some_and(|Extension(claims)| !claims.can_access_scope(&req.scope)) {
+        return Err(HttpAuthError::Forbidden.into());
+    }
+
     // Update stored editing user data
     let created_by = action_user.store_user(&db).await?;
 
@@ -1,60 +1,107 @@
 //! File related endpoints
 
 use crate::{
+    content_disposition::{DispositionType, content_disposition, content_disposition_value},
     error::{DynHttpError, HttpCommonError, HttpErrorResponse, HttpResult, HttpStatusResult},
-    extensions::max_file_size::MaxFileSizeBytes,
+    extensions::{
+        max_file_size::MaxFileSizeBytes, presigned_download_expiry::PresignedDownloadExpiry,
+    },
     middleware::{
         action_user::{ActionUser, UserParams},
-        tenant::{TenantDb, TenantEvents, TenantParams, TenantSearch, TenantStorage},
+        tenant::{TenantDb, TenantEvents, TenantParams, TenantS3, TenantSearch, TenantStorage},
     },
     models::{
         document_box::DocumentBoxScope,
         file::{
-            CreatePresignedRequest, FileResponse, GetPresignedRequest, HttpFileError,
-            PresignedDownloadResponse, PresignedStatusResponse, PresignedUploadResponse,
-            RawFileQuery, UpdateFileRequest,
+            CreatePresignedRequest, DuplicateFileGroup, DuplicateFilesResponse, DuplicatePolicy,
+            FileChecksumResponse, FileResponse, GetPresignedRequest, HttpFileError,
+            ListDuplicatesQuery, ListPresignedQuery, ListPresignedResponse,
+            PresignedDownloadResponse, PresignedMultipartPartsResponse,
+            PresignedMultipartUploadResponse, PresignedStatusResponse, PresignedUploadResponse,
+            PresignedUploadTaskResponse, PresignedUploadTaskStatus, RawFileQuery,
+            UpdateFileRequest, UploadFileRequest, UploadedPartResponse,
         },
         folder::HttpFolderError,
+        utils::page_size,
     },
+    multipart::{MultipartPlan, presign_upload_parts},
+    raw_file::{RawFileMetadata, raw_file_response},
 };
 use axum::{
     Extension, Json,
     body::Body,
     extract::{Path, Query},
-    http::{HeaderValue, Response, StatusCode, header},
+    http::{HeaderMap, Response, StatusCode, header},
+    response::IntoResponse,
 };
+use axum_typed_multipart::TypedMultipart;
 use axum_valid::Garde;
+use chrono::Utc;
 use docbox_core::{
+    events::TenantEventMessage,
     files::{
-        delete_file::delete_file,
         update_file::{UpdateFile, UpdateFileError},
+        upload_file::{UploadFile, upload_file},
         upload_file_presigned::{CreatePresigned, create_presigned_upload},
     },
     utils::file::get_file_name_ext,
 };
-use docbox_database::models::{
-    edit_history::EditHistory,
-    file::{File, FileId, FileWithExtra},
-    folder::Folder,
-    generated_file::{GeneratedFile, GeneratedFileType},
-    presigned_upload_task::{PresignedTaskStatus, PresignedUploadTask, PresignedUploadTaskId},
+use docbox_database::{
+    DbPool,
+    models::{
+        document_box::{DocumentBoxScopeRaw, WithScope},
+        edit_history::EditHistory,
+        file::{File, FileId, FileWithExtra},
+        folder::{Folder, FolderId},
+        generated_file::{GeneratedFile, GeneratedFileType},
+        presigned_upload_task::{PresignedTaskStatus, PresignedUploadTask, PresignedUploadTaskId},
+    },
 };
+use docbox_processing::{ProcessingConfig, ProcessingLayer};
 use docbox_search::models::{FileSearchRequest, FileSearchResultResponse};
-use std::{str::FromStr, time::Duration};
+use docbox_serverless_shared::{
+    checksum::{UploadChecksum, is_valid_checksum, sha256_hex},
+    database::{
+        file::{
+            count_duplicate_files, find_duplicate_files, find_file_by_hash, find_file_checksum,
+        },
+        presigned_upload_task::{
+            count_incomplete_presigned_tasks, find_incomplete_presigned_tasks,
+            find_presigned_task_multipart_upload_id, find_presigned_tasks_multipart_upload_ids,
+            set_presigned_task_checksum, set_presigned_task_multipart_upload_id,
+            set_presigned_task_status,
+        },
+        trash::trash_file,
+    },
+    s3::{PresignedDownloadOptions, TenantS3Storage},
+    task_lock::PresignedTaskLock,
+};
+use docbox_storage::TenantStorageLayer;
+use mime::Mime;
+use std::str::FromStr;
 
 pub const FILE_TAG: &str = "File";
 
 /// Upload file
 ///
-/// This endpoint is not available in docbox serverless, for serverless
-/// environments use presigned file uploads instead
+/// Uploads a file directly as a multipart form body, the file is processed
+/// and the created file is returned once complete.
+///
+/// Lambda request payloads are limited in size, so only small files can be
+/// uploaded directly. Files larger than the maximum direct upload size are
+/// rejected as unsupported and must use presigned file uploads instead
 #[utoipa::path(
     post,
     operation_id = "file_upload",
     tag = FILE_TAG,
     path = "/box/{scope}/file",
+    request_body(content = UploadFileRequest, content_type = "multipart/form-data"),
     responses(
-        (status = 501, description = "unsupported endpoint", body = HttpErrorResponse)
+        (status = 201, description = "Uploaded file successfully", body = FileResponse),
+        (status = 400, description = "Malformed or invalid request not meeting validation requirements", body = HttpErrorResponse),
+        (status = 404, description = "Target folder could not be found", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse),
+        (status = 501, description = "File is too large to upload directly, use a presigned upload", body = HttpErrorResponse)
     ),
     params(
         ("scope" = DocumentBoxScope, Path, description = "Scope to create the file within"),
@@ -62,8 +109,110 @@ pub const FILE_TAG: &str = "File";
         UserParams
     )
 )]
-pub async fn upload() -> HttpResult<()> {
-    Err(HttpCommonError::Unsupported.into())
+#[tracing::instrument(skip_all, fields(scope = %scope, name = %req.name))]
+#[allow(clippy::too_many_arguments)]
+pub async fn upload(
+    action_user: ActionUser,
+    TenantDb(db): TenantDb,
+    TenantSearch(search): TenantSearch,
+    TenantStorage(storage): TenantStorage,
+    TenantEvents(events): TenantEvents,
+    Extension(processing): Extension<ProcessingLayer>,
+    Path(DocumentBoxScope(scope)): Path<DocumentBoxScope>,
+    Garde(TypedMultipart(req)): Garde<TypedMultipart<UploadFileRequest>>,
+) -> Result<(StatusCode, Json<FileResponse>), DynHttpError> {
+    let folder_id: FolderId = req
+        .folder_id
+        .parse()
+        .map_err(|_| HttpFileError::InvalidFolderId)?;
+
+    let parent_id: Option<FileId> = req
+        .parent_id
+        .map(|parent_id| parent_id.parse())
+        .transpose()
+        .map_err(|_| HttpFileError::InvalidParentId)?;
+
+    let processing_config: Option<ProcessingConfig> = req
+        .processing_config
+        .map(|processing_config| serde_json::from_str(&processing_config))
+        .transpose()
+        .map_err(|_| HttpFileError::InvalidProcessingConfig)?;
+
+    // Use the explicit mime type, falling back to the mime of the file field
+    let mime = match req
+        .mime
+        .as_deref()
+        .or(req.file.metadata.content_type.as_deref())
+    {
+        Some(mime) => Mime::from_str(mime).map_err(|_| HttpFileError::InvalidMimeType)?,
+        None => mime::APPLICATION_OCTET_STREAM,
+    };
+
+    let mime = resolve_upload_mime(mime, &req.name, req.disable_mime_sniffing);
+
+    let folder = Folder::find_by_id(&db, &scope, folder_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query folder");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFolderError::UnknownTargetFolder)?;
+
+    // Update stored editing user data
+    let created_by = action_user.store_user(&db).await?;
+
+    let upload = UploadFile {
+        fixed_id: None,
+        parent_id,
+        folder_id: folder.id,
+        document_box: scope.clone(),
+        name: req.name,
+        mime,
+        file_bytes: req.file.contents,
+        created_by: created_by.map(|user| user.id),
+        file_key: None,
+        processing_config,
+    };
+
+    let uploaded = upload_file(&db, &search, &storage, &processing, &events, upload)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to upload file");
+            HttpCommonError::ServerError
+        })?;
+
+    let file = File::find_with_extra(&db, &scope, uploaded.file.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query file");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFileError::UnknownFile)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(FileResponse {
+            file,
+            generated: uploaded.generated,
+            checksum: None,
+        }),
+    ))
+}
+
+/// Attempts to guess the file mime type from the file name when
+/// application/octet-stream is specified (Likely from old browsers)
+/// unless mime sniffing has been disabled
+fn resolve_upload_mime(mime: Mime, name: &str, disable_mime_sniffing: Option<bool>) -> Mime {
+    if mime != mime::APPLICATION_OCTET_STREAM || disable_mime_sniffing.is_some_and(|value| value) {
+        return mime;
+    }
+
+    get_file_name_ext(name)
+        .and_then(|ext| {
+            let guesses = mime_guess::from_ext(&ext);
+            guesses.first()
+        })
+        .unwrap_or(mime)
 }
 
 /// Create presigned file upload
@@ -77,9 +226,11 @@ pub async fn upload() -> HttpResult<()> {
     tag = FILE_TAG,
     path = "/box/{scope}/file/presigned",
     responses(
+        (status = 200, description = "Existing file with identical contents when using the return_existing duplicate policy", body = FileResponse),
         (status = 201, description = "Created presigned upload successfully", body = PresignedUploadResponse),
         (status = 400, description = "Malformed or invalid request not meeting validation requirements", body = HttpErrorResponse),
         (status = 404, description = "Target folder could not be found", body = HttpErrorResponse),
+        (status = 409, description = "File is a duplicate of an existing file when using the reject duplicate policy", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
     params(
@@ -94,14 +245,102 @@ pub async fn create_presigned(
     Extension(MaxFileSizeBytes(max_file_size)): Extension<MaxFileSizeBytes>,
     TenantDb(db): TenantDb,
     TenantStorage(storage): TenantStorage,
+    TenantS3(s3): TenantS3,
     Path(DocumentBoxScope(scope)): Path<DocumentBoxScope>,
     Garde(Json(req)): Garde<Json<CreatePresignedRequest>>,
-) -> Result<(StatusCode, Json<PresignedUploadResponse>), DynHttpError> {
+) -> Result<Response<Body>, DynHttpError> {
+    if let Some(existing) = check_duplicate(&db, &scope, &req).await? {
+        return Ok((StatusCode::OK, Json(existing)).into_response());
+    }
+
+    let response =
+        create_presigned_task(action_user, max_file_size, &db, &storage, &s3, scope, req).await?;
+
+    Ok((StatusCode::CREATED, Json(response)).into_response())
+}
+
+/// Checks the request against its duplicate policy, provides back the existing
+/// file when the policy is to return the existing file
+async fn check_duplicate(
+    db: &DbPool,
+    scope: &DocumentBoxScopeRaw,
+    req: &CreatePresignedRequest,
+) -> Result<Option<FileResponse>, DynHttpError> {
+    if req.on_duplicate == DuplicatePolicy::Allow {
+        return Ok(None);
+    }
+
+    let hash = req
+        .checksum
+        .clone()
+        .map(UploadChecksum::from)
+        .and_then(|checksum| sha256_hex(&checksum))
+        .ok_or(HttpFileError::DuplicatePolicyRequiresChecksum)?;
+
+    let existing = find_file_by_hash(db, scope, &hash).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to query files by hash");
+        HttpCommonError::ServerError
+    })?;
+
+    let existing = match existing {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    if req.on_duplicate == DuplicatePolicy::Reject {
+        return Err(HttpFileError::DuplicateFile(existing.id).into());
+    }
+
+    let file = File::find_with_extra(db, scope, existing.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query file");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFileError::UnknownFile)?;
+
+    let generated = GeneratedFile::find_all(db, existing.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query generated files");
+            HttpCommonError::ServerError
+        })?;
+
+    let checksum = find_file_checksum(db, existing.id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to query file checksum");
+        HttpCommonError::ServerError
+    })?;
+
+    Ok(Some(FileResponse {
+        file,
+        generated,
+        checksum: checksum.map(FileChecksumResponse::from),
+    }))
+}
+
+/// Creates a presigned upload task for the `req`
+async fn create_presigned_task(
+    action_user: ActionUser,
+    max_file_size: i32,
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    s3: &TenantS3Storage,
+    scope: String,
+    req: CreatePresignedRequest,
+) -> Result<PresignedUploadResponse, DynHttpError> {
     if req.size > max_file_size {
         return Err(HttpFileError::FileTooLarge(req.size, max_file_size).into());
     }
 
-    let folder = Folder::find_by_id(&db, &scope, req.folder_id)
+    let checksum = req.checksum.map(UploadChecksum::from);
+    if checksum
+        .as_ref()
+        .is_some_and(|checksum| !is_valid_checksum(checksum))
+    {
+        return Err(HttpFileError::InvalidChecksum.into());
+    }
+
+    let folder = Folder::find_by_id(db, &scope, req.folder_id)
         .await
         .map_err(|cause| {
             tracing::error!(?cause, "failed to query folder");
@@ -110,31 +349,16 @@ pub async fn create_presigned(
         .ok_or(HttpFolderError::UnknownTargetFolder)?;
 
     // Update stored editing user data
-    let created_by = action_user.store_user(&db).await?;
+    let created_by = action_user.store_user(db).await?;
 
-    let mut mime = req.mime;
-
-    // Attempt to guess the file mime type when application/octet-stream is specified
-    // (Likely from old browsers)
-    if mime == mime::APPLICATION_OCTET_STREAM
-        && req.disable_mime_sniffing.is_none_or(|value| !value)
-    {
-        let guessed_mime = get_file_name_ext(&req.name).and_then(|ext| {
-            let guesses = mime_guess::from_ext(&ext);
-            guesses.first()
-        });
-
-        if let Some(guessed_mime) = guessed_mime {
-            mime = guessed_mime
-        }
-    }
+    let mime = resolve_upload_mime(req.mime, &req.name, req.disable_mime_sniffing);
 
     let response = create_presigned_upload(
-        &db,
-        &storage,
+        db,
+        storage,
         CreatePresigned {
             name: req.name,
-            document_box: scope,
+            document_box: scope.clone(),
             folder,
             size: req.size,
             mime,
@@ -149,15 +373,391 @@ pub async fn create_presigned(
         HttpCommonError::ServerError
     })?;
 
+    let checksum = match checksum {
+        Some(value) => value,
+        None => {
+            return Ok(PresignedUploadResponse {
+                task_id: response.task_id,
+                method: response.method,
+                uri: response.uri,
+                headers: response.headers,
+            });
+        }
+    };
+
+    // The storage layer cannot sign checksums into the upload so the upload
+    // is signed again directly against S3 with the checksum header required
+    let task = PresignedUploadTask::find(db, &scope, response.task_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query presigned upload");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFileError::UnknownTask)?;
+
+    set_presigned_task_checksum(db, task.id, &checksum)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to store presigned upload checksum");
+            HttpCommonError::ServerError
+        })?;
+
+    let expires_in = (task.expires_at - Utc::now())
+        .to_std()
+        .map_err(|_| HttpFileError::PresignedUploadExpired)?;
+
+    let signed_request = s3
+        .create_presigned_upload(
+            &task.file_key,
+            &task.mime,
+            i64::from(task.size),
+            &checksum,
+            expires_in,
+        )
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to presign checksum upload");
+            HttpCommonError::ServerError
+        })?;
+
+    Ok(PresignedUploadResponse {
+        task_id: task.id,
+        method: signed_request.method().to_string(),
+        uri: signed_request.uri().to_string(),
+        headers: signed_request
+            .headers()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    })
+}
+
+/// Create multipart presigned file upload
+///
+/// Creates a new "presigned" upload where the file is uploaded directly
+/// to storage as multiple parts, each part can be retried individually
+/// and [get_presigned_multipart_parts] can be used to resume the upload.
+/// [complete_presigned_multipart] is called by the client after all the
+/// parts have been uploaded
+#[utoipa::path(
+    post,
+    operation_id = "file_create_presigned_multipart",
+    tag = FILE_TAG,
+    path = "/box/{scope}/file/presigned/multipart",
+    responses(
+        (status = 200, description = "Existing file with identical contents when using the return_existing duplicate policy", body = FileResponse),
+        (status = 201, description = "Created multipart presigned upload successfully", body = PresignedMultipartUploadResponse),
+        (status = 400, description = "Malformed or invalid request not meeting validation requirements", body = HttpErrorResponse),
+        (status = 404, description = "Target folder could not be found", body = HttpErrorResponse),
+        (status = 409, description = "File is a duplicate of an existing file when using the reject duplicate policy", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope to create the file within"),
+        TenantParams,
+        UserParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, req = ?req))]
+pub async fn create_presigned_multipart(
+    action_user: ActionUser,
+    Extension(MaxFileSizeBytes(max_file_size)): Extension<MaxFileSizeBytes>,
+    TenantDb(db): TenantDb,
+    TenantStorage(storage): TenantStorage,
+    TenantS3(s3): TenantS3,
+    Path(DocumentBoxScope(scope)): Path<DocumentBoxScope>,
+    Garde(Json(req)): Garde<Json<CreatePresignedRequest>>,
+) -> Result<Response<Body>, DynHttpError> {
+    if let Some(existing) = check_duplicate(&db, &scope, &req).await? {
+        return Ok((StatusCode::OK, Json(existing)).into_response());
+    }
+
+    // The task is created through the regular presigned flow, the single
+    // upload request is replaced by the requests for each part
+    let response = create_presigned_task(
+        action_user,
+        max_file_size,
+        &db,
+        &storage,
+        &s3,
+        scope.clone(),
+        req,
+    )
+    .await?;
+
+    let task = PresignedUploadTask::find(&db, &scope, response.task_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query presigned upload");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFileError::UnknownTask)?;
+
+    let upload_id = s3
+        .create_multipart_upload(&task.file_key, &task.mime)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to create multipart upload");
+            HttpCommonError::ServerError
+        })?;
+
+    set_presigned_task_multipart_upload_id(&db, task.id, &upload_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to store multipart upload id");
+            HttpCommonError::ServerError
+        })?;
+
+    let plan = MultipartPlan::for_size(i64::from(task.size));
+    let parts = presign_upload_parts(
+        &s3,
+        &task.file_key,
+        &upload_id,
+        plan.part_numbers(),
+        task.expires_at,
+    )
+    .await?;
+
     Ok((
         StatusCode::CREATED,
-        Json(PresignedUploadResponse {
-            task_id: response.task_id,
-            method: response.method,
-            uri: response.uri,
-            headers: response.headers,
+        Json(PresignedMultipartUploadResponse {
+            task_id: task.id,
+            upload_id,
+            part_size: plan.part_size,
+            parts,
+            expires_at: task.expires_at,
         }),
-    ))
+    )
+        .into_response())
+}
+
+/// Get multipart presigned upload parts
+///
+/// Lists the parts of a pending multipart presigned upload that have
+/// already been uploaded along with new presigned requests for the
+/// parts that have not, used to resume an interrupted upload
+#[utoipa::path(
+    get,
+    operation_id = "file_get_presigned_multipart_parts",
+    tag = FILE_TAG,
+    path = "/box/{scope}/file/presigned/{task_id}/multipart/parts",
+    responses(
+        (status = 200, description = "Obtained multipart upload parts successfully", body = PresignedMultipartPartsResponse),
+        (status = 400, description = "Presigned upload is not a multipart upload", body = HttpErrorResponse),
+        (status = 404, description = "Presigned upload not found", body = HttpErrorResponse),
+        (status = 409, description = "Presigned upload is no longer pending", body = HttpErrorResponse),
+        (status = 410, description = "Presigned upload has expired", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope the file resides within"),
+        ("task_id" = Uuid, Path, description = "ID of the task to query"),
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, task_id = %task_id))]
+pub async fn get_presigned_multipart_parts(
+    TenantDb(db): TenantDb,
+    TenantS3(s3): TenantS3,
+    Path((scope, task_id)): Path<(DocumentBoxScope, PresignedUploadTaskId)>,
+) -> HttpResult<PresignedMultipartPartsResponse> {
+    let DocumentBoxScope(scope) = scope;
+
+    let (task, upload_id) = find_pending_multipart_task(&db, &scope, task_id).await?;
+    let plan = MultipartPlan::for_size(i64::from(task.size));
+
+    let uploaded = s3
+        .list_multipart_parts(&task.file_key, &upload_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to list multipart upload parts");
+            HttpCommonError::ServerError
+        })?;
+
+    let pending_parts: Vec<i32> = plan
+        .part_numbers()
+        .filter(|part_number| !uploaded.iter().any(|part| part.part_number == *part_number))
+        .collect();
+
+    let pending = presign_upload_parts(
+        &s3,
+        &task.file_key,
+        &upload_id,
+        pending_parts,
+        task.expires_at,
+    )
+    .await?;
+
+    Ok(Json(PresignedMultipartPartsResponse {
+        upload_id,
+        part_size: plan.part_size,
+        uploaded: uploaded
+            .into_iter()
+            .map(|part| UploadedPartResponse {
+                part_number: part.part_number,
+                etag: part.etag,
+                size: part.size,
+            })
+            .collect(),
+        pending,
+        expires_at: task.expires_at,
+    }))
+}
+
+/// Complete multipart presigned upload
+///
+/// Completes a multipart presigned upload once all of its parts have
+/// been uploaded, the file is then processed in the background in the
+/// same way as a regular presigned upload. Use [get_presigned] to check
+/// when the file is ready
+#[utoipa::path(
+    post,
+    operation_id = "file_complete_presigned_multipart",
+    tag = FILE_TAG,
+    path = "/box/{scope}/file/presigned/{task_id}/multipart/complete",
+    responses(
+        (status = 202, description = "Multipart upload completed and is being processed"),
+        (status = 400, description = "Presigned upload is not a multipart upload or is missing parts", body = HttpErrorResponse),
+        (status = 404, description = "Presigned upload not found", body = HttpErrorResponse),
+        (status = 409, description = "Presigned upload is no longer pending", body = HttpErrorResponse),
+        (status = 410, description = "Presigned upload has expired", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope the file resides within"),
+        ("task_id" = Uuid, Path, description = "ID of the task to complete"),
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, task_id = %task_id))]
+pub async fn complete_presigned_multipart(
+    TenantDb(db): TenantDb,
+    TenantS3(s3): TenantS3,
+    Path((scope, task_id)): Path<(DocumentBoxScope, PresignedUploadTaskId)>,
+) -> HttpStatusResult {
+    let DocumentBoxScope(scope) = scope;
+
+    let (task, upload_id) = find_pending_multipart_task(&db, &scope, task_id).await?;
+    if task.expires_at <= Utc::now() {
+        return Err(HttpFileError::PresignedUploadExpired.into());
+    }
+
+    let plan = MultipartPlan::for_size(i64::from(task.size));
+
+    let mut parts = s3
+        .list_multipart_parts(&task.file_key, &upload_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to list multipart upload parts");
+            HttpCommonError::ServerError
+        })?;
+
+    parts.retain(|part| plan.part_numbers().contains(&part.part_number));
+    parts.sort_by_key(|part| part.part_number);
+
+    // Parts outside the plan were removed above so there can never be more
+    // parts than planned, a count that does not fit is still rejected
+    let missing_parts = usize::try_from(plan.part_count)
+        .ok()
+        .and_then(|part_count| part_count.checked_sub(parts.len()))
+        .ok_or(HttpFileError::InvalidUploadParts)?;
+    if missing_parts > 0 {
+        return Err(HttpFileError::MissingUploadParts(missing_parts).into());
+    }
+
+    // Completing the upload creates the file in storage which notifies the
+    // upload completion lambda to finish the upload
+    s3.complete_multipart_upload(&task.file_key, &upload_id, parts)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to complete multipart upload");
+            HttpCommonError::ServerError
+        })?;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Abort multipart presigned upload
+///
+/// Aborts a pending multipart presigned upload, the parts that were
+/// uploaded are removed from storage and the upload is marked as failed
+#[utoipa::path(
+    post,
+    operation_id = "file_abort_presigned_multipart",
+    tag = FILE_TAG,
+    path = "/box/{scope}/file/presigned/{task_id}/multipart/abort",
+    responses(
+        (status = 204, description = "Aborted multipart upload successfully"),
+        (status = 400, description = "Presigned upload is not a multipart upload", body = HttpErrorResponse),
+        (status = 404, description = "Presigned upload not found", body = HttpErrorResponse),
+        (status = 409, description = "Presigned upload is no longer pending", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope the file resides within"),
+        ("task_id" = Uuid, Path, description = "ID of the task to abort"),
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, task_id = %task_id))]
+pub async fn abort_presigned_multipart(
+    TenantDb(db): TenantDb,
+    TenantS3(s3): TenantS3,
+    Path((scope, task_id)): Path<(DocumentBoxScope, PresignedUploadTaskId)>,
+) -> HttpStatusResult {
+    let DocumentBoxScope(scope) = scope;
+
+    let (task, upload_id) = find_pending_multipart_task(&db, &scope, task_id).await?;
+
+    s3.abort_multipart_upload(&task.file_key, &upload_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to abort multipart upload");
+            HttpCommonError::ServerError
+        })?;
+
+    set_presigned_task_status(
+        &db,
+        task.id,
+        PresignedTaskStatus::Failed {
+            error: "upload aborted".to_string(),
+        },
+    )
+    .await
+    .map_err(|cause| {
+        tracing::error!(?cause, "failed to mark presigned upload as failed");
+        HttpCommonError::ServerError
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Finds a pending multipart presigned upload task along with its multipart upload ID
+async fn find_pending_multipart_task(
+    db: &DbPool,
+    scope: &DocumentBoxScopeRaw,
+    task_id: PresignedUploadTaskId,
+) -> Result<(PresignedUploadTask, String), DynHttpError> {
+    let task = PresignedUploadTask::find(db, scope, task_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query presigned upload");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFileError::UnknownTask)?;
+
+    if !matches!(task.status, PresignedTaskStatus::Pending) {
+        return Err(HttpFileError::PresignedUploadNotPending.into());
+    }
+
+    let upload_id = find_presigned_task_multipart_upload_id(db, task.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query multipart upload");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFileError::NotMultipartUpload)?;
+
+    Ok((task, upload_id))
 }
 
 /// Get presigned file upload
@@ -222,6 +822,246 @@ pub async fn get_presigned(
     Ok(Json(PresignedStatusResponse::Complete { file, generated }))
 }
 
+/// List presigned file uploads
+///
+/// Lists the presigned uploads within the document box that are still
+/// pending or have failed
+#[utoipa::path(
+    get,
+    operation_id = "file_list_presigned",
+    tag = FILE_TAG,
+    path = "/box/{scope}/file/presigned",
+    responses(
+        (status = 200, description = "Listed presigned uploads successfully", body = ListPresignedResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope the uploads reside within"),
+        ListPresignedQuery,
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, query = ?query))]
+pub async fn list_presigned(
+    TenantDb(db): TenantDb,
+    Path(DocumentBoxScope(scope)): Path<DocumentBoxScope>,
+    Query(query): Query<ListPresignedQuery>,
+) -> HttpResult<ListPresignedResponse> {
+    let offset = query.offset.unwrap_or(0);
+    let limit = page_size(query.size);
+
+    let tasks = find_incomplete_presigned_tasks(&db, &scope, offset, limit)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query presigned uploads");
+            HttpCommonError::ServerError
+        })?;
+
+    let total = count_incomplete_presigned_tasks(&db, &scope)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query presigned uploads total");
+            HttpCommonError::ServerError
+        })?;
+
+    let task_ids: Vec<PresignedUploadTaskId> = tasks.iter().map(|task| task.id).collect();
+    let upload_ids = find_presigned_tasks_multipart_upload_ids(&db, &task_ids)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query multipart uploads");
+            HttpCommonError::ServerError
+        })?;
+
+    let results = tasks
+        .into_iter()
+        .map(|task| {
+            // Completed uploads are excluded by the query, every task is included
+            // so the page size is consistent with the total
+            let status = match task.status {
+                PresignedTaskStatus::Pending => PresignedUploadTaskStatus::Pending,
+                PresignedTaskStatus::Failed { error } => {
+                    PresignedUploadTaskStatus::Failed { error }
+                }
+                PresignedTaskStatus::Completed { file_id } => {
+                    PresignedUploadTaskStatus::Completed { file_id }
+                }
+            };
+
+            PresignedUploadTaskResponse {
+                task_id: task.id,
+                name: task.name,
+                mime: task.mime,
+                size: task.size,
+                folder_id: task.folder_id,
+                multipart: upload_ids.contains_key(&task.id),
+                status,
+                created_at: task.created_at,
+                expires_at: task.expires_at,
+            }
+        })
+        .collect();
+
+    Ok(Json(ListPresignedResponse { results, total }))
+}
+
+/// Cancel presigned file upload
+///
+/// Cancels a pending or failed presigned upload, any partially
+/// uploaded file is removed from storage and the upload is deleted
+#[utoipa::path(
+    delete,
+    operation_id = "file_cancel_presigned",
+    tag = FILE_TAG,
+    path = "/box/{scope}/file/presigned/{task_id}",
+    responses(
+        (status = 204, description = "Cancelled presigned upload successfully"),
+        (status = 404, description = "Presigned upload not found", body = HttpErrorResponse),
+        (status = 409, description = "Presigned upload has completed or is being completed", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope the file resides within"),
+        ("task_id" = Uuid, Path, description = "ID of the task to cancel"),
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, task_id = %task_id))]
+pub async fn cancel_presigned(
+    TenantDb(db): TenantDb,
+    TenantStorage(storage): TenantStorage,
+    TenantS3(s3): TenantS3,
+    Path((scope, task_id)): Path<(DocumentBoxScope, PresignedUploadTaskId)>,
+) -> HttpStatusResult {
+    let DocumentBoxScope(scope) = scope;
+
+    // Claim the task so the upload cannot be completed while it's being cancelled
+    let lock = PresignedTaskLock::try_acquire(&db, task_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to lock presigned upload");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFileError::PresignedUploadNotPending)?;
+
+    let result = cancel_locked_presigned(&db, &storage, &s3, &scope, task_id).await;
+
+    lock.release(&db).await;
+
+    result
+}
+
+/// Cancel the presigned upload while holding its claim
+async fn cancel_locked_presigned(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    s3: &TenantS3Storage,
+    scope: &DocumentBoxScopeRaw,
+    task_id: PresignedUploadTaskId,
+) -> HttpStatusResult {
+    let task = PresignedUploadTask::find(db, scope, task_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query presigned upload");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpFileError::UnknownTask)?;
+
+    if matches!(task.status, PresignedTaskStatus::Completed { .. }) {
+        return Err(HttpFileError::PresignedUploadNotPending.into());
+    }
+
+    let upload_id = find_presigned_task_multipart_upload_id(db, task.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query multipart upload");
+            HttpCommonError::ServerError
+        })?;
+
+    if let Some(upload_id) = &upload_id
+        && matches!(task.status, PresignedTaskStatus::Pending)
+    {
+        s3.abort_multipart_upload(&task.file_key, upload_id)
+            .await
+            .map_err(|cause| {
+                tracing::error!(?cause, "failed to abort multipart upload");
+                HttpCommonError::ServerError
+            })?;
+    }
+
+    // Remove any partially uploaded file
+    storage.delete_file(&task.file_key).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to delete presigned upload file");
+        HttpCommonError::ServerError
+    })?;
+
+    PresignedUploadTask::delete(db, task.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to delete presigned upload");
+            HttpCommonError::ServerError
+        })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get duplicate files
+///
+/// Lists groups of files within the document box that have
+/// identical contents
+#[utoipa::path(
+    get,
+    operation_id = "file_get_duplicates",
+    tag = FILE_TAG,
+    path = "/box/{scope}/file/duplicates",
+    responses(
+        (status = 200, description = "Obtained duplicate files successfully", body = DuplicateFilesResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope to find duplicates within"),
+        ListDuplicatesQuery,
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, query = ?query))]
+pub async fn get_duplicates(
+    TenantDb(db): TenantDb,
+    Path(DocumentBoxScope(scope)): Path<DocumentBoxScope>,
+    Query(query): Query<ListDuplicatesQuery>,
+) -> HttpResult<DuplicateFilesResponse> {
+    let offset = query.offset.unwrap_or(0);
+    let limit = page_size(query.size);
+
+    // Files sharing their hash with another file, ordered by hash then creation date,
+    // the offset and limit apply to the hashes so groups are never split across pages
+    let files = find_duplicate_files(&db, &scope, offset, limit)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query duplicate files");
+            HttpCommonError::ServerError
+        })?;
+
+    let total = count_duplicate_files(&db, &scope).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to query duplicate files total");
+        HttpCommonError::ServerError
+    })?;
+
+    let mut groups: Vec<DuplicateFileGroup> = Vec::new();
+
+    for file in files {
+        match groups.last_mut() {
+            Some(group) if group.hash == file.hash => group.files.push(file.into()),
+            _ => groups.push(DuplicateFileGroup {
+                hash: file.hash.clone(),
+                size: file.size,
+                files: vec![file.into()],
+            }),
+        }
+    }
+
+    Ok(Json(DuplicateFilesResponse { groups, total }))
+}
+
 /// Get file by ID
 ///
 /// Gets a specific file details, metadata and associated
@@ -263,7 +1103,16 @@ pub async fn get(
             HttpCommonError::ServerError
         })?;
 
-    Ok(Json(FileResponse { file, generated }))
+    let checksum = find_file_checksum(&db, file_id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to query file checksum");
+        HttpCommonError::ServerError
+    })?;
+
+    Ok(Json(FileResponse {
+        file,
+        generated,
+        checksum: checksum.map(FileChecksumResponse::from),
+    }))
 }
 
 /// Get file children
@@ -427,7 +1276,10 @@ pub async fn update(
     path = "/box/{scope}/file/{file_id}/raw",
     responses(
         (status = 200, description = "Obtained raw file successfully"),
+        (status = 206, description = "Obtained requested range of the raw file successfully"),
+        (status = 304, description = "File has not been modified"),
         (status = 404, description = "File not found", body = HttpErrorResponse),
+        (status = 416, description = "Requested range is not satisfiable"),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
     params(
@@ -440,8 +1292,10 @@ pub async fn update(
 pub async fn get_raw(
     TenantDb(db): TenantDb,
     TenantStorage(storage): TenantStorage,
+    TenantS3(s3): TenantS3,
     Path((scope, file_id)): Path<(DocumentBoxScope, FileId)>,
     Query(query): Query<RawFileQuery>,
+    headers: HeaderMap,
 ) -> Result<Response<Body>, DynHttpError> {
     let DocumentBoxScope(scope) = scope;
 
@@ -453,38 +1307,47 @@ pub async fn get_raw(
         })?
         .ok_or(HttpFileError::UnknownFile)?;
 
-    let byte_stream = storage.get_file(&file.file_key).await.map_err(|cause| {
-        tracing::error!(?cause, "failed to get file from storage");
+    let checksum = find_file_checksum(&db, file.id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to query file checksum");
         HttpCommonError::ServerError
     })?;
 
-    let body = axum::body::Body::from_stream(byte_stream);
+    let disposition =
+        content_disposition(DispositionType::from_download(query.download), &file.name)?;
 
-    let ty = if query.download {
-        "attachment"
-    } else {
-        "inline"
-    };
+    let csp = raw_content_security_policy(&file.mime);
 
-    let disposition = format!("{};filename=\"{}\"", ty, file.name);
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, &file.mime)
+        .header(header::CONTENT_SECURITY_POLICY, csp)
+        .header(header::CONTENT_DISPOSITION, disposition);
 
-    let csp = match mime::Mime::from_str(&file.mime) {
+    raw_file_response(
+        &headers,
+        &storage,
+        &s3,
+        RawFileMetadata {
+            file_key: &file.file_key,
+            size: u64::try_from(file.size).ok(),
+            hash: &file.hash,
+            last_modified: file.created_at,
+            checksum: checksum.as_ref(),
+        },
+        builder,
+    )
+    .await
+}
+
+/// Content security policy for serving raw file contents of the `mime` type
+fn raw_content_security_policy(mime: &str) -> &'static str {
+    match mime::Mime::from_str(mime) {
         // Images are served with a strict image only content security policy
         Ok(mime) if mime.type_() == mime::IMAGE => {
             "default-src 'none'; style-src 'self' 'unsafe-inline'; img-src 'self' data:;"
         }
         // Default policy
         _ => "script-src 'none'; object-src 'none'; base-uri 'none'; form-action 'none'",
-    };
-
-    Ok(Response::builder()
-        .header(header::CONTENT_TYPE, file.mime)
-        .header(header::CONTENT_SECURITY_POLICY, csp)
-        .header(
-            header::CONTENT_DISPOSITION,
-            HeaderValue::from_str(&disposition)?,
-        )
-        .body(body)?)
+    }
 }
 
 /// Get file raw presigned
@@ -499,6 +1362,7 @@ pub async fn get_raw(
     path = "/box/{scope}/file/{file_id}/raw-presigned",
     responses(
         (status = 200, description = "Obtained raw file successfully"),
+        (status = 400, description = "Invalid expiry time", body = HttpErrorResponse),
         (status = 404, description = "File not found", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
@@ -511,12 +1375,21 @@ pub async fn get_raw(
 #[tracing::instrument(skip_all, fields(scope = %scope, file_id = %file_id, req = ?req))]
 pub async fn get_raw_presigned(
     TenantDb(db): TenantDb,
-    TenantStorage(storage): TenantStorage,
+    TenantS3(s3): TenantS3,
+    Extension(expiry): Extension<PresignedDownloadExpiry>,
     Path((scope, file_id)): Path<(DocumentBoxScope, FileId)>,
-    Json(req): Json<GetPresignedRequest>,
+    Garde(Json(req)): Garde<Json<GetPresignedRequest>>,
 ) -> HttpResult<PresignedDownloadResponse> {
     let DocumentBoxScope(scope) = scope;
 
+    let expires_at =
+        expiry
+            .duration(req.expires_at)
+            .ok_or(HttpFileError::InvalidPresignedExpiry(
+                expiry.min_seconds,
+                expiry.max_seconds,
+            ))?;
+
     let file = File::find(&db, &scope, file_id)
         .await
         .map_err(|cause| {
@@ -525,11 +1398,17 @@ pub async fn get_raw_presigned(
         })?
         .ok_or(HttpFileError::UnknownFile)?;
 
-    let expires_at = req.expires_at.unwrap_or(900);
-    let expires_at = Duration::from_secs(expires_at as u64);
+    // Override the response headers so the file is served with its real name and type
+    let options = PresignedDownloadOptions {
+        content_type: Some(file.mime.clone()),
+        content_disposition: Some(content_disposition_value(
+            DispositionType::from_download(req.download),
+            &file.name,
+        )),
+    };
 
-    let (signed_request, expires_at) = storage
-        .create_presigned_download(&file.file_key, expires_at)
+    let (signed_request, expires_at) = s3
+        .create_presigned_download(&file.file_key, expires_at, options)
         .await
         .map_err(|cause| {
             tracing::error!(?cause, "failed to created file presigned download");
@@ -563,7 +1442,10 @@ pub async fn get_raw_presigned(
     path = "/box/{scope}/file/{file_id}/raw/{*file_name}",
     responses(
         (status = 200, description = "Obtained raw file successfully"),
+        (status = 206, description = "Obtained requested range of the raw file successfully"),
+        (status = 304, description = "File has not been modified"),
         (status = 404, description = "File not found", body = HttpErrorResponse),
+        (status = 416, description = "Requested range is not satisfiable"),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
     params(
@@ -576,10 +1458,12 @@ pub async fn get_raw_presigned(
 pub async fn get_raw_named(
     db: TenantDb,
     storage: TenantStorage,
+    s3: TenantS3,
     Path((scope, file_id, _tail)): Path<(DocumentBoxScope, FileId, String)>,
     query: Query<RawFileQuery>,
+    headers: HeaderMap,
 ) -> Result<Response<Body>, DynHttpError> {
-    get_raw(db, storage, Path((scope, file_id)), query).await
+    get_raw(db, storage, s3, Path((scope, file_id)), query, headers).await
 }
 
 /// Search
@@ -636,27 +1520,29 @@ pub async fn search(
 
 /// Delete file by ID
 ///
-/// Deletes the provided file
+/// Moves the provided file to the trash, the file can be restored
+/// until it is permanently deleted after the trash retention period
 #[utoipa::path(
     delete,
     operation_id = "file_delete",
     tag = FILE_TAG,
     path = "/box/{scope}/file/{file_id}",
     responses(
-        (status = 204, description = "Deleted file successfully"),
+        (status = 204, description = "Moved file to the trash successfully"),
         (status = 404, description = "File not found", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
     params(
         ("scope" = DocumentBoxScope, Path, description = "Scope the file resides within"),
         ("file_id" = Uuid, Path, description = "ID of the file to delete"),
-        TenantParams
+        TenantParams,
+        UserParams
     )
 )]
 #[tracing::instrument(skip_all, fields(scope = %scope, file_id = %file_id))]
 pub async fn delete(
+    action_user: ActionUser,
     TenantDb(db): TenantDb,
-    TenantStorage(storage): TenantStorage,
     TenantSearch(search): TenantSearch,
     TenantEvents(events): TenantEvents,
     Path((scope, file_id)): Path<(DocumentBoxScope, FileId)>,
@@ -671,13 +1557,34 @@ pub async fn delete(
         })?
         .ok_or(HttpFileError::UnknownFile)?;
 
-    delete_file(&db, &storage, &search, &events, file, scope)
+    // Trashed files are hidden from search until they are restored, removed from
+    // search before trashing so a failed delete can be retried
+    search.delete_data(file.id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to remove trashed file from search index");
+        HttpCommonError::ServerError
+    })?;
+
+    let deleted_by = action_user.store_user(&db).await?;
+    let deleted_by = deleted_by.as_ref().map(|value| value.id.to_string());
+
+    let trashed = trash_file(&db, file.id, deleted_by)
         .await
         .map_err(|cause| {
-            tracing::error!(?cause, "failed to delete file");
+            tracing::error!(?cause, "failed to move file to trash");
             HttpCommonError::ServerError
         })?;
 
+    // File was already moved to the trash by another request
+    if !trashed {
+        return Err(HttpFileError::UnknownFile.into());
+    }
+
+    // File is deleted from the perspective of event consumers once it is in the trash
+    events.publish_event(TenantEventMessage::FileDeleted(WithScope {
+        data: file,
+        scope,
+    }));
+
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -732,6 +1639,7 @@ pub async fn get_generated(
     path = "/box/{scope}/file/{file_id}/generated/{type}/raw",
     responses(
         (status = 200, description = "Obtained raw file successfully"),
+        (status = 304, description = "File has not been modified"),
         (status = 404, description = "Generated file not found", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
@@ -746,7 +1654,9 @@ pub async fn get_generated(
 pub async fn get_generated_raw(
     TenantDb(db): TenantDb,
     TenantStorage(storage): TenantStorage,
+    TenantS3(s3): TenantS3,
     Path((scope, file_id, generated_type)): Path<(DocumentBoxScope, FileId, GeneratedFileType)>,
+    headers: HeaderMap,
 ) -> Result<Response<Body>, DynHttpError> {
     let DocumentBoxScope(scope) = scope;
 
@@ -758,13 +1668,6 @@ pub async fn get_generated_raw(
         })?
         .ok_or(HttpFileError::NoMatchingGenerated)?;
 
-    let byte_stream = storage.get_file(&file.file_key).await.map_err(|cause| {
-        tracing::error!(?cause, "failed to file from storage");
-        HttpCommonError::ServerError
-    })?;
-
-    let body = axum::body::Body::from_stream(byte_stream);
-
     let csp = match mime::Mime::from_str(&file.mime) {
         // Images are served with a strict image only content security policy
         Ok(mime) if mime.type_() == mime::IMAGE => "default-src 'none'; img-src 'self' data:;",
@@ -772,14 +1675,41 @@ pub async fn get_generated_raw(
         _ => "script-src 'none'; object-src 'none'; base-uri 'none'; form-action 'none'",
     };
 
-    Ok(Response::builder()
-        .header(header::CONTENT_TYPE, file.mime)
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, &file.mime)
         .header(header::CONTENT_SECURITY_POLICY, csp)
         .header(
             header::CONTENT_DISPOSITION,
-            HeaderValue::from_str("inline;filename=\"preview.pdf\"")?,
-        )
-        .body(body)?)
+            content_disposition(DispositionType::Inline, "preview.pdf")?,
+        );
+
+    // Generated file sizes are not stored, the size is read from storage so that
+    // range requests are supported
+    let size = s3
+        .get_file_size(&file.file_key)
+        .await
+        .inspect_err(|cause| {
+            tracing::warn!(
+                ?cause,
+                "failed to get generated file size, range requests disabled"
+            );
+        })
+        .ok();
+
+    raw_file_response(
+        &headers,
+        &storage,
+        &s3,
+        RawFileMetadata {
+            file_key: &file.file_key,
+            size,
+            hash: &file.hash,
+            last_modified: file.created_at,
+            checksum: None,
+        },
+        builder,
+    )
+    .await
 }
 
 /// Get generated file raw presigned
@@ -794,6 +1724,7 @@ pub async fn get_generated_raw(
     path = "/box/{scope}/file/{file_id}/generated/{type}/raw-presigned",
     responses(
         (status = 200, description = "Obtained raw file successfully"),
+        (status = 400, description = "Invalid expiry time", body = HttpErrorResponse),
         (status = 404, description = "Generated file not found", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
@@ -807,12 +1738,21 @@ pub async fn get_generated_raw(
 #[tracing::instrument(skip_all, fields(scope = %scope, file_id = %file_id, generated_type = %generated_type, req = ?req))]
 pub async fn get_generated_raw_presigned(
     TenantDb(db): TenantDb,
-    TenantStorage(storage): TenantStorage,
+    TenantS3(s3): TenantS3,
+    Extension(expiry): Extension<PresignedDownloadExpiry>,
     Path((scope, file_id, generated_type)): Path<(DocumentBoxScope, FileId, GeneratedFileType)>,
-    Json(req): Json<GetPresignedRequest>,
+    Garde(Json(req)): Garde<Json<GetPresignedRequest>>,
 ) -> HttpResult<PresignedDownloadResponse> {
     let DocumentBoxScope(scope) = scope;
 
+    let expires_at =
+        expiry
+            .duration(req.expires_at)
+            .ok_or(HttpFileError::InvalidPresignedExpiry(
+                expiry.min_seconds,
+                expiry.max_seconds,
+            ))?;
+
     let file = GeneratedFile::find(&db, &scope, file_id, generated_type)
         .await
         .map_err(|cause| {
@@ -821,11 +1761,17 @@ pub async fn get_generated_raw_presigned(
         })?
         .ok_or(HttpFileError::NoMatchingGenerated)?;
 
-    let expires_at = req.expires_at.unwrap_or(900);
-    let expires_at = Duration::from_secs(expires_at as u64);
+    // Override the response headers to match the raw generated file route
+    let options = PresignedDownloadOptions {
+        content_type: Some(file.mime.clone()),
+        content_disposition: Some(content_disposition_value(
+            DispositionType::from_download(req.download),
+            "preview.pdf",
+        )),
+    };
 
-    let (signed_request, expires_at) = storage
-        .create_presigned_download(&file.file_key, expires_at)
+    let (signed_request, expires_at) = s3
+        .create_presigned_download(&file.file_key, expires_at, options)
         .await
         .map_err(|_| HttpCommonError::ServerError)?;
 
@@ -853,6 +1799,7 @@ pub async fn get_generated_raw_presigned(
     path = "/box/{scope}/file/{file_id}/generated/{type}/raw/{*tail}",
     responses(
         (status = 200, description = "Obtained raw file successfully"),
+        (status = 304, description = "File has not been modified"),
         (status = 404, description = "Generated file not found", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
@@ -867,12 +1814,21 @@ pub async fn get_generated_raw_presigned(
 pub async fn get_generated_raw_named(
     db: TenantDb,
     storage: TenantStorage,
+    s3: TenantS3,
     Path((scope, file_id, generated_type, _tail)): Path<(
         DocumentBoxScope,
         FileId,
         GeneratedFileType,
         String,
     )>,
+    headers: HeaderMap,
 ) -> Result<Response<Body>, DynHttpError> {
-    get_generated_raw(db, storage, Path((scope, file_id, generated_type))).await
+    get_generated_raw(
+        db,
+        storage,
+        s3,
+        Path((scope, file_id, generated_type)),
+        headers,
+    )
+    .await
 }
@@ -4,7 +4,7 @@ use crate::{
     error::{DynHttpError, HttpCommonError, HttpErrorResponse, HttpResult, HttpStatusResult},
     middleware::{
         action_user::{ActionUser, UserParams},
-        tenant::{TenantDb, TenantEvents, TenantParams, TenantSearch, TenantStorage},
+        tenant::{TenantDb, TenantEvents, TenantParams, TenantSearch},
     },
     models::{
         document_box::DocumentBoxScope,
@@ -13,15 +13,22 @@ use crate::{
 };
 use axum::{Json, extract::Path, http::StatusCode};
 use axum_valid::Garde;
-use docbox_core::folders::{
-    create_folder::{CreateFolderData, safe_create_folder},
-    delete_folder::delete_folder,
-    update_folder::{UpdateFolder, UpdateFolderError},
+use docbox_core::{
+    events::TenantEventMessage,
+    folders::{
+        create_folder::{CreateFolderData, safe_create_folder},
+        update_folder::{UpdateFolder, UpdateFolderError},
+    },
 };
 use docbox_database::models::{
+    document_box::WithScope,
     edit_history::EditHistory,
     folder::{self, Folder, FolderId, FolderWithExtra, ResolvedFolderWithExtra},
 };
+use docbox_serverless_shared::{
+    database::trash::trash_folder, document_box::DocumentBoxContents,
+    search_index::remove_document_box_contents,
+};
 
 pub const FOLDER_TAG: &str = "Folder";
 
@@ -270,31 +277,32 @@ pub async fn update(
 
 /// Delete a folder by ID
 ///
-/// Deletes a document box folder and all its contents. This will
-/// traverse the folder contents as a stack deleting all files and
-/// folders within the folder before deleting itself
+/// Moves a document box folder and all its contents to the trash, the
+/// folder can be restored along with its contents until it is permanently
+/// deleted after the trash retention period
 #[utoipa::path(
     delete,
     operation_id = "folder_delete",
     tag = FOLDER_TAG,
     path = "/box/{scope}/folder/{folder_id}",
     responses(
-        (status = 204, description = "Deleted folder successfully"),
+        (status = 204, description = "Moved folder to the trash successfully"),
         (status = 404, description = "Folder not found", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
     params(
         ("scope" = DocumentBoxScope, Path, description = "Scope the folder resides within"),
         ("folder_id" = Uuid, Path, description = "ID of the folder to delete"),
-        TenantParams
+        TenantParams,
+        UserParams
     )
 )]
 #[tracing::instrument(skip_all, fields(scope = %scope, folder_id = %folder_id))]
 pub async fn delete(
+    action_user: ActionUser,
     TenantDb(db): TenantDb,
-    TenantStorage(storage): TenantStorage,
-    TenantEvents(events): TenantEvents,
     TenantSearch(search): TenantSearch,
+    TenantEvents(events): TenantEvents,
     Path((scope, folder_id)): Path<(DocumentBoxScope, FolderId)>,
 ) -> HttpStatusResult {
     let DocumentBoxScope(scope) = scope;
@@ -314,12 +322,51 @@ pub async fn delete(
         return Err(HttpFolderError::CannotDeleteRoot.into());
     }
 
-    delete_folder(&db, &storage, &search, &events, folder)
+    // Contents are loaded before trashing as they are hidden once the folder is in the trash
+    let contents = DocumentBoxContents::load_folder(&db, folder.id)
         .await
         .map_err(|cause| {
-            tracing::error!(?cause, "failed to delete folder");
+            tracing::error!(?cause, "failed to load folder contents");
             HttpCommonError::ServerError
         })?;
 
+    // Trashed folders and their contents are hidden from search until they are
+    // restored, removed from search before trashing so a failed delete can be retried
+    search.delete_data(folder.id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to remove trashed folder from search index");
+        HttpCommonError::ServerError
+    })?;
+
+    remove_document_box_contents(&search, &contents)
+        .await
+        .map_err(|cause| {
+            tracing::error!(
+                ?cause,
+                "failed to remove trashed folder contents from search"
+            );
+            HttpCommonError::ServerError
+        })?;
+
+    let deleted_by = action_user.store_user(&db).await?;
+    let deleted_by = deleted_by.as_ref().map(|value| value.id.to_string());
+
+    let trashed = trash_folder(&db, folder.id, deleted_by)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to move folder to trash");
+            HttpCommonError::ServerError
+        })?;
+
+    // Folder was already moved to the trash by another request
+    if !trashed {
+        return Err(HttpFolderError::UnknownFolder.into());
+    }
+
+    // Folder is deleted from the perspective of event consumers once it is in the trash
+    events.publish_event(TenantEventMessage::FolderDeleted(WithScope {
+        data: folder,
+        scope,
+    }));
+
     Ok(StatusCode::NO_CONTENT)
 }
@@ -17,21 +17,22 @@ use crate::{
 };
 use axum::http::header;
 use axum::{
+    Extension, Json,
     body::Body,
     extract::Path,
     http::{Response, StatusCode},
-    Extension, Json,
 };
 use axum_valid::Garde;
+use docbox_core::events::TenantEventMessage;
+use docbox_core::links::create_link::{CreateLinkData, safe_create_link};
 use docbox_core::links::update_link::{UpdateLink, UpdateLinkError};
-use docbox_core::links::{
-    create_link::safe_create_link, create_link::CreateLinkData, delete_link::delete_link,
-};
 use docbox_database::models::{
+    document_box::WithScope,
     edit_history::EditHistory,
     folder::Folder,
     link::{CreatedByUser, LastModifiedByUser, Link, LinkId, LinkWithExtra},
 };
+use docbox_serverless_shared::database::trash::trash_link;
 use docbox_web_scraper::WebsiteMetaService;
 use std::sync::Arc;
 
@@ -438,25 +439,28 @@ pub async fn update(
 
 /// Delete a link by ID
 ///
-/// Deletes a specific link using its ID
+/// Moves a specific link to the trash, the link can be restored until
+/// it is permanently deleted after the trash retention period
 #[utoipa::path(
     delete,
     operation_id = "link_delete",
     tag = LINK_TAG,
     path = "/box/{scope}/link/{link_id}",
     responses(
-        (status = 204, description = "Deleted link successfully"),
+        (status = 204, description = "Moved link to the trash successfully"),
         (status = 404, description = "Link not found", body = HttpErrorResponse),
         (status = 500, description = "Internal server error", body = HttpErrorResponse)
     ),
     params(
         ("scope" = DocumentBoxScope, Path, description = "Scope the link resides within"),
         ("link_id" = Uuid, Path, description = "ID of the link to delete"),
-        TenantParams
+        TenantParams,
+        UserParams
     )
 )]
 #[tracing::instrument(skip_all, fields(scope = %scope, link_id = %link_id))]
 pub async fn delete(
+    action_user: ActionUser,
     TenantDb(db): TenantDb,
     TenantSearch(search): TenantSearch,
     TenantEvents(events): TenantEvents,
@@ -474,12 +478,33 @@ pub async fn delete(
         // Link not found
         .ok_or(HttpLinkError::UnknownLink)?;
 
-    delete_link(&db, &search, &events, link, scope)
+    // Trashed links are hidden from search until they are restored, removed from
+    // search before trashing so a failed delete can be retried
+    search.delete_data(link.id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to remove trashed link from search index");
+        HttpCommonError::ServerError
+    })?;
+
+    let deleted_by = action_user.store_user(&db).await?;
+    let deleted_by = deleted_by.as_ref().map(|value| value.id.to_string());
+
+    let trashed = trash_link(&db, link.id, deleted_by)
         .await
         .map_err(|cause| {
-            tracing::error!(?cause, "failed to delete folder");
+            tracing::error!(?cause, "failed to move link to trash");
             HttpCommonError::ServerError
         })?;
 
+    // Link was already moved to the trash by another request
+    if !trashed {
+        return Err(HttpLinkError::UnknownLink.into());
+    }
+
+    // Link is deleted from the perspective of event consumers once it is in the trash
+    events.publish_event(TenantEventMessage::LinkDeleted(WithScope {
+        data: link,
+        scope,
+    }));
+
     Ok(StatusCode::NO_CONTENT)
 }
@@ -1,9 +1,16 @@
 use axum::{
     Router,
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    routing::{delete, get, post},
 };
 
-use super::middleware::tenant::tenant_auth_middleware;
+use super::middleware::{
+    admin::admin_auth_middleware, jwt::jwt_scope_middleware, tenant::tenant_auth_middleware,
+    upload_limit::direct_upload_limit_middleware,
+};
+use crate::docs::{ApiDoc, openapi_json};
+use utoipa::OpenApi;
+use utoipa_scalar::{Scalar, Servable};
 
 pub mod admin;
 pub mod document_box;
@@ -11,6 +18,7 @@ pub mod file;
 pub mod folder;
 pub mod link;
 pub mod task;
+pub mod trash;
 pub mod utils;
 
 pub fn router() -> Router {
@@ -22,6 +30,13 @@ pub fn router() -> Router {
         .route("/server-details", get(utils::server_details))
 }
 
+/// Routes for the OpenAPI document and the interactive API explorer
+pub fn docs_router() -> Router {
+    Router::new()
+        .route("/openapi.json", get(openapi_json))
+        .merge(Scalar::with_url("/docs", ApiDoc::openapi()))
+}
+
 /// Routes for /admin/
 pub fn admin_router() -> Router {
     Router::new()
@@ -45,7 +60,7 @@ pub fn admin_router() -> Router {
             post(admin::search_tenant).layer(axum::middleware::from_fn(tenant_auth_middleware)),
         )
         .route(
-            "/reprocess_octet_stream_files_tenant",
+            "/reprocess-octet-stream-files",
             post(admin::reprocess_octet_stream_files_tenant)
                 .layer(axum::middleware::from_fn(tenant_auth_middleware)),
         )
@@ -53,6 +68,20 @@ pub fn admin_router() -> Router {
             "/purge-expired-presigned-tasks",
             post(admin::http_purge_expired_presigned_tasks),
         )
+        .route("/migrate-tenants", post(admin::http_migrate_tenants))
+        .route(
+            "/tenant-api-keys",
+            get(admin::get_tenant_api_keys)
+                .post(admin::create_tenant_api_key_tenant)
+                .layer(axum::middleware::from_fn(tenant_auth_middleware)),
+        )
+        .route(
+            "/tenant-api-keys/{name}",
+            delete(admin::delete_tenant_api_key_tenant)
+                .layer(axum::middleware::from_fn(tenant_auth_middleware)),
+        )
+        // Admin routes require an admin API key
+        .layer(axum::middleware::from_fn(admin_auth_middleware))
 }
 
 /// Routes for /box/
@@ -65,10 +94,13 @@ pub fn document_box_router() -> Router {
                 .route("/", get(document_box::get).delete(document_box::delete))
                 .route("/stats", get(document_box::stats))
                 .route("/search", post(document_box::search))
+                .route("/trash", get(trash::get))
                 .nest("/file", file_router())
                 .nest("/task", task_router())
                 .nest("/link", link_router())
-                .nest("/folder", folder_router()),
+                .nest("/folder", folder_router())
+                // Layer to restrict token access to allowed scopes
+                .layer(axum::middleware::from_fn(jwt_scope_middleware)),
         )
         // Layer to authorize requests
         .layer(axum::middleware::from_fn(tenant_auth_middleware))
@@ -83,19 +115,40 @@ pub fn folder_router() -> Router {
                 "/",
                 get(folder::get).put(folder::update).delete(folder::delete),
             )
-            .route("/edit-history", get(folder::get_edit_history)),
+            .route("/edit-history", get(folder::get_edit_history))
+            .route("/restore", post(trash::restore_folder)),
     )
 }
 
 /// Routes for /box/:scope/file/
 pub fn file_router() -> Router {
     Router::new()
-        .route("/", post(file::upload))
+        .route(
+            "/",
+            // Direct uploads enforce their own body size limit
+            post(file::upload).layer((
+                DefaultBodyLimit::disable(),
+                axum::middleware::from_fn(direct_upload_limit_middleware),
+            )),
+        )
+        .route("/duplicates", get(file::get_duplicates))
         .nest(
             "/presigned",
             Router::new()
-                .route("/", post(file::create_presigned))
-                .route("/{task_id}", get(file::get_presigned)),
+                .route("/", get(file::list_presigned).post(file::create_presigned))
+                .route("/multipart", post(file::create_presigned_multipart))
+                .nest(
+                    "/{task_id}",
+                    Router::new()
+                        .route("/", get(file::get_presigned).delete(file::cancel_presigned))
+                        .nest(
+                            "/multipart",
+                            Router::new()
+                                .route("/parts", get(file::get_presigned_multipart_parts))
+                                .route("/complete", post(file::complete_presigned_multipart))
+                                .route("/abort", post(file::abort_presigned_multipart)),
+                        ),
+                ),
         )
         .nest(
             "/{file_id}",
@@ -109,6 +162,7 @@ pub fn file_router() -> Router {
                 .route("/children", get(file::get_children))
                 .route("/edit-history", get(file::get_edit_history))
                 .route("/search", post(file::search))
+                .route("/restore", post(trash::restore_file))
                 // Generated file instance
                 .nest(
                     "/generated",
@@ -140,6 +194,7 @@ pub fn link_router() -> Router {
             .route("/metadata", get(link::get_metadata))
             .route("/favicon", get(link::get_favicon))
             .route("/image", get(link::get_image))
-            .route("/edit-history", get(link::get_edit_history)),
+            .route("/edit-history", get(link::get_edit_history))
+            .route("/restore", post(trash::restore_link)),
     )
 }
@@ -0,0 +1,345 @@
+//! Trash related endpoints
+
+use crate::{
+    error::{DynHttpError, HttpCommonError, HttpErrorResponse, HttpResult, HttpStatusResult},
+    middleware::tenant::{TenantDb, TenantParams, TenantSearch, TenantStorage},
+    models::{
+        document_box::DocumentBoxScope,
+        trash::{HttpTrashError, TrashQuery, TrashResponse, TrashedItem},
+        utils::page_size,
+    },
+};
+use axum::{
+    Json,
+    extract::{Path, Query},
+    http::StatusCode,
+};
+use docbox_database::{
+    DbPool,
+    models::{
+        document_box::DocumentBoxScopeRaw,
+        file::{File, FileId},
+        folder::{Folder, FolderId},
+        link::{Link, LinkId},
+    },
+};
+use docbox_search::TenantSearchIndex;
+use docbox_serverless_shared::{
+    database::trash::{
+        count_trashed_files, count_trashed_folders, count_trashed_links, find_trashed_file,
+        find_trashed_files, find_trashed_folder, find_trashed_folders, find_trashed_link,
+        find_trashed_links, is_folder_in_trash, restore_file as restore_trashed_file,
+        restore_folder as restore_trashed_folder, restore_link as restore_trashed_link,
+    },
+    document_box::DocumentBoxContents,
+    search_index::index_document_box_contents,
+};
+use docbox_storage::TenantStorageLayer;
+
+pub const TRASH_TAG: &str = "Trash";
+
+/// Get trash
+///
+/// Lists the files, folders and links that have been deleted from the
+/// document box and can be restored. Items are permanently deleted once
+/// they have been in the trash longer than the retention period
+#[utoipa::path(
+    get,
+    operation_id = "trash_get",
+    tag = TRASH_TAG,
+    path = "/box/{scope}/trash",
+    responses(
+        (status = 200, description = "Obtained trash successfully", body = TrashResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope of the document box"),
+        TrashQuery,
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, query = ?query))]
+pub async fn get(
+    TenantDb(db): TenantDb,
+    Path(DocumentBoxScope(scope)): Path<DocumentBoxScope>,
+    Query(query): Query<TrashQuery>,
+) -> HttpResult<TrashResponse> {
+    let offset = query.offset.unwrap_or(0);
+    let limit = page_size(query.size);
+
+    let map_err = |cause| {
+        tracing::error!(?cause, "failed to query trash");
+        HttpCommonError::ServerError
+    };
+
+    let folders = find_trashed_folders(&db, &scope, None, offset, limit)
+        .await
+        .map_err(map_err)?;
+    let files = find_trashed_files(&db, &scope, None, offset, limit)
+        .await
+        .map_err(map_err)?;
+    let links = find_trashed_links(&db, &scope, None, offset, limit)
+        .await
+        .map_err(map_err)?;
+
+    let total_folders = count_trashed_folders(&db, &scope, None)
+        .await
+        .map_err(map_err)?;
+    let total_files = count_trashed_files(&db, &scope, None)
+        .await
+        .map_err(map_err)?;
+    let total_links = count_trashed_links(&db, &scope, None)
+        .await
+        .map_err(map_err)?;
+
+    Ok(Json(TrashResponse {
+        folders: folders.into_iter().map(TrashedItem::from).collect(),
+        files: files.into_iter().map(TrashedItem::from).collect(),
+        links: links.into_iter().map(TrashedItem::from).collect(),
+        total_folders,
+        total_files,
+        total_links,
+    }))
+}
+
+/// Restore file
+///
+/// Restores a file from the trash back into its folder
+#[utoipa::path(
+    post,
+    operation_id = "trash_restore_file",
+    tag = TRASH_TAG,
+    path = "/box/{scope}/file/{file_id}/restore",
+    responses(
+        (status = 204, description = "Restored file successfully"),
+        (status = 404, description = "File not found in the trash", body = HttpErrorResponse),
+        (status = 409, description = "Folder the file was in does not exist or is in the trash", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope the file resides within"),
+        ("file_id" = Uuid, Path, description = "ID of the file to restore"),
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, file_id = %file_id))]
+pub async fn restore_file(
+    TenantDb(db): TenantDb,
+    TenantSearch(search): TenantSearch,
+    TenantStorage(storage): TenantStorage,
+    Path((scope, file_id)): Path<(DocumentBoxScope, FileId)>,
+) -> HttpStatusResult {
+    let DocumentBoxScope(scope) = scope;
+
+    let file = find_trashed_file(&db, &scope, file_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query trashed file");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpTrashError::UnknownFile)?;
+
+    ensure_parent_folder(&db, &scope, file.folder_id).await?;
+
+    restore_trashed_file(&db, file.id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to restore file");
+        HttpCommonError::ServerError
+    })?;
+
+    let file = File::find_with_extra(&db, &scope, file.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query restored file");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpTrashError::UnknownFile)?;
+
+    let contents = DocumentBoxContents {
+        files: vec![file],
+        ..Default::default()
+    };
+
+    index_restored(&db, &search, &storage, &scope, contents).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Restore folder
+///
+/// Restores a folder from the trash back into its parent folder along
+/// with all of its contents
+#[utoipa::path(
+    post,
+    operation_id = "trash_restore_folder",
+    tag = TRASH_TAG,
+    path = "/box/{scope}/folder/{folder_id}/restore",
+    responses(
+        (status = 204, description = "Restored folder successfully"),
+        (status = 404, description = "Folder not found in the trash", body = HttpErrorResponse),
+        (status = 409, description = "Parent folder does not exist or is in the trash", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope the folder resides within"),
+        ("folder_id" = Uuid, Path, description = "ID of the folder to restore"),
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, folder_id = %folder_id))]
+pub async fn restore_folder(
+    TenantDb(db): TenantDb,
+    TenantSearch(search): TenantSearch,
+    TenantStorage(storage): TenantStorage,
+    Path((scope, folder_id)): Path<(DocumentBoxScope, FolderId)>,
+) -> HttpStatusResult {
+    let DocumentBoxScope(scope) = scope;
+
+    let folder = find_trashed_folder(&db, &scope, folder_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query trashed folder");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpTrashError::UnknownFolder)?;
+
+    // Only non-root folders can be moved to the trash
+    let parent_id = folder
+        .folder_id
+        .ok_or(HttpTrashError::ParentFolderUnavailable)?;
+
+    ensure_parent_folder(&db, &scope, parent_id).await?;
+
+    restore_trashed_folder(&db, folder.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to restore folder");
+            HttpCommonError::ServerError
+        })?;
+
+    let folder = Folder::find_by_id_with_extra(&db, &scope, folder.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query restored folder");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpTrashError::UnknownFolder)?;
+
+    let mut contents = DocumentBoxContents::load_folder(&db, folder.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to load restored folder contents");
+            HttpCommonError::ServerError
+        })?;
+    contents.folders.push(folder);
+
+    index_restored(&db, &search, &storage, &scope, contents).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Restore link
+///
+/// Restores a link from the trash back into its folder
+#[utoipa::path(
+    post,
+    operation_id = "trash_restore_link",
+    tag = TRASH_TAG,
+    path = "/box/{scope}/link/{link_id}/restore",
+    responses(
+        (status = 204, description = "Restored link successfully"),
+        (status = 404, description = "Link not found in the trash", body = HttpErrorResponse),
+        (status = 409, description = "Folder the link was in does not exist or is in the trash", body = HttpErrorResponse),
+        (status = 500, description = "Internal server error", body = HttpErrorResponse)
+    ),
+    params(
+        ("scope" = DocumentBoxScope, Path, description = "Scope the link resides within"),
+        ("link_id" = Uuid, Path, description = "ID of the link to restore"),
+        TenantParams
+    )
+)]
+#[tracing::instrument(skip_all, fields(scope = %scope, link_id = %link_id))]
+pub async fn restore_link(
+    TenantDb(db): TenantDb,
+    TenantSearch(search): TenantSearch,
+    TenantStorage(storage): TenantStorage,
+    Path((scope, link_id)): Path<(DocumentBoxScope, LinkId)>,
+) -> HttpStatusResult {
+    let DocumentBoxScope(scope) = scope;
+
+    let link = find_trashed_link(&db, &scope, link_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query trashed link");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpTrashError::UnknownLink)?;
+
+    ensure_parent_folder(&db, &scope, link.folder_id).await?;
+
+    restore_trashed_link(&db, link.id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to restore link");
+        HttpCommonError::ServerError
+    })?;
+
+    let link = Link::find_with_extra(&db, &scope, link.id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query restored link");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpTrashError::UnknownLink)?;
+
+    let contents = DocumentBoxContents {
+        links: vec![link],
+        ..Default::default()
+    };
+
+    index_restored(&db, &search, &storage, &scope, contents).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Ensures the folder an item is being restored into exists, folders
+/// that are in the trash (or within a trashed folder) are not found
+async fn ensure_parent_folder(
+    db: &DbPool,
+    scope: &DocumentBoxScopeRaw,
+    folder_id: FolderId,
+) -> Result<(), DynHttpError> {
+    Folder::find_by_id(db, scope, folder_id)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to query parent folder");
+            HttpCommonError::ServerError
+        })?
+        .ok_or(HttpTrashError::ParentFolderUnavailable)?;
+
+    let in_trash = is_folder_in_trash(db, folder_id).await.map_err(|cause| {
+        tracing::error!(?cause, "failed to query parent folder trash");
+        HttpCommonError::ServerError
+    })?;
+
+    if in_trash {
+        return Err(HttpTrashError::ParentFolderUnavailable.into());
+    }
+
+    Ok(())
+}
+
+/// Adds restored items back to the search index
+async fn index_restored(
+    db: &DbPool,
+    search: &TenantSearchIndex,
+    storage: &TenantStorageLayer,
+    scope: &str,
+    contents: DocumentBoxContents,
+) -> Result<(), HttpCommonError> {
+    index_document_box_contents(db, search, storage, scope, contents)
+        .await
+        .map_err(|cause| {
+            tracing::error!(?cause, "failed to add restored items to search index");
+            HttpCommonError::ServerError
+        })?;
+
+    Ok(())
+}
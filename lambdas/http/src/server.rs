@@ -0,0 +1,63 @@
+//! Standalone HTTP server mode, serves the same router that the lambda
+//! uses as a long-lived server for local development and testing
+
+use axum::Router;
+use lambda_http::{Error, tracing};
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+/// Default address to bind the server to when one is not specified
+const DEFAULT_SERVER_ADDRESS: &str = "0.0.0.0:8080";
+
+/// Serves the provided `app` router on the address from `DOCBOX_SERVER_ADDRESS`
+/// until a shutdown signal is received
+pub async fn serve(app: Router) -> Result<(), Error> {
+    let server_address: SocketAddr = std::env::var("DOCBOX_SERVER_ADDRESS")
+        .unwrap_or_else(|_| DEFAULT_SERVER_ADDRESS.to_string())
+        .parse()?;
+
+    let listener = TcpListener::bind(server_address).await?;
+
+    tracing::info!("server started on {server_address}");
+
+    axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+
+    tracing::info!("server stopped");
+
+    Ok(())
+}
+
+/// Future that completes when a CTRL+C or SIGTERM signal is received
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(error) = tokio::signal::ctrl_c().await {
+            tracing::error!(?error, "failed to listen for ctrl+c signal");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(error) => {
+                tracing::error!(?error, "failed to listen for terminate signal");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+
+    tracing::info!("shutdown signal received, stopping server");
+}
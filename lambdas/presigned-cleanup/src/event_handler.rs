@@ -1,25 +1,50 @@
 use aws_lambda_events::event::eventbridge::EventBridgeEvent;
-use chrono::Utc;
+use chrono::{DateTime, TimeDelta, Utc};
 use docbox_core::aws::aws_config;
-use docbox_database::{
-    DatabasePoolCache, DatabasePoolCacheConfig, DbPool, DbResult,
-    models::{
-        presigned_upload_task::{PresignedTaskStatus, PresignedUploadTask},
-        tenant::Tenant,
+use docbox_database::{DatabasePoolCache, DatabasePoolCacheConfig};
+use docbox_secrets::{SecretManager, SecretsManagerConfig};
+use docbox_serverless_shared::{
+    presigned::{
+        PurgeExpiredPresignedOptions, PurgeExpiredPresignedSummary, PurgeTenantOptions,
+        purge_expired_presigned_tasks,
+    },
+    reconcile::{
+        ReconcileStorageOptions, ReconcileStorageSummary, ReconcileTenantOptions, reconcile_storage,
     },
+    s3::S3StorageFactory,
+    tenant_sweep::TenantSweepOptions,
 };
-use docbox_secrets::{SecretManager, SecretsManagerConfig};
-use docbox_storage::{StorageLayerFactory, StorageLayerFactoryConfig, TenantStorageLayer};
+use docbox_storage::{StorageLayerFactory, StorageLayerFactoryConfig};
 use lambda_runtime::{Error, LambdaEvent, tracing};
-use std::sync::Arc;
-use thiserror::Error;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use tokio::sync::OnceCell;
+use uuid::Uuid;
 
 static DEPENDENCIES: OnceCell<Dependencies> = OnceCell::const_new();
 
+/// Time reserved before the lambda deadline for tenants that are
+/// already being purged to finish
+const DEADLINE_MARGIN: Duration = Duration::from_secs(30);
+
+/// Time reserved before the lambda deadline for reporting the results,
+/// tenants that are still running at this point are cancelled
+const CANCEL_MARGIN: Duration = Duration::from_secs(5);
+
+/// Default number of tenants to purge concurrently
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Default hours before an unreferenced object is considered orphaned
+const DEFAULT_GRACE_PERIOD_HOURS: u32 = 24;
+
 pub struct Dependencies {
     pub db: Arc<DatabasePoolCache>,
     pub storage: StorageLayerFactory,
+    pub s3: S3StorageFactory,
+    pub concurrency: usize,
 }
 
 async fn dependencies() -> Result<Dependencies, Box<dyn std::error::Error + Send + Sync>> {
@@ -42,110 +67,200 @@ async fn dependencies() -> Result<Dependencies, Box<dyn std::error::Error + Send
     let storage_factory_config = StorageLayerFactoryConfig::from_env()?;
     let storage = StorageLayerFactory::from_config(&aws_config, storage_factory_config);
 
-    Ok(Dependencies { db, storage })
+    // Setup S3 storage for aborting incomplete multipart uploads and listing objects
+    let s3 = S3StorageFactory::from_config(&aws_config);
+
+    // Number of tenants to purge at once
+    let concurrency = match std::env::var("DOCBOX_PRESIGNED_CLEANUP_CONCURRENCY") {
+        Ok(value) => value.parse::<usize>()?,
+        Err(_) => DEFAULT_CONCURRENCY,
+    };
+
+    Ok(Dependencies {
+        db,
+        storage,
+        s3,
+        concurrency,
+    })
+}
+
+/// Optional details provided in the EventBridge event to control the cleanup,
+/// scheduled events provide an empty detail which purges all tenants
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CleanupEventDetail {
+    /// Cleanup to perform
+    pub mode: CleanupMode,
+    /// Only cleanup the tenant with this ID
+    pub tenant_id: Option<Uuid>,
+    /// Only cleanup tenants within this environment
+    pub tenant_env: Option<String>,
+    /// Purge tasks that expired before this date instead of the current date
+    pub older_than: Option<DateTime<Utc>>,
+    /// Hours since an object was last modified before it can be considered orphaned
+    pub grace_period_hours: Option<u32>,
+    /// Report what would be removed without deleting anything, storage reconciliation
+    /// is always a dry run unless this is explicitly set to false
+    pub dry_run: Option<bool>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CleanupMode {
+    /// Purge expired presigned upload tasks
+    #[default]
+    PurgePresigned,
+    /// Find and remove objects in storage that are no longer referenced
+    ReconcileStorage,
+}
+
+/// Summary of the cleanup that was performed
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum CleanupSummary {
+    PurgePresigned(PurgeExpiredPresignedSummary),
+    ReconcileStorage(ReconcileStorageSummary),
 }
 
 pub(crate) async fn outer_function_handler(
-    event: LambdaEvent<EventBridgeEvent>,
-) -> Result<(), Error> {
+    event: LambdaEvent<EventBridgeEvent<CleanupEventDetail>>,
+) -> Result<CleanupSummary, Error> {
     let dependencies = DEPENDENCIES.get_or_try_init(dependencies).await?;
     function_handler(event, dependencies).await
 }
 
 async fn function_handler(
-    _event: LambdaEvent<EventBridgeEvent>,
+    event: LambdaEvent<EventBridgeEvent<CleanupEventDetail>>,
     dependencies: &Dependencies,
-) -> Result<(), Error> {
-    // Run the presigned purge
-    if let Err(error) = purge_expired_presigned_tasks(&dependencies.db, &dependencies.storage).await
-    {
-        tracing::error!(?error, "failed to purge presigned tasks");
-    }
+) -> Result<CleanupSummary, Error> {
+    let detail = event.payload.detail;
 
-    Ok(())
+    let sweep = TenantSweepOptions {
+        concurrency: dependencies.concurrency,
+        deadline: Some(lambda_deadline(event.context.deadline, DEADLINE_MARGIN)),
+        cancel_at: Some(lambda_deadline(event.context.deadline, CANCEL_MARGIN)),
+        tenant_id: detail.tenant_id,
+        tenant_env: detail.tenant_env.clone(),
+    };
+
+    match detail.mode {
+        CleanupMode::PurgePresigned => purge_presigned(dependencies, sweep, &detail)
+            .await
+            .map(CleanupSummary::PurgePresigned),
+        CleanupMode::ReconcileStorage => reconcile(dependencies, sweep, &detail)
+            .await
+            .map(CleanupSummary::ReconcileStorage),
+    }
 }
 
-#[derive(Debug, Error)]
-pub enum PurgeExpiredPresignedError {
-    #[error("failed to connect to database")]
-    ConnectDatabase,
+/// Purge the expired presigned tasks
+async fn purge_presigned(
+    dependencies: &Dependencies,
+    sweep: TenantSweepOptions,
+    detail: &CleanupEventDetail,
+) -> Result<PurgeExpiredPresignedSummary, Error> {
+    let options = PurgeExpiredPresignedOptions {
+        sweep,
+        tenant_options: PurgeTenantOptions {
+            expired_before: detail.older_than.unwrap_or_else(Utc::now),
+            dry_run: detail.dry_run.unwrap_or(false),
+        },
+    };
 
-    #[error("failed to query available tenants")]
-    QueryTenants,
-}
+    if options.tenant_options.dry_run {
+        tracing::info!(?detail, "performing dry run of presigned purge");
+    }
 
-/// Purge the presigned tasks for all tenants
-#[tracing::instrument(skip_all)]
-async fn purge_expired_presigned_tasks(
-    db_cache: &Arc<DatabasePoolCache>,
-    storage: &StorageLayerFactory,
-) -> Result<(), PurgeExpiredPresignedError> {
-    let db = db_cache.get_root_pool().await.map_err(|error| {
-        tracing::error!(?error, "failed to connect to root database");
-        PurgeExpiredPresignedError::ConnectDatabase
-    })?;
-
-    let tenants = Tenant::all(&db).await.map_err(|error| {
-        tracing::error!(?error, "failed to query available tenants");
-        PurgeExpiredPresignedError::QueryTenants
-    })?;
-
-    // Early drop the root database pool access
-    drop(db);
-
-    for tenant in tenants {
-        // Create the database connection pool
-        let db = db_cache.get_tenant_pool(&tenant).await.map_err(|error| {
-            tracing::error!(?error, "failed to connect to tenant database");
-            PurgeExpiredPresignedError::ConnectDatabase
-        })?;
-
-        let storage = storage.create_storage_layer(&tenant);
-
-        if let Err(cause) = purge_expired_presigned_tasks_tenant(&db, &storage).await {
-            tracing::error!(
-                ?cause,
-                ?tenant,
-                "failed to purge presigned tasks for tenant"
-            );
+    // Run the presigned purge
+    let summary = match purge_expired_presigned_tasks(
+        &dependencies.db,
+        &dependencies.storage,
+        &dependencies.s3,
+        options,
+    )
+    .await
+    {
+        Ok(value) => value,
+        Err(error) => {
+            tracing::error!(?error, "failed to purge presigned tasks");
+            return Err(error.into());
         }
-    }
+    };
+
+    tracing::info!(
+        tenants_processed = summary.tenants_processed,
+        tenants_skipped = summary.tenants_skipped,
+        tenants_failed = summary.failures.len(),
+        tasks_deleted = summary.tasks_deleted,
+        objects_deleted = summary.objects_deleted,
+        multipart_uploads_aborted = summary.multipart_uploads_aborted,
+        dry_run = summary.dry_run,
+        failures = ?summary.failures,
+        "purged expired presigned tasks"
+    );
 
-    Ok(())
+    Ok(summary)
 }
 
-/// Purge the presigned tasks for a specific tenant
-async fn purge_expired_presigned_tasks_tenant(
-    db: &DbPool,
-    storage: &TenantStorageLayer,
-) -> DbResult<()> {
-    let current_date = Utc::now();
-    let tasks = PresignedUploadTask::find_expired(db, current_date).await?;
-    if tasks.is_empty() {
-        return Ok(());
+/// Reconcile tenant storage to find and remove orphaned objects
+async fn reconcile(
+    dependencies: &Dependencies,
+    sweep: TenantSweepOptions,
+    detail: &CleanupEventDetail,
+) -> Result<ReconcileStorageSummary, Error> {
+    let grace_period_hours = detail
+        .grace_period_hours
+        .unwrap_or(DEFAULT_GRACE_PERIOD_HOURS);
+
+    let options = ReconcileStorageOptions {
+        sweep,
+        tenant_options: ReconcileTenantOptions {
+            grace_period: TimeDelta::hours(grace_period_hours.into()),
+            dry_run: detail.dry_run.unwrap_or(true),
+        },
+    };
+
+    if options.tenant_options.dry_run {
+        tracing::info!(?detail, "performing dry run of storage reconciliation");
     }
 
-    for task in tasks {
-        // Delete the task itself
-        if let Err(error) = PresignedUploadTask::delete(db, task.id).await {
-            tracing::error!(?error, "failed to delete presigned upload task");
+    let summary = match reconcile_storage(
+        &dependencies.db,
+        &dependencies.storage,
+        &dependencies.s3,
+        options,
+    )
+    .await
+    {
+        Ok(value) => value,
+        Err(error) => {
+            tracing::error!(?error, "failed to reconcile storage");
+            return Err(error.into());
         }
+    };
 
-        // Delete incomplete file uploads
-        match task.status {
-            PresignedTaskStatus::Completed { .. } => {
-                // Upload completed, nothing to revert
-            }
-            PresignedTaskStatus::Failed { .. } | PresignedTaskStatus::Pending => {
-                if let Err(error) = storage.delete_file(&task.file_key).await {
-                    tracing::error!(
-                        ?error,
-                        "failed to delete expired presigned task file from tenant"
-                    );
-                }
-            }
-        }
-    }
+    tracing::info!(
+        tenants_processed = summary.tenants_processed,
+        tenants_skipped = summary.tenants_skipped,
+        tenants_failed = summary.failures.len(),
+        objects_scanned = summary.objects_scanned,
+        orphaned_objects = summary.orphaned_objects.len(),
+        objects_deleted = summary.objects_deleted,
+        dry_run = summary.dry_run,
+        failures = ?summary.failures,
+        "reconciled tenant storage"
+    );
+
+    Ok(summary)
+}
+
+/// Determines the time `margin` before the lambda `deadline` (milliseconds
+/// since the unix epoch)
+fn lambda_deadline(deadline: u64, margin: Duration) -> Instant {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let remaining = Duration::from_millis(deadline).saturating_sub(now);
 
-    Ok(())
+    Instant::now() + remaining.saturating_sub(margin)
 }
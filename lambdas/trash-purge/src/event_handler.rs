@@ -0,0 +1,197 @@
+use aws_lambda_events::event::eventbridge::EventBridgeEvent;
+use chrono::{DateTime, TimeDelta, Utc};
+use docbox_core::{
+    aws::{SqsClient, aws_config},
+    events::{EventPublisherFactory, sqs::SqsEventPublisherFactory},
+};
+use docbox_database::{DatabasePoolCache, DatabasePoolCacheConfig};
+use docbox_search::{SearchIndexFactory, SearchIndexFactoryConfig};
+use docbox_secrets::{SecretManager, SecretsManagerConfig};
+use docbox_serverless_shared::{
+    tenant_sweep::TenantSweepOptions,
+    trash::{
+        DEFAULT_RETENTION_DAYS, PurgeTrashOptions, PurgeTrashSummary, PurgeTrashTenantOptions,
+        purge_trash,
+    },
+};
+use docbox_storage::{StorageLayerFactory, StorageLayerFactoryConfig};
+use lambda_runtime::{Error, LambdaEvent, tracing};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+static DEPENDENCIES: OnceCell<Dependencies> = OnceCell::const_new();
+
+/// Time reserved before the lambda deadline for tenants that are
+/// already being purged to finish
+const DEADLINE_MARGIN: Duration = Duration::from_secs(30);
+
+/// Time reserved before the lambda deadline for reporting the results,
+/// tenants that are still running at this point are cancelled
+const CANCEL_MARGIN: Duration = Duration::from_secs(5);
+
+/// Default number of tenants to purge concurrently
+const DEFAULT_CONCURRENCY: usize = 8;
+
+pub struct Dependencies {
+    pub db: Arc<DatabasePoolCache>,
+    pub storage: StorageLayerFactory,
+    pub search: SearchIndexFactory,
+    pub events: EventPublisherFactory,
+    pub concurrency: usize,
+    pub retention_days: i64,
+}
+
+async fn dependencies() -> Result<Dependencies, Box<dyn std::error::Error + Send + Sync>> {
+    let aws_config = aws_config().await;
+
+    // Create secrets manager
+    let secrets_config = SecretsManagerConfig::from_env()?;
+    let secrets = SecretManager::from_config(&aws_config, secrets_config);
+
+    // Load database credentials
+    let db_pool_config = DatabasePoolCacheConfig::from_env()?;
+
+    // Setup database cache / connector
+    let db = Arc::new(DatabasePoolCache::from_config(
+        db_pool_config,
+        secrets.clone(),
+    ));
+
+    // Create the SQS client
+    // Warning: Will panic if the configuration provided is invalid
+    let sqs_client = SqsClient::new(&aws_config);
+
+    // Setup event publisher factories
+    let sqs_publisher_factory = SqsEventPublisherFactory::new(sqs_client.clone());
+    let events = EventPublisherFactory::new(sqs_publisher_factory);
+
+    // Setup search index factory
+    let search_config = SearchIndexFactoryConfig::from_env()?;
+    let search = SearchIndexFactory::from_config(&aws_config, secrets, db.clone(), search_config)?;
+
+    // Setup storage factory
+    let storage_factory_config = StorageLayerFactoryConfig::from_env()?;
+    let storage = StorageLayerFactory::from_config(&aws_config, storage_factory_config);
+
+    // Number of tenants to purge at once
+    let concurrency = match std::env::var("DOCBOX_TRASH_PURGE_CONCURRENCY") {
+        Ok(value) => value.parse::<usize>()?,
+        Err(_) => DEFAULT_CONCURRENCY,
+    };
+
+    // Number of days items are kept in the trash
+    let retention_days: i64 = match std::env::var("DOCBOX_TRASH_RETENTION_DAYS") {
+        Ok(value) => value.parse::<u32>()?.into(),
+        Err(_) => DEFAULT_RETENTION_DAYS,
+    };
+
+    Ok(Dependencies {
+        db,
+        storage,
+        search,
+        events,
+        concurrency,
+        retention_days,
+    })
+}
+
+/// Optional details provided in the EventBridge event to control the purge,
+/// scheduled events provide an empty detail which purges all tenants
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct PurgeEventDetail {
+    /// Only purge the tenant with this ID
+    pub tenant_id: Option<Uuid>,
+    /// Only purge tenants within this environment
+    pub tenant_env: Option<String>,
+    /// Purge items deleted before this date instead of the retention period
+    pub older_than: Option<DateTime<Utc>>,
+    /// Report what would be purged without deleting anything
+    pub dry_run: bool,
+}
+
+pub(crate) async fn outer_function_handler(
+    event: LambdaEvent<EventBridgeEvent<PurgeEventDetail>>,
+) -> Result<PurgeTrashSummary, Error> {
+    let dependencies = DEPENDENCIES.get_or_try_init(dependencies).await?;
+    function_handler(event, dependencies).await
+}
+
+async fn function_handler(
+    event: LambdaEvent<EventBridgeEvent<PurgeEventDetail>>,
+    dependencies: &Dependencies,
+) -> Result<PurgeTrashSummary, Error> {
+    let detail = event.payload.detail;
+
+    if detail.dry_run {
+        tracing::info!(?detail, "performing dry run of trash purge");
+    }
+
+    let deleted_before = detail
+        .older_than
+        .unwrap_or_else(|| Utc::now() - TimeDelta::days(dependencies.retention_days));
+
+    let deadline = lambda_deadline(event.context.deadline, DEADLINE_MARGIN);
+
+    let options = PurgeTrashOptions {
+        sweep: TenantSweepOptions {
+            concurrency: dependencies.concurrency,
+            deadline: Some(deadline),
+            cancel_at: Some(lambda_deadline(event.context.deadline, CANCEL_MARGIN)),
+            tenant_id: detail.tenant_id,
+            tenant_env: detail.tenant_env,
+        },
+        tenant_options: PurgeTrashTenantOptions {
+            deleted_before,
+            dry_run: detail.dry_run,
+            deadline: Some(deadline),
+        },
+    };
+
+    let summary = match purge_trash(
+        &dependencies.db,
+        &dependencies.storage,
+        &dependencies.search,
+        &dependencies.events,
+        options,
+    )
+    .await
+    {
+        Ok(value) => value,
+        Err(error) => {
+            tracing::error!(?error, "failed to purge trash");
+            return Err(error.into());
+        }
+    };
+
+    tracing::info!(
+        tenants_processed = summary.tenants_processed,
+        tenants_skipped = summary.tenants_skipped,
+        tenants_incomplete = summary.tenants_incomplete,
+        tenants_failed = summary.failures.len(),
+        folders_purged = summary.folders_purged,
+        files_purged = summary.files_purged,
+        links_purged = summary.links_purged,
+        dry_run = summary.dry_run,
+        failures = ?summary.failures,
+        "purged trash"
+    );
+
+    Ok(summary)
+}
+
+/// Determines the time `margin` before the lambda `deadline` (milliseconds
+/// since the unix epoch)
+fn lambda_deadline(deadline: u64, margin: Duration) -> Instant {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let remaining = Duration::from_millis(deadline).saturating_sub(now);
+
+    Instant::now() + remaining.saturating_sub(margin)
+}
@@ -1,24 +1,43 @@
-use std::sync::Arc;
-
+use crate::{
+    events::{UploadCompletionEvent, UploadedObject, parse_message_body},
+    verify::{UploadRejected, UploadVerificationConfig, VerifyUploadError, verify_uploaded_object},
+};
 use ::tracing::Instrument;
-use aws_lambda_events::event::s3::S3Event;
+use aws_lambda_events::event::sqs::{BatchItemFailure, SqsBatchResponse, SqsMessage};
 use docbox_core::{
     aws::{SqsClient, aws_config},
     events::{EventPublisherFactory, sqs::SqsEventPublisherFactory},
     files::upload_file_presigned::{CompletePresigned, safe_complete_presigned},
 };
 use docbox_database::{
-    DatabasePoolCache, DatabasePoolCacheConfig,
-    models::{folder::Folder, presigned_upload_task::PresignedUploadTask, tenant::Tenant},
+    DatabasePoolCache, DatabasePoolCacheConfig, DbPool,
+    models::{
+        document_box::DocumentBoxScopeRaw,
+        folder::Folder,
+        presigned_upload_task::{PresignedTaskStatus, PresignedUploadTask, PresignedUploadTaskId},
+        tenant::Tenant,
+    },
 };
 use docbox_processing::{
-    ProcessingLayer, ProcessingLayerConfig,
+    ProcessingError, ProcessingLayer, ProcessingLayerConfig,
     office::{OfficeConverter, OfficeConverterConfig, OfficeProcessingLayer},
 };
 use docbox_search::{SearchIndexFactory, SearchIndexFactoryConfig};
 use docbox_secrets::{SecretManager, SecretsManagerConfig};
+use docbox_serverless_shared::{
+    checksum::UploadChecksum,
+    database::{
+        file::set_file_checksum,
+        presigned_upload_task::{find_presigned_task_checksum, set_presigned_task_status},
+    },
+    task_lock::PresignedTaskLock,
+};
 use docbox_storage::{StorageLayerFactory, StorageLayerFactoryConfig};
+use futures::future::join_all;
 use lambda_runtime::{Error, LambdaEvent, tracing};
+use serde_json::Value;
+use std::sync::Arc;
+use thiserror::Error;
 use tokio::sync::OnceCell;
 
 static DEPENDENCIES: OnceCell<Dependencies> = OnceCell::const_new();
@@ -29,6 +48,7 @@ pub struct Dependencies {
     pub storage: StorageLayerFactory,
     pub events: EventPublisherFactory,
     pub processing: ProcessingLayer,
+    pub verification: UploadVerificationConfig,
 }
 
 async fn dependencies() -> Result<Dependencies, Box<dyn std::error::Error + Send + Sync>> {
@@ -77,55 +97,179 @@ async fn dependencies() -> Result<Dependencies, Box<dyn std::error::Error + Send
     let storage_factory_config = StorageLayerFactoryConfig::from_env()?;
     let storage = StorageLayerFactory::from_config(&aws_config, storage_factory_config);
 
+    // Load the uploaded file verification config
+    let verification = UploadVerificationConfig::from_env();
+
     Ok(Dependencies {
         db_cache,
         storage,
         processing,
         events,
         search,
+        verification,
     })
 }
 
-pub(crate) async fn outer_function_handler(event: LambdaEvent<S3Event>) -> Result<(), Error> {
+pub(crate) async fn outer_function_handler(
+    event: LambdaEvent<Value>,
+) -> Result<SqsBatchResponse, Error> {
     let dependencies = DEPENDENCIES.get_or_try_init(dependencies).await?;
     function_handler(event, dependencies).await
 }
 
 async fn function_handler(
-    event: LambdaEvent<S3Event>,
+    event: LambdaEvent<Value>,
     dependencies: &Dependencies,
-) -> Result<(), Error> {
-    let (bucket_name, object_key) = match get_object_parts(&event.payload) {
-        Some(value) => value,
-        None => return Ok(()),
+) -> Result<SqsBatchResponse, Error> {
+    let event = UploadCompletionEvent::from_value(event.payload)?;
+
+    match event {
+        UploadCompletionEvent::Sqs(event) => {
+            let results = join_all(
+                event
+                    .records
+                    .into_iter()
+                    .map(|message| handle_message(dependencies, message)),
+            )
+            .await;
+
+            // Report the failed messages so that only they are retried, the
+            // response types are non-exhaustive so they're built from defaults
+            let mut response = SqsBatchResponse::default();
+            response.batch_item_failures = results
+                .into_iter()
+                .filter_map(|result| result.err())
+                .map(|item_identifier| {
+                    let mut failure = BatchItemFailure::default();
+                    failure.item_identifier = item_identifier;
+                    failure
+                })
+                .collect();
+
+            Ok(response)
+        }
+
+        UploadCompletionEvent::Objects(objects) => {
+            // Direct invocations have no partial failure reporting, the whole
+            // event is failed so that it can be retried
+            handle_objects(dependencies, objects).await?;
+            Ok(SqsBatchResponse::default())
+        }
+    }
+}
+
+/// Handles a single SQS message, provides back the message ID as the
+/// error when the message should be retried
+async fn handle_message(data: &Dependencies, message: SqsMessage) -> Result<(), String> {
+    let message_id = message.message_id.unwrap_or_default();
+
+    let objects = match message.body.as_deref().map(parse_message_body) {
+        Some(Ok(value)) => value,
+        Some(Err(error)) => {
+            // Malformed messages will never succeed, don't retry them
+            tracing::error!(?error, %message_id, "invalid upload completion message");
+            return Ok(());
+        }
+        None => {
+            tracing::warn!(%message_id, "upload completion message missing body");
+            return Ok(());
+        }
     };
 
-    handle_file_uploaded(dependencies, bucket_name, object_key).await;
-    Ok(())
+    handle_objects(data, objects).await.map_err(|error| {
+        tracing::error!(?error, %message_id, "failed to handle upload completion message");
+        message_id
+    })
 }
 
-fn get_object_parts(payload: &S3Event) -> Option<(String, String)> {
-    let record = payload.records.first()?;
-    let bucket = &record.s3.bucket;
-    let object = &record.s3.object;
+/// Handles all the uploaded `objects` concurrently, fails if any of
+/// the objects failed
+async fn handle_objects(
+    data: &Dependencies,
+    objects: Vec<UploadedObject>,
+) -> Result<(), UploadCompletionError> {
+    let results = join_all(objects.into_iter().map(|object| {
+        handle_file_uploaded(data, object.bucket_name, object.object_key, object.size)
+    }))
+    .await;
+
+    results.into_iter().collect()
+}
+
+#[derive(Debug, Error)]
+pub enum UploadCompletionError {
+    #[error("failed to connect to database")]
+    ConnectDatabase,
+
+    #[error("failed to query tenant")]
+    QueryTenant,
+
+    #[error("failed to query presigned upload")]
+    QueryTask,
+
+    #[error("failed to query folder")]
+    QueryFolder,
+
+    #[error("failed to claim presigned upload")]
+    LockTask,
+
+    #[error("presigned upload folder no longer exists")]
+    UnknownFolder,
+
+    #[error("failed to verify uploaded file")]
+    VerifyObject,
+
+    #[error(transparent)]
+    Rejected(UploadRejected),
+
+    #[error("failed to process uploaded file")]
+    ProcessFile,
+
+    #[error("presigned upload has an invalid mime type")]
+    InvalidMime,
 
-    let bucket_name = bucket.name.as_ref()?.clone();
-    let object_key = object.key.as_ref()?.clone();
+    #[error("failed to complete presigned upload")]
+    CompletePresigned,
 
-    Some((bucket_name, object_key))
+    #[error("failed to update presigned upload")]
+    UpdateTask,
+}
+
+impl UploadCompletionError {
+    /// Whether the error is transient and the upload completion should be
+    /// retried, permanent errors will never succeed and are instead reported
+    /// by failing the presigned upload task
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            UploadCompletionError::UnknownFolder
+            | UploadCompletionError::Rejected(_)
+            | UploadCompletionError::ProcessFile
+            | UploadCompletionError::InvalidMime => false,
+            UploadCompletionError::ConnectDatabase
+            | UploadCompletionError::QueryTenant
+            | UploadCompletionError::QueryTask
+            | UploadCompletionError::LockTask
+            | UploadCompletionError::QueryFolder
+            | UploadCompletionError::VerifyObject
+            | UploadCompletionError::CompletePresigned
+            | UploadCompletionError::UpdateTask => true,
+        }
+    }
 }
 
 /// Handle file upload notifications
 #[tracing::instrument(skip(data))]
-pub async fn handle_file_uploaded(data: &Dependencies, bucket_name: String, object_key: String) {
+pub async fn handle_file_uploaded(
+    data: &Dependencies,
+    bucket_name: String,
+    object_key: String,
+    object_size: Option<i64>,
+) -> Result<(), UploadCompletionError> {
     let tenant = {
-        let db = match data.db_cache.get_root_pool().await {
-            Ok(value) => value,
-            Err(error) => {
-                tracing::error!(?error, "failed to acquire root database pool");
-                return;
-            }
-        };
+        let db = data.db_cache.get_root_pool().await.map_err(|error| {
+            tracing::error!(?error, "failed to acquire root database pool");
+            UploadCompletionError::ConnectDatabase
+        })?;
 
         match Tenant::find_by_bucket(&db, &bucket_name).await {
             Ok(Some(value)) => value,
@@ -133,11 +277,11 @@ pub async fn handle_file_uploaded(data: &Dependencies, bucket_name: String, obje
                 tracing::warn!(
                     "file was uploaded into a bucket sqs is listening to but there was no matching tenant"
                 );
-                return;
+                return Ok(());
             }
             Err(error) => {
                 tracing::error!(?error, "failed to query tenant for bucket");
-                return;
+                return Err(UploadCompletionError::QueryTenant);
             }
         }
     };
@@ -145,9 +289,9 @@ pub async fn handle_file_uploaded(data: &Dependencies, bucket_name: String, obje
     // Provide a span that contains the tenant metadata
     let span = tracing::info_span!("tenant", tenant_id = %tenant.id, tenant_env = %tenant.env);
 
-    handle_file_uploaded_tenant(tenant, data, bucket_name, object_key)
+    handle_file_uploaded_tenant(tenant, data, object_key, object_size)
         .instrument(span)
-        .await;
+        .await
 }
 
 /// Handle file upload notification once the tenant has been identified
@@ -155,67 +299,217 @@ pub async fn handle_file_uploaded(data: &Dependencies, bucket_name: String, obje
 pub async fn handle_file_uploaded_tenant(
     tenant: Tenant,
     data: &Dependencies,
-    bucket_name: String,
     object_key: String,
-) {
-    let object_key = match urlencoding::decode(&object_key) {
-        Ok(value) => value.to_string(),
-        Err(error) => {
-            tracing::warn!(
-                ?error,
-                "file was uploaded into a bucket but had an invalid file name"
-            );
-            return;
-        }
-    };
-
-    let db = match data.db_cache.get_tenant_pool(&tenant).await {
-        Ok(value) => value,
-        Err(error) => {
+    object_size: Option<i64>,
+) -> Result<(), UploadCompletionError> {
+    let db = data
+        .db_cache
+        .get_tenant_pool(&tenant)
+        .await
+        .map_err(|error| {
             tracing::error!(?error, "failed to get tenant database pool");
-            return;
-        }
-    };
+            UploadCompletionError::ConnectDatabase
+        })?;
 
-    // Locate a pending upload task for the uploaded file
+    // Locate a pending upload task for the uploaded file, multipart uploads only create
+    // the object once the upload is completed so they are handled like any other upload
     let task = match PresignedUploadTask::find_by_file_key(&db, &object_key).await {
         Ok(Some(task)) => task,
         // Ignore files that aren't attached to a presigned upload task
         // (Things like generated files will show up here)
         Ok(None) => {
             tracing::debug!("uploaded file was not a presigned upload");
-            return;
+            return Ok(());
         }
         Err(error) => {
             tracing::error!(?error, "unable to query presigned upload");
-            return;
+            return Err(UploadCompletionError::QueryTask);
         }
     };
 
-    let scope = task.document_box.clone();
+    // Duplicate notification for an upload that has already been completed
+    if !matches!(task.status, PresignedTaskStatus::Pending) {
+        tracing::debug!("presigned upload has already been completed");
+        return Ok(());
+    }
 
-    // Retrieve the target folder
-    let folder = match Folder::find_by_id(&db, &scope, task.folder_id).await {
+    let lock = match PresignedTaskLock::try_acquire(&db, task.id).await {
         Ok(Some(value)) => value,
+        // Another notification for the same upload is already completing the upload
         Ok(None) => {
-            tracing::error!("presigned upload folder no longer exists");
-            return;
+            tracing::debug!("presigned upload is already being completed");
+            return Ok(());
         }
         Err(error) => {
-            tracing::error!(?error, "unable to query folder");
+            tracing::error!(?error, "failed to claim presigned upload");
+            return Err(UploadCompletionError::LockTask);
+        }
+    };
+
+    let result = handle_locked_task(&tenant, data, db.clone(), &object_key, object_size).await;
+
+    lock.release(&db).await;
+
+    result
+}
+
+/// Handle completing the presigned task for `object_key` while
+/// holding the claim for the task
+async fn handle_locked_task(
+    tenant: &Tenant,
+    data: &Dependencies,
+    db: DbPool,
+    object_key: &str,
+    object_size: Option<i64>,
+) -> Result<(), UploadCompletionError> {
+    // Query the task again now that the claim is held, a concurrent
+    // completion may have completed the task before the claim was taken
+    let task = PresignedUploadTask::find_by_file_key(&db, object_key)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "unable to query presigned upload");
+            UploadCompletionError::QueryTask
+        })?;
+
+    let task = match task {
+        Some(task) if matches!(task.status, PresignedTaskStatus::Pending) => task,
+        _ => {
+            tracing::debug!("presigned upload has already been completed");
+            return Ok(());
+        }
+    };
+
+    let task_id = task.id;
+
+    match verify_and_complete_task(tenant, data, db.clone(), task, object_size).await {
+        // Permanent failures are reported through the task status
+        Err(error) if !error.is_retryable() => {
+            tracing::warn!(?error, "presigned upload failed permanently");
+
+            // Rejected uploads are removed from storage
+            if matches!(error, UploadCompletionError::Rejected(_)) {
+                let storage = data.storage.create_storage_layer(tenant);
+                if let Err(error) = storage.delete_file(object_key).await {
+                    tracing::error!(?error, "failed to delete rejected upload from storage");
+                }
+            }
+
+            set_presigned_task_status(
+                &db,
+                task_id,
+                PresignedTaskStatus::Failed {
+                    error: error.to_string(),
+                },
+            )
+            .await
+            .map_err(|error| {
+                tracing::error!(?error, "failed to mark presigned upload as failed");
+                UploadCompletionError::UpdateTask
+            })?;
+
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Verify the uploaded object matches the presigned upload `task` then
+/// complete the upload
+async fn verify_and_complete_task(
+    tenant: &Tenant,
+    data: &Dependencies,
+    db: DbPool,
+    task: PresignedUploadTask,
+    object_size: Option<i64>,
+) -> Result<(), UploadCompletionError> {
+    let storage = data.storage.create_storage_layer(tenant);
+
+    let checksum = find_presigned_task_checksum(&db, task.id)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to query presigned upload checksum");
+            UploadCompletionError::QueryTask
+        })?;
+
+    verify_uploaded_object(
+        &data.verification,
+        &storage,
+        &task,
+        checksum.as_ref(),
+        object_size,
+    )
+    .await
+    .map_err(|error| match error {
+        VerifyUploadError::Rejected(reason) => UploadCompletionError::Rejected(reason),
+        VerifyUploadError::ReadObject => UploadCompletionError::VerifyObject,
+    })?;
+
+    let scope = task.document_box.clone();
+    let task_id = task.id;
+
+    complete_presigned_task(tenant, data, db.clone(), task).await?;
+
+    if let Some(checksum) = checksum {
+        store_file_checksum(&db, &scope, task_id, &checksum).await;
+    }
+
+    Ok(())
+}
+
+/// Stores the verified `checksum` on the file created by completing the
+/// presigned upload `task_id`. The upload is already complete at this point
+/// so failures are only logged rather than failing the upload
+async fn store_file_checksum(
+    db: &DbPool,
+    scope: &DocumentBoxScopeRaw,
+    task_id: PresignedUploadTaskId,
+    checksum: &UploadChecksum,
+) {
+    let task = match PresignedUploadTask::find(db, scope, task_id).await {
+        Ok(Some(task)) => task,
+        Ok(None) => return,
+        Err(error) => {
+            tracing::error!(?error, "failed to query completed presigned upload");
             return;
         }
     };
 
+    let PresignedTaskStatus::Completed { file_id } = task.status else {
+        return;
+    };
+
+    if let Err(error) = set_file_checksum(db, file_id, checksum).await {
+        tracing::error!(?error, %file_id, "failed to store file checksum");
+    }
+}
+
+/// Complete the presigned upload `task`
+async fn complete_presigned_task(
+    tenant: &Tenant,
+    data: &Dependencies,
+    db: DbPool,
+    task: PresignedUploadTask,
+) -> Result<(), UploadCompletionError> {
+    let scope = task.document_box.clone();
+
+    // Retrieve the target folder
+    let folder = Folder::find_by_id(&db, &scope, task.folder_id)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "unable to query folder");
+            UploadCompletionError::QueryFolder
+        })?
+        .ok_or(UploadCompletionError::UnknownFolder)?;
+
     // Update stored editing user data
     let complete = CompletePresigned { task, folder };
 
-    let search = data.search.create_search_index(&tenant);
-    let storage = data.storage.create_storage_layer(&tenant);
-    let events = data.events.create_event_publisher(&tenant);
+    let search = data.search.create_search_index(tenant);
+    let storage = data.storage.create_storage_layer(tenant);
+    let events = data.events.create_event_publisher(tenant);
 
     // Create task future that performs the file upload
-    if let Err(error) = safe_complete_presigned(
+    safe_complete_presigned(
         db,
         search,
         storage,
@@ -224,7 +518,31 @@ pub async fn handle_file_uploaded_tenant(
         complete,
     )
     .await
-    {
+    .map_err(|error| {
         tracing::error!(?error, "failed to complete presigned file upload");
+        classify_complete_error(&error)
+    })?;
+
+    Ok(())
+}
+
+/// Classifies an error from completing a presigned upload by its causes, files that
+/// fail to process or fail validation will fail the same way on every attempt so
+/// they are permanent failures, anything else is treated as transient
+fn classify_complete_error(error: &(dyn std::error::Error + 'static)) -> UploadCompletionError {
+    let mut cause = Some(error);
+
+    while let Some(error) = cause {
+        if error.is::<ProcessingError>() {
+            return UploadCompletionError::ProcessFile;
+        }
+
+        if error.is::<mime::FromStrError>() {
+            return UploadCompletionError::InvalidMime;
+        }
+
+        cause = error.source();
     }
+
+    UploadCompletionError::CompletePresigned
 }
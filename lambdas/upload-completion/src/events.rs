@@ -0,0 +1,347 @@
+//! Parsing for the different event shapes that can notify the lambda of an
+//! uploaded object:
+//! - SQS batches containing S3 notification (or EventBridge) message bodies
+//! - Direct S3 notifications
+//! - EventBridge S3 "Object Created" events
+
+use aws_lambda_events::event::{s3::S3Event, sqs::SqsEvent};
+use serde::Deserialize;
+use serde_json::Value;
+use thiserror::Error;
+
+/// Event received by the lambda
+pub enum UploadCompletionEvent {
+    /// Batch of SQS messages
+    Sqs(SqsEvent),
+    /// Objects created directly from an S3 notification or EventBridge event
+    Objects(Vec<UploadedObject>),
+}
+
+/// Object that was uploaded to S3
+#[derive(Debug, Clone)]
+pub struct UploadedObject {
+    /// Name of the bucket the object was uploaded to
+    pub bucket_name: String,
+    /// Key of the uploaded object (URL decoded)
+    pub object_key: String,
+    /// Size of the uploaded object in bytes when provided by the event
+    pub size: Option<i64>,
+}
+
+#[derive(Debug, Error)]
+pub enum ParseEventError {
+    #[error("unknown event type")]
+    UnknownEvent,
+
+    #[error("object key was not valid url encoding")]
+    InvalidObjectKey,
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// EventBridge event with S3 "Object Created" details
+#[derive(Deserialize)]
+struct EventBridgeObjectCreated {
+    detail: EventBridgeObjectCreatedDetail,
+}
+
+#[derive(Deserialize)]
+struct EventBridgeObjectCreatedDetail {
+    bucket: EventBridgeBucket,
+    object: EventBridgeObject,
+}
+
+#[derive(Deserialize)]
+struct EventBridgeBucket {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct EventBridgeObject {
+    key: String,
+    #[serde(default)]
+    size: Option<i64>,
+}
+
+impl UploadCompletionEvent {
+    /// Parse the event from the raw lambda payload
+    pub fn from_value(value: Value) -> Result<UploadCompletionEvent, ParseEventError> {
+        // SQS batches and S3 notifications are both a collection of "Records"
+        if let Some(records) = value.get("Records").and_then(Value::as_array) {
+            let event_source = records
+                .first()
+                .and_then(|record| record.get("eventSource"))
+                .and_then(Value::as_str);
+
+            return match event_source {
+                Some("aws:sqs") => Ok(UploadCompletionEvent::Sqs(serde_json::from_value(value)?)),
+                _ => Ok(UploadCompletionEvent::Objects(parse_s3_event(value)?)),
+            };
+        }
+
+        if is_eventbridge_object_created(&value) {
+            let event: EventBridgeObjectCreated = serde_json::from_value(value)?;
+            return Ok(UploadCompletionEvent::Objects(vec![UploadedObject {
+                bucket_name: event.detail.bucket.name,
+                object_key: event.detail.object.key,
+                size: event.detail.object.size,
+            }]));
+        }
+
+        Err(ParseEventError::UnknownEvent)
+    }
+}
+
+/// Parse the uploaded objects from the body of an SQS message, SQS messages can
+/// contain either S3 notifications or EventBridge events
+pub fn parse_message_body(body: &str) -> Result<Vec<UploadedObject>, ParseEventError> {
+    let value: Value = serde_json::from_str(body)?;
+
+    // S3 sends a test event when configuring notifications, these contain no records
+    if value.get("Event").and_then(Value::as_str) == Some("s3:TestEvent") {
+        return Ok(Vec::new());
+    }
+
+    match UploadCompletionEvent::from_value(value)? {
+        UploadCompletionEvent::Objects(objects) => Ok(objects),
+        // Nested SQS events are not expected
+        UploadCompletionEvent::Sqs(_) => Err(ParseEventError::UnknownEvent),
+    }
+}
+
+fn is_eventbridge_object_created(value: &Value) -> bool {
+    value.get("source").and_then(Value::as_str) == Some("aws.s3")
+        && value.get("detail-type").and_then(Value::as_str) == Some("Object Created")
+}
+
+fn parse_s3_event(value: Value) -> Result<Vec<UploadedObject>, ParseEventError> {
+    let event: S3Event = serde_json::from_value(value)?;
+
+    event
+        .records
+        .into_iter()
+        .filter_map(|record| {
+            let bucket_name = record.s3.bucket.name?;
+            let object_key = record.s3.object.key?;
+            Some((bucket_name, object_key, record.s3.object.size))
+        })
+        .map(|(bucket_name, object_key, size)| {
+            Ok(UploadedObject {
+                bucket_name,
+                object_key: decode_object_key(&object_key)?,
+                size,
+            })
+        })
+        .collect()
+}
+
+/// Decodes an object key from an S3 notification, keys are form URL encoded
+/// so spaces are encoded as "+"
+fn decode_object_key(object_key: &str) -> Result<String, ParseEventError> {
+    let object_key = object_key.replace('+', " ");
+    urlencoding::decode(&object_key)
+        .map(|value| value.into_owned())
+        .map_err(|_| ParseEventError::InvalidObjectKey)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ParseEventError, UploadCompletionEvent, parse_message_body};
+    use serde_json::Value;
+
+    /// S3 "ObjectCreated:Put" notification (from the S3 event message structure docs)
+    const S3_NOTIFICATION: &str = r#"{
+        "Records": [
+            {
+                "eventVersion": "2.1",
+                "eventSource": "aws:s3",
+                "awsRegion": "us-west-2",
+                "eventTime": "1970-01-01T00:00:00.000Z",
+                "eventName": "ObjectCreated:Put",
+                "userIdentity": { "principalId": "AIDAJDPLRKLG7UEXAMPLE" },
+                "requestParameters": { "sourceIPAddress": "127.0.0.1" },
+                "responseElements": {
+                    "x-amz-request-id": "C3D13FE58DE4C810",
+                    "x-amz-id-2": "FMyUVURIY8/IgAtTv8xRjskZQpcIZ9KG4V5Wp6S7S/JRWeUWerMUE5JgHvANOjpD"
+                },
+                "s3": {
+                    "s3SchemaVersion": "1.0",
+                    "configurationId": "testConfigRule",
+                    "bucket": {
+                        "name": "docbox-tenant-bucket",
+                        "ownerIdentity": { "principalId": "A3NL1KOZZKExample" },
+                        "arn": "arn:aws:s3:::docbox-tenant-bucket"
+                    },
+                    "object": {
+                        "key": "my-scope/2f5c4a1e_annual+report%282024%29.pdf",
+                        "size": 1024,
+                        "eTag": "d41d8cd98f00b204e9800998ecf8427e",
+                        "sequencer": "0055AED6DCD90281E5"
+                    }
+                }
+            }
+        ]
+    }"#;
+
+    /// EventBridge S3 "Object Created" event (from the EventBridge S3 event docs)
+    const EVENTBRIDGE_OBJECT_CREATED: &str = r#"{
+        "version": "0",
+        "id": "17793124-05d4-b198-2fde-7ededc63b103",
+        "detail-type": "Object Created",
+        "source": "aws.s3",
+        "account": "111122223333",
+        "time": "2021-11-12T00:00:00Z",
+        "region": "ca-central-1",
+        "resources": ["arn:aws:s3:::docbox-tenant-bucket"],
+        "detail": {
+            "version": "0",
+            "bucket": { "name": "docbox-tenant-bucket" },
+            "object": {
+                "key": "my-scope/2f5c4a1e_report.pdf",
+                "size": 5,
+                "etag": "b1946ac92492d2347c6235b4d2611184",
+                "sequencer": "617f08299329d189"
+            },
+            "request-id": "N4N7GDK58NMKJ12R",
+            "requester": "123456789012",
+            "source-ip-address": "1.2.3.4",
+            "reason": "PutObject"
+        }
+    }"#;
+
+    /// SQS batch event wrapping the provided message `body`
+    fn sqs_event(body: &str) -> Value {
+        serde_json::json!({
+            "Records": [
+                {
+                    "messageId": "059f36b4-87a3-44ab-83d2-661975830a7d",
+                    "receiptHandle": "AQEBwJnKyrHigUMZj6rYigCgxlaS3SLy0a",
+                    "body": body,
+                    "attributes": {
+                        "ApproximateReceiveCount": "1",
+                        "SentTimestamp": "1545082649183",
+                        "SenderId": "AIDAIENQZJOLO23YVJ4VO",
+                        "ApproximateFirstReceiveTimestamp": "1545082649185"
+                    },
+                    "messageAttributes": {},
+                    "md5OfBody": "e4e68fb7bd0e697a0ae8f1bb342846b3",
+                    "eventSource": "aws:sqs",
+                    "eventSourceARN": "arn:aws:sqs:us-east-2:123456789012:docbox-upload-completion",
+                    "awsRegion": "us-east-2"
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn parses_sqs_batch_of_s3_notifications() {
+        let event = UploadCompletionEvent::from_value(sqs_event(S3_NOTIFICATION)).unwrap();
+
+        let UploadCompletionEvent::Sqs(event) = event else {
+            panic!("expected an sqs event");
+        };
+
+        assert_eq!(event.records.len(), 1);
+
+        let body = event.records[0].body.as_deref().unwrap();
+        let objects = parse_message_body(body).unwrap();
+
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].bucket_name, "docbox-tenant-bucket");
+        assert_eq!(
+            objects[0].object_key,
+            "my-scope/2f5c4a1e_annual report(2024).pdf"
+        );
+        assert_eq!(objects[0].size, Some(1024));
+    }
+
+    #[test]
+    fn parses_sqs_message_containing_eventbridge_event() {
+        let objects = parse_message_body(EVENTBRIDGE_OBJECT_CREATED).unwrap();
+
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].bucket_name, "docbox-tenant-bucket");
+        assert_eq!(objects[0].object_key, "my-scope/2f5c4a1e_report.pdf");
+        assert_eq!(objects[0].size, Some(5));
+    }
+
+    #[test]
+    fn parses_direct_s3_notification() {
+        let value: Value = serde_json::from_str(S3_NOTIFICATION).unwrap();
+        let event = UploadCompletionEvent::from_value(value).unwrap();
+
+        let UploadCompletionEvent::Objects(objects) = event else {
+            panic!("expected uploaded objects");
+        };
+
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].bucket_name, "docbox-tenant-bucket");
+        assert_eq!(
+            objects[0].object_key,
+            "my-scope/2f5c4a1e_annual report(2024).pdf"
+        );
+        assert_eq!(objects[0].size, Some(1024));
+    }
+
+    #[test]
+    fn parses_eventbridge_object_created() {
+        let value: Value = serde_json::from_str(EVENTBRIDGE_OBJECT_CREATED).unwrap();
+        let event = UploadCompletionEvent::from_value(value).unwrap();
+
+        let UploadCompletionEvent::Objects(objects) = event else {
+            panic!("expected uploaded objects");
+        };
+
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].bucket_name, "docbox-tenant-bucket");
+        assert_eq!(objects[0].object_key, "my-scope/2f5c4a1e_report.pdf");
+        assert_eq!(objects[0].size, Some(5));
+    }
+
+    #[test]
+    fn s3_test_event_has_no_objects() {
+        let body = r#"{
+            "Service": "Amazon S3",
+            "Event": "s3:TestEvent",
+            "Time": "2024-01-01T00:00:00.000Z",
+            "Bucket": "docbox-tenant-bucket",
+            "RequestId": "5582815E1AEA5ADF",
+            "HostId": "8cLeGAmw098X5cv4Zkwcmo8vvZa3eH3eKxsPzbB9wrR+YstdA6Knx4Ip8EXAMPLE"
+        }"#;
+
+        assert!(parse_message_body(body).unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_s3_record_is_rejected() {
+        // Record claiming to be from S3 without the S3 entity
+        let value = serde_json::json!({
+            "Records": [
+                {
+                    "eventSource": "aws:s3",
+                    "eventName": "ObjectCreated:Put",
+                    "s3": "not an object"
+                }
+            ]
+        });
+
+        assert!(matches!(
+            UploadCompletionEvent::from_value(value),
+            Err(ParseEventError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn malformed_message_body_is_rejected() {
+        assert!(matches!(
+            parse_message_body("not json"),
+            Err(ParseEventError::Json(_))
+        ));
+
+        assert!(matches!(
+            parse_message_body(r#"{ "unexpected": true }"#),
+            Err(ParseEventError::UnknownEvent)
+        ));
+    }
+}
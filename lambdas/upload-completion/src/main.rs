@@ -1,6 +1,10 @@
+#![recursion_limit = "256"]
+
 use lambda_runtime::{Error, run, service_fn, tracing};
 
 mod event_handler;
+mod events;
+mod verify;
 
 use crate::event_handler::outer_function_handler;
 
@@ -0,0 +1,344 @@
+//! Verification that an uploaded object matches the presigned upload
+//! it was uploaded for
+
+use docbox_database::models::presigned_upload_task::PresignedUploadTask;
+use docbox_serverless_shared::checksum::{ChecksumHasher, UploadChecksum};
+use docbox_storage::TenantStorageLayer;
+use futures::{Stream, TryStreamExt};
+use lambda_runtime::tracing;
+use std::fmt::Debug;
+use thiserror::Error;
+
+/// Number of bytes from the start of the object used to sniff the mime type
+const SNIFF_LENGTH: usize = 8192;
+
+/// Configuration for verifying uploaded objects
+#[derive(Debug, Default, Clone)]
+pub struct UploadVerificationConfig {
+    /// Mime types that are allowed to be uploaded, when empty all
+    /// mime types not present in `denied_mime_types` are allowed
+    pub allowed_mime_types: Vec<String>,
+    /// Mime types that are not allowed to be uploaded
+    pub denied_mime_types: Vec<String>,
+}
+
+impl UploadVerificationConfig {
+    /// Load the configuration from the environment
+    ///
+    /// Both lists are comma separated and support wildcard subtypes (i.e image/*)
+    pub fn from_env() -> UploadVerificationConfig {
+        UploadVerificationConfig {
+            allowed_mime_types: mime_list_from_env("DOCBOX_UPLOAD_ALLOWED_MIME_TYPES"),
+            denied_mime_types: mime_list_from_env("DOCBOX_UPLOAD_DENIED_MIME_TYPES"),
+        }
+    }
+
+    /// Whether mime type checking is enabled
+    fn checks_mime(&self) -> bool {
+        !self.allowed_mime_types.is_empty() || !self.denied_mime_types.is_empty()
+    }
+
+    /// Checks if the `mime` type is allowed to be uploaded
+    fn is_mime_allowed(&self, mime: &str) -> bool {
+        if self
+            .denied_mime_types
+            .iter()
+            .any(|pattern| mime_matches(pattern, mime))
+        {
+            return false;
+        }
+
+        self.allowed_mime_types.is_empty()
+            || self
+                .allowed_mime_types
+                .iter()
+                .any(|pattern| mime_matches(pattern, mime))
+    }
+}
+
+fn mime_list_from_env(key: &str) -> Vec<String> {
+    std::env::var(key)
+        .map(|value| {
+            value
+                .split(',')
+                .map(|mime| mime.trim().to_ascii_lowercase())
+                .filter(|mime| !mime.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Checks if `mime` matches the `pattern`, patterns can be an exact
+/// mime type or a wildcard subtype (i.e image/*)
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    let mime = mime.to_ascii_lowercase();
+
+    match pattern.strip_suffix("/*") {
+        Some(ty) => mime.split('/').next() == Some(ty),
+        None => pattern == mime,
+    }
+}
+
+/// Reason an uploaded object was rejected
+#[derive(Debug, Error)]
+pub enum UploadRejected {
+    #[error("uploaded file size {actual} does not match the declared size {expected}")]
+    SizeMismatch { expected: i64, actual: i64 },
+
+    #[error("file type {0} is not allowed")]
+    DisallowedMimeType(String),
+
+    #[error("uploaded file does not match the provided checksum")]
+    ChecksumMismatch,
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyUploadError {
+    #[error(transparent)]
+    Rejected(UploadRejected),
+
+    #[error("failed to read uploaded file")]
+    ReadObject,
+}
+
+/// Verifies the uploaded object matches the size, type and checksum
+/// declared when the presigned upload was created
+///
+/// `object_size` is the size reported by the upload event, when not
+/// available the size is determined by reading the object. `checksum`
+/// is the checksum stored for the task when it was created
+pub async fn verify_uploaded_object(
+    config: &UploadVerificationConfig,
+    storage: &TenantStorageLayer,
+    task: &PresignedUploadTask,
+    checksum: Option<&UploadChecksum>,
+    object_size: Option<i64>,
+) -> Result<(), VerifyUploadError> {
+    let expected = i64::from(task.size);
+
+    if let Some(actual) = object_size
+        && actual != expected
+    {
+        return Err(VerifyUploadError::Rejected(UploadRejected::SizeMismatch {
+            expected,
+            actual,
+        }));
+    }
+
+    // Declared type is checked before reading the object
+    if config.checks_mime() && !config.is_mime_allowed(&task.mime) {
+        return Err(VerifyUploadError::Rejected(
+            UploadRejected::DisallowedMimeType(task.mime.clone()),
+        ));
+    }
+
+    // Size is known and there is no need to read the content
+    if object_size.is_some() && !config.checks_mime() && checksum.is_none() {
+        return Ok(());
+    }
+
+    // The whole object must be read to determine the size or compute the checksum
+    let hasher = checksum.map(|checksum| ChecksumHasher::new(checksum.algorithm));
+    let read_all = object_size.is_none() || hasher.is_some();
+
+    let object = read_object(storage, &task.file_key, read_all, hasher).await?;
+
+    if object_size.is_none() && object.size != expected {
+        return Err(VerifyUploadError::Rejected(UploadRejected::SizeMismatch {
+            expected,
+            actual: object.size,
+        }));
+    }
+
+    if let (Some(checksum), Some(actual)) = (checksum, object.checksum)
+        && checksum.value != actual
+    {
+        return Err(VerifyUploadError::Rejected(
+            UploadRejected::ChecksumMismatch,
+        ));
+    }
+
+    // Only the sniffed type is checked against the lists, sniffed types are not
+    // compared against the declared type as container formats (i.e docx and zip)
+    // are detected as their container
+    if config.checks_mime()
+        && let Some(sniffed) = infer::get(&object.head)
+        && !config.is_mime_allowed(sniffed.mime_type())
+    {
+        return Err(VerifyUploadError::Rejected(
+            UploadRejected::DisallowedMimeType(sniffed.mime_type().to_string()),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Contents read from an uploaded object
+struct ReadObject {
+    /// Start of the object for sniffing the mime type
+    head: Vec<u8>,
+    /// Number of bytes read from the object
+    size: i64,
+    /// Checksum of the object when a hasher was provided
+    checksum: Option<String>,
+}
+
+/// Reads the start of the object for sniffing the mime type, when `read_all`
+/// is true the whole object is read to determine its size and checksum
+async fn read_object(
+    storage: &TenantStorageLayer,
+    file_key: &str,
+    read_all: bool,
+    hasher: Option<ChecksumHasher>,
+) -> Result<ReadObject, VerifyUploadError> {
+    let byte_stream = storage.get_file(file_key).await.map_err(|error| {
+        tracing::error!(?error, "failed to get uploaded file from storage");
+        VerifyUploadError::ReadObject
+    })?;
+
+    read_object_stream(byte_stream, read_all, hasher).await
+}
+
+/// Reads the object from its `byte_stream`, the checksum is computed as each chunk
+/// is read so only the start of the object used for sniffing is kept in memory
+async fn read_object_stream<S, B, E>(
+    mut byte_stream: S,
+    read_all: bool,
+    mut hasher: Option<ChecksumHasher>,
+) -> Result<ReadObject, VerifyUploadError>
+where
+    S: Stream<Item = Result<B, E>> + Unpin,
+    B: AsRef<[u8]>,
+    E: Debug,
+{
+    let mut head = Vec::with_capacity(SNIFF_LENGTH);
+    let mut size: i64 = 0;
+
+    while let Some(chunk) = byte_stream.try_next().await.map_err(|error| {
+        tracing::error!(?error, "failed to read uploaded file from storage");
+        VerifyUploadError::ReadObject
+    })? {
+        let chunk = chunk.as_ref();
+        size += chunk.len() as i64;
+
+        if let Some(hasher) = &mut hasher {
+            hasher.update(chunk);
+        }
+
+        if head.len() < SNIFF_LENGTH {
+            let length = (SNIFF_LENGTH - head.len()).min(chunk.len());
+            head.extend_from_slice(&chunk[..length]);
+        } else if !read_all {
+            break;
+        }
+    }
+
+    Ok(ReadObject {
+        head,
+        size,
+        checksum: hasher.map(ChecksumHasher::finalize),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ChecksumHasher, SNIFF_LENGTH, UploadVerificationConfig, mime_matches, read_object_stream,
+    };
+    use docbox_serverless_shared::checksum::ChecksumAlgorithm;
+    use futures::stream;
+    use std::convert::Infallible;
+
+    fn verification_config(allowed: &[&str], denied: &[&str]) -> UploadVerificationConfig {
+        UploadVerificationConfig {
+            allowed_mime_types: allowed.iter().map(|mime| mime.to_string()).collect(),
+            denied_mime_types: denied.iter().map(|mime| mime.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn mime_matches_exact_types() {
+        assert!(mime_matches("application/pdf", "application/pdf"));
+        assert!(mime_matches("application/pdf", "Application/PDF"));
+        assert!(!mime_matches("application/pdf", "application/zip"));
+    }
+
+    #[test]
+    fn mime_matches_wildcard_subtypes() {
+        assert!(mime_matches("image/*", "image/png"));
+        assert!(mime_matches("image/*", "IMAGE/JPEG"));
+        assert!(!mime_matches("image/*", "application/png"));
+        // Wildcards only match the full top level type
+        assert!(!mime_matches("image/*", "imagex/png"));
+    }
+
+    #[test]
+    fn empty_lists_allow_everything() {
+        let config = verification_config(&[], &[]);
+        assert!(!config.checks_mime());
+        assert!(config.is_mime_allowed("application/x-msdownload"));
+    }
+
+    #[test]
+    fn allow_list_restricts_types() {
+        let config = verification_config(&["image/*", "application/pdf"], &[]);
+        assert!(config.is_mime_allowed("image/png"));
+        assert!(config.is_mime_allowed("application/pdf"));
+        assert!(!config.is_mime_allowed("application/zip"));
+    }
+
+    #[test]
+    fn deny_list_takes_precedence_over_allow_list() {
+        let config = verification_config(&["image/*"], &["image/svg+xml"]);
+        assert!(config.is_mime_allowed("image/png"));
+        assert!(!config.is_mime_allowed("image/svg+xml"));
+
+        // Denied types are rejected even when allowed explicitly
+        let config = verification_config(&["application/pdf"], &["application/*"]);
+        assert!(!config.is_mime_allowed("application/pdf"));
+    }
+
+    #[test]
+    fn deny_list_without_allow_list() {
+        let config = verification_config(&[], &["application/x-msdownload"]);
+        assert!(config.is_mime_allowed("application/pdf"));
+        assert!(!config.is_mime_allowed("application/x-msdownload"));
+    }
+
+    #[tokio::test]
+    async fn reads_checksum_across_chunks_keeping_only_the_head() {
+        let contents: Vec<u8> = (0..(SNIFF_LENGTH * 3)).map(|value| value as u8).collect();
+        let chunks = contents
+            .chunks(1000)
+            .map(|chunk| Ok::<_, Infallible>(chunk.to_vec()))
+            .collect::<Vec<_>>();
+
+        let mut expected = ChecksumHasher::new(ChecksumAlgorithm::Sha256);
+        expected.update(&contents);
+
+        let object = read_object_stream(
+            stream::iter(chunks),
+            true,
+            Some(ChecksumHasher::new(ChecksumAlgorithm::Sha256)),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(object.size, contents.len() as i64);
+        assert_eq!(object.head, contents[..SNIFF_LENGTH]);
+        assert_eq!(object.checksum, Some(expected.finalize()));
+    }
+
+    #[tokio::test]
+    async fn stops_reading_after_the_head_when_not_reading_all() {
+        let chunks = (0..10).map(|_| Ok::<_, Infallible>(vec![0u8; SNIFF_LENGTH]));
+
+        let object = read_object_stream(stream::iter(chunks), false, None)
+            .await
+            .unwrap();
+
+        assert_eq!(object.head.len(), SNIFF_LENGTH);
+        assert!(object.size < (SNIFF_LENGTH * 10) as i64);
+        assert_eq!(object.checksum, None);
+    }
+}
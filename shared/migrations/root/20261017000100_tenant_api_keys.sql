@@ -0,0 +1,13 @@
+-- API keys that can only act on a single tenant, stored alongside the tenants
+CREATE TABLE IF NOT EXISTS "docbox_serverless_tenant_api_keys" (
+    "id" UUID NOT NULL PRIMARY KEY,
+    -- ID and environment of the tenant the key belongs to
+    "tenant_id" UUID NOT NULL,
+    "tenant_env" VARCHAR NOT NULL,
+    -- Name of the key, unique within the tenant
+    "name" VARCHAR NOT NULL,
+    -- Hex encoded SHA-256 hash of the key, the key itself is never stored
+    "key_hash" VARCHAR NOT NULL UNIQUE,
+    "created_at" TIMESTAMPTZ NOT NULL,
+    UNIQUE ("tenant_id", "tenant_env", "name")
+);
@@ -0,0 +1,9 @@
+-- Claims for presigned upload tasks that are being completed or cancelled, a
+-- claim prevents the same upload from being completed or cancelled concurrently
+CREATE TABLE IF NOT EXISTS "docbox_serverless_presigned_task_claims" (
+    -- ID of the claimed presigned upload task
+    "task_id" UUID NOT NULL PRIMARY KEY,
+    -- When the claim was taken, claims that are older than the claim timeout
+    -- were abandoned and can be claimed again
+    "claimed_at" TIMESTAMPTZ NOT NULL
+);
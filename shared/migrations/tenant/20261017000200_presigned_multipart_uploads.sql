@@ -0,0 +1,8 @@
+-- Multipart upload of a presigned upload task, tasks without a row are
+-- regular single request uploads
+CREATE TABLE IF NOT EXISTS "docbox_serverless_presigned_multipart_uploads" (
+    "task_id" UUID NOT NULL PRIMARY KEY
+        REFERENCES "docbox_presigned_upload_tasks" ("id") ON DELETE CASCADE,
+    -- ID of the multipart upload within storage
+    "upload_id" VARCHAR NOT NULL
+);
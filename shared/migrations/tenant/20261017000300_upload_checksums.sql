@@ -0,0 +1,20 @@
+-- Checksum the contents of a presigned upload must match, tasks without a row
+-- were created without a checksum
+CREATE TABLE IF NOT EXISTS "docbox_serverless_presigned_task_checksums" (
+    "task_id" UUID NOT NULL PRIMARY KEY
+        REFERENCES "docbox_presigned_upload_tasks" ("id") ON DELETE CASCADE,
+    -- Algorithm used to compute the checksum (SHA256, CRC32C or MD5)
+    "algorithm" VARCHAR NOT NULL,
+    -- Base64 encoded checksum
+    "value" VARCHAR NOT NULL
+);
+
+-- Verified checksum of the contents of a file uploaded with a checksum
+CREATE TABLE IF NOT EXISTS "docbox_serverless_file_checksums" (
+    "file_id" UUID NOT NULL PRIMARY KEY
+        REFERENCES "docbox_files" ("id") ON DELETE CASCADE,
+    -- Algorithm used to compute the checksum (SHA256, CRC32C or MD5)
+    "algorithm" VARCHAR NOT NULL,
+    -- Base64 encoded checksum
+    "value" VARCHAR NOT NULL
+);
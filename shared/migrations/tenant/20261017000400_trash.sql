@@ -0,0 +1,22 @@
+-- Files, folders and links that are moved to the trash are kept until they are
+-- restored or purged, items with a "deleted_at" date are in the trash
+ALTER TABLE "docbox_files"
+    ADD COLUMN IF NOT EXISTS "deleted_at" TIMESTAMP WITH TIME ZONE NULL,
+    ADD COLUMN IF NOT EXISTS "deleted_by" VARCHAR NULL;
+
+ALTER TABLE "docbox_folders"
+    ADD COLUMN IF NOT EXISTS "deleted_at" TIMESTAMP WITH TIME ZONE NULL,
+    ADD COLUMN IF NOT EXISTS "deleted_by" VARCHAR NULL;
+
+ALTER TABLE "docbox_links"
+    ADD COLUMN IF NOT EXISTS "deleted_at" TIMESTAMP WITH TIME ZONE NULL,
+    ADD COLUMN IF NOT EXISTS "deleted_by" VARCHAR NULL;
+
+CREATE INDEX IF NOT EXISTS "docbox_files_deleted_at_idx"
+    ON "docbox_files" ("deleted_at") WHERE "deleted_at" IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS "docbox_folders_deleted_at_idx"
+    ON "docbox_folders" ("deleted_at") WHERE "deleted_at" IS NOT NULL;
+
+CREATE INDEX IF NOT EXISTS "docbox_links_deleted_at_idx"
+    ON "docbox_links" ("deleted_at") WHERE "deleted_at" IS NOT NULL;
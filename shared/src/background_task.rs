@@ -0,0 +1,137 @@
+//! Background tasks that are too long running to complete within a single
+//! HTTP request, these are published to an SQS queue and processed by the
+//! background tasks lambda
+
+use crate::{reprocess::ReprocessFilesProgress, search_index::RebuildSearchIndexProgress};
+use docbox_core::aws::SqsClient;
+use docbox_database::models::{tasks::TaskId, tenant::Tenant};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Message published to the background task queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackgroundTaskMessage {
+    /// ID of the tenant the task is for
+    pub tenant_id: Uuid,
+    /// Environment of the tenant the task is for
+    pub tenant_env: String,
+    /// What the task should be performed on
+    pub target: BackgroundTaskTarget,
+    /// The work to perform
+    pub kind: BackgroundTaskKind,
+}
+
+/// Target of a background task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum BackgroundTaskTarget {
+    /// Every document box within the tenant, the background tasks lambda
+    /// creates and publishes a document box task for each document box
+    Tenant {
+        /// Number of document boxes that tasks have already been created for,
+        /// the fan out continues from here when it spans multiple messages
+        #[serde(default)]
+        offset: u64,
+    },
+    /// A single document box
+    DocumentBox {
+        /// Scope of the document box
+        scope: String,
+        /// ID of the task tracking the progress
+        task_id: TaskId,
+    },
+}
+
+/// Type of background task to perform
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum BackgroundTaskKind {
+    /// Rebuild the search index for the document box
+    RebuildSearchIndex {
+        /// Progress of the rebuild, tasks that don't complete within a single
+        /// message are continued by publishing a message with their progress
+        #[serde(default)]
+        progress: RebuildSearchIndexProgress,
+    },
+    /// Reprocess application/octet-stream files within the document box
+    ReprocessOctetStreamFiles {
+        /// Progress of the reprocessing, continued in the same way as a rebuild
+        #[serde(default)]
+        progress: ReprocessFilesProgress,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum PublishBackgroundTaskError {
+    #[error("background task queue is not configured")]
+    NotConfigured,
+
+    #[error("failed to serialize background task message")]
+    Serialize(#[from] serde_json::Error),
+
+    #[error("failed to publish background task message")]
+    Publish,
+}
+
+/// Publisher for sending tasks to the background task queue
+#[derive(Clone)]
+pub struct BackgroundTaskQueue {
+    client: SqsClient,
+    /// URL of the SQS queue, background tasks are unavailable when
+    /// no queue has been configured
+    queue_url: Option<String>,
+}
+
+impl BackgroundTaskQueue {
+    pub fn new(client: SqsClient, queue_url: Option<String>) -> Self {
+        Self { client, queue_url }
+    }
+
+    /// Create a queue using the URL from the `DOCBOX_BACKGROUND_TASK_QUEUE_URL`
+    /// environment variable
+    pub fn from_env(client: SqsClient) -> Self {
+        let queue_url = std::env::var("DOCBOX_BACKGROUND_TASK_QUEUE_URL").ok();
+        Self::new(client, queue_url)
+    }
+
+    /// Whether a queue has been configured for publishing background tasks
+    pub fn is_configured(&self) -> bool {
+        self.queue_url.is_some()
+    }
+
+    /// Publish a background task for the `tenant`
+    pub async fn publish(
+        &self,
+        tenant: &Tenant,
+        target: BackgroundTaskTarget,
+        kind: BackgroundTaskKind,
+    ) -> Result<(), PublishBackgroundTaskError> {
+        let queue_url = self
+            .queue_url
+            .as_ref()
+            .ok_or(PublishBackgroundTaskError::NotConfigured)?;
+
+        let message = BackgroundTaskMessage {
+            tenant_id: tenant.id,
+            tenant_env: tenant.env.clone(),
+            target,
+            kind,
+        };
+
+        let message_body = serde_json::to_string(&message)?;
+
+        self.client
+            .send_message()
+            .queue_url(queue_url)
+            .message_body(message_body)
+            .send()
+            .await
+            .map_err(|error| {
+                tracing::error!(?error, "failed to send background task message");
+                PublishBackgroundTaskError::Publish
+            })?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+//! Checksums for verifying the contents of uploaded files
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Algorithm used to compute the checksum of an upload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Sha256,
+    Crc32c,
+    Md5,
+}
+
+impl ChecksumAlgorithm {
+    /// Name the algorithm is stored under in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecksumAlgorithm::Sha256 => "SHA256",
+            ChecksumAlgorithm::Crc32c => "CRC32C",
+            ChecksumAlgorithm::Md5 => "MD5",
+        }
+    }
+}
+
+impl FromStr for ChecksumAlgorithm {
+    type Err = UnknownChecksumAlgorithm;
+
+    /// Parses the stored name of an algorithm
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "SHA256" => Ok(ChecksumAlgorithm::Sha256),
+            "CRC32C" => Ok(ChecksumAlgorithm::Crc32c),
+            "MD5" => Ok(ChecksumAlgorithm::Md5),
+            _ => Err(UnknownChecksumAlgorithm),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("unknown checksum algorithm")]
+pub struct UnknownChecksumAlgorithm;
+
+/// Checksum of the contents of an upload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadChecksum {
+    /// Algorithm used to compute the checksum
+    pub algorithm: ChecksumAlgorithm,
+    /// Base64 encoded checksum
+    pub value: String,
+}
+
+/// Length in bytes of the decoded checksum for the `algorithm`
+fn checksum_length(algorithm: ChecksumAlgorithm) -> usize {
+    match algorithm {
+        ChecksumAlgorithm::Sha256 => 32,
+        ChecksumAlgorithm::Crc32c => 4,
+        ChecksumAlgorithm::Md5 => 16,
+    }
+}
+
+/// Checks that the checksum value is base64 encoded and the correct
+/// length for its algorithm
+pub fn is_valid_checksum(checksum: &UploadChecksum) -> bool {
+    STANDARD
+        .decode(&checksum.value)
+        .is_ok_and(|value| value.len() == checksum_length(checksum.algorithm))
+}
+
+/// Provides the hex encoded SHA-256 hash from a SHA-256 checksum, matching
+/// the format of the content hash stored for files
+pub fn sha256_hex(checksum: &UploadChecksum) -> Option<String> {
+    if !matches!(checksum.algorithm, ChecksumAlgorithm::Sha256) {
+        return None;
+    }
+
+    let value = STANDARD.decode(&checksum.value).ok()?;
+    Some(hex(&value))
+}
+
+/// Computes the hex encoded SHA-256 hash of file contents, matching
+/// the format of the content hash stored for files
+pub fn content_hash(bytes: &[u8]) -> String {
+    hex(&Sha256::digest(bytes))
+}
+
+fn hex(value: &[u8]) -> String {
+    value.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Creates the value for a `Repr-Digest` header (RFC 9530) from the checksum
+pub fn repr_digest(checksum: &UploadChecksum) -> String {
+    let algorithm = match checksum.algorithm {
+        ChecksumAlgorithm::Sha256 => "sha-256",
+        ChecksumAlgorithm::Crc32c => "crc32c",
+        ChecksumAlgorithm::Md5 => "md5",
+    };
+
+    format!("{algorithm}=:{}:", checksum.value)
+}
+
+/// Incrementally computes the checksum of file contents
+pub enum ChecksumHasher {
+    Sha256(Sha256),
+    Crc32c(u32),
+    Md5(Md5),
+}
+
+impl ChecksumHasher {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        match algorithm {
+            ChecksumAlgorithm::Sha256 => ChecksumHasher::Sha256(Sha256::new()),
+            ChecksumAlgorithm::Crc32c => ChecksumHasher::Crc32c(0),
+            ChecksumAlgorithm::Md5 => ChecksumHasher::Md5(Md5::new()),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            ChecksumHasher::Sha256(hasher) => hasher.update(bytes),
+            ChecksumHasher::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, bytes),
+            ChecksumHasher::Md5(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Provides back the base64 encoded checksum
+    pub fn finalize(self) -> String {
+        match self {
+            ChecksumHasher::Sha256(hasher) => STANDARD.encode(hasher.finalize()),
+            ChecksumHasher::Crc32c(crc) => STANDARD.encode(crc.to_be_bytes()),
+            ChecksumHasher::Md5(hasher) => STANDARD.encode(hasher.finalize()),
+        }
+    }
+}
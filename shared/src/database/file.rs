@@ -0,0 +1,135 @@
+//! Queries for files
+
+use crate::checksum::{ChecksumAlgorithm, UploadChecksum};
+use docbox_database::{
+    DbPool, DbResult,
+    models::file::{File, FileId},
+};
+
+/// Common table expression for the files within the document box `$1` that are
+/// not in the trash, files within a trashed folder (or any of its sub folders)
+/// are in the trash along with the folder
+const ACTIVE_FILES_CTE: &str = r#"WITH RECURSIVE "trashed_folders" AS (
+    SELECT "id" FROM "docbox_folders"
+    WHERE "document_box" = $1 AND "deleted_at" IS NOT NULL
+    UNION
+    SELECT "child"."id" FROM "docbox_folders" "child"
+    INNER JOIN "trashed_folders" ON "child"."folder_id" = "trashed_folders"."id"
+),
+"active_files" AS (
+    SELECT "file".* FROM "docbox_files" "file"
+    INNER JOIN "docbox_folders" "folder" ON "file"."folder_id" = "folder"."id"
+    WHERE "folder"."document_box" = $1 AND "file"."deleted_at" IS NULL
+        AND "file"."folder_id" NOT IN (SELECT "id" FROM "trashed_folders")
+)"#;
+
+/// Updates the mime type of the `file`, provides back the updated file
+pub async fn set_file_mime(db: &DbPool, file: File, mime: String) -> DbResult<File> {
+    sqlx::query(r#"UPDATE "docbox_files" SET "mime" = $1 WHERE "id" = $2"#)
+        .bind(&mime)
+        .bind(file.id)
+        .execute(db)
+        .await?;
+
+    Ok(File { mime, ..file })
+}
+
+/// Stores the verified `checksum` of the contents of the file `file_id`
+pub async fn set_file_checksum(
+    db: &DbPool,
+    file_id: FileId,
+    checksum: &UploadChecksum,
+) -> DbResult<()> {
+    sqlx::query(
+        r#"INSERT INTO "docbox_serverless_file_checksums" ("file_id", "algorithm", "value")
+        VALUES ($1, $2, $3)
+        ON CONFLICT ("file_id") DO UPDATE
+        SET "algorithm" = EXCLUDED."algorithm", "value" = EXCLUDED."value""#,
+    )
+    .bind(file_id)
+    .bind(checksum.algorithm.as_str())
+    .bind(&checksum.value)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Finds the checksum of the contents of the file `file_id`, provides [None]
+/// when the file was not uploaded with a checksum
+pub async fn find_file_checksum(db: &DbPool, file_id: FileId) -> DbResult<Option<UploadChecksum>> {
+    let row: Option<(String, String)> = sqlx::query_as(
+        r#"SELECT "algorithm", "value" FROM "docbox_serverless_file_checksums"
+        WHERE "file_id" = $1"#,
+    )
+    .bind(file_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.and_then(|(algorithm, value)| {
+        let algorithm = algorithm.parse::<ChecksumAlgorithm>().ok()?;
+        Some(UploadChecksum { algorithm, value })
+    }))
+}
+
+/// Finds the oldest file within the document box `scope` with the content `hash`,
+/// files in the trash are not included
+pub async fn find_file_by_hash(db: &DbPool, scope: &str, hash: &str) -> DbResult<Option<File>> {
+    let query = format!(
+        r#"{ACTIVE_FILES_CTE}
+        SELECT * FROM "active_files" WHERE "hash" = $2
+        ORDER BY "created_at" ASC
+        LIMIT 1"#
+    );
+
+    sqlx::query_as(&query)
+        .bind(scope)
+        .bind(hash)
+        .fetch_optional(db)
+        .await
+}
+
+/// Finds the files within the document box `scope` that share their content hash
+/// with another file, ordered by hash then creation date. The `offset` and `limit`
+/// apply to the hashes so groups of files are never split across pages. Files in
+/// the trash are not included
+pub async fn find_duplicate_files(
+    db: &DbPool,
+    scope: &str,
+    offset: u64,
+    limit: u64,
+) -> DbResult<Vec<File>> {
+    let query = format!(
+        r#"{ACTIVE_FILES_CTE}
+        SELECT * FROM "active_files" WHERE "hash" IN (
+            SELECT "hash" FROM "active_files"
+            GROUP BY "hash"
+            HAVING COUNT(*) > 1
+            ORDER BY "hash"
+            OFFSET $2 LIMIT $3
+        )
+        ORDER BY "hash" ASC, "created_at" ASC"#
+    );
+
+    sqlx::query_as(&query)
+        .bind(scope)
+        .bind(offset as i64)
+        .bind(limit as i64)
+        .fetch_all(db)
+        .await
+}
+
+/// Counts the groups of files within the document box `scope` that share their
+/// content hash, the total for [find_duplicate_files]
+pub async fn count_duplicate_files(db: &DbPool, scope: &str) -> DbResult<i64> {
+    let query = format!(
+        r#"{ACTIVE_FILES_CTE}
+        SELECT COUNT(*) FROM (
+            SELECT "hash" FROM "active_files"
+            GROUP BY "hash"
+            HAVING COUNT(*) > 1
+        ) "duplicates""#
+    );
+
+    sqlx::query_scalar(&query).bind(scope).fetch_one(db).await
+}
@@ -0,0 +1,33 @@
+//! Queries for generated files
+
+use docbox_database::{DbPool, DbResult, models::generated_file::CreateGeneratedFile};
+use uuid::Uuid;
+
+/// Stores a generated file created when processing a file
+pub async fn create_generated_file(db: &DbPool, create: CreateGeneratedFile) -> DbResult<()> {
+    sqlx::query(
+        r#"INSERT INTO "docbox_generated_files" ("id", "file_id", "mime", "type", "hash", "file_key", "created_at")
+        VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+    )
+    .bind(create.id)
+    .bind(create.file_id)
+    .bind(create.mime)
+    .bind(create.ty.to_string())
+    .bind(create.hash)
+    .bind(create.file_key)
+    .bind(create.created_at)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Deletes the generated file `generated_id`
+pub async fn delete_generated_file(db: &DbPool, generated_id: Uuid) -> DbResult<()> {
+    sqlx::query(r#"DELETE FROM "docbox_generated_files" WHERE "id" = $1"#)
+        .bind(generated_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,9 @@
+//! Queries for the tenant and root databases that are not provided by the
+//! docbox database models, these use the same tables as the docbox models
+//! along with the tables created by the serverless migrations
+
+pub mod file;
+pub mod generated_file;
+pub mod presigned_upload_task;
+pub mod tenant_api_key;
+pub mod trash;
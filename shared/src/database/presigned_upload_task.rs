@@ -0,0 +1,154 @@
+//! Queries for presigned upload tasks
+
+use crate::checksum::{ChecksumAlgorithm, UploadChecksum};
+use docbox_database::{
+    DbPool, DbResult,
+    models::presigned_upload_task::{
+        PresignedTaskStatus, PresignedUploadTask, PresignedUploadTaskId,
+    },
+};
+use sqlx::types::Json;
+use std::collections::HashMap;
+
+/// Updates the status of the presigned upload task `task_id`
+pub async fn set_presigned_task_status(
+    db: &DbPool,
+    task_id: PresignedUploadTaskId,
+    status: PresignedTaskStatus,
+) -> DbResult<()> {
+    sqlx::query(r#"UPDATE "docbox_presigned_upload_tasks" SET "status" = $1 WHERE "id" = $2"#)
+        .bind(Json(status))
+        .bind(task_id)
+        .execute(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Finds the presigned upload tasks within the document box `scope` that have not
+/// completed (pending or failed), newest first
+///
+/// The status is stored as JSON tagged by its "status" field
+pub async fn find_incomplete_presigned_tasks(
+    db: &DbPool,
+    scope: &str,
+    offset: u64,
+    limit: u64,
+) -> DbResult<Vec<PresignedUploadTask>> {
+    sqlx::query_as(
+        r#"SELECT * FROM "docbox_presigned_upload_tasks"
+        WHERE "document_box" = $1 AND "status"->>'status' <> 'Completed'
+        ORDER BY "created_at" DESC
+        OFFSET $2 LIMIT $3"#,
+    )
+    .bind(scope)
+    .bind(offset as i64)
+    .bind(limit as i64)
+    .fetch_all(db)
+    .await
+}
+
+/// Counts the presigned upload tasks within the document box `scope` that have
+/// not completed, the total for [find_incomplete_presigned_tasks]
+pub async fn count_incomplete_presigned_tasks(db: &DbPool, scope: &str) -> DbResult<i64> {
+    sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM "docbox_presigned_upload_tasks"
+        WHERE "document_box" = $1 AND "status"->>'status' <> 'Completed'"#,
+    )
+    .bind(scope)
+    .fetch_one(db)
+    .await
+}
+
+/// Stores the ID of the multipart upload for the presigned upload task `task_id`
+pub async fn set_presigned_task_multipart_upload_id(
+    db: &DbPool,
+    task_id: PresignedUploadTaskId,
+    upload_id: &str,
+) -> DbResult<()> {
+    sqlx::query(
+        r#"INSERT INTO "docbox_serverless_presigned_multipart_uploads" ("task_id", "upload_id")
+        VALUES ($1, $2)
+        ON CONFLICT ("task_id") DO UPDATE SET "upload_id" = EXCLUDED."upload_id""#,
+    )
+    .bind(task_id)
+    .bind(upload_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Finds the ID of the multipart upload for the presigned upload task `task_id`,
+/// provides [None] when the task is not a multipart upload
+pub async fn find_presigned_task_multipart_upload_id(
+    db: &DbPool,
+    task_id: PresignedUploadTaskId,
+) -> DbResult<Option<String>> {
+    sqlx::query_scalar(
+        r#"SELECT "upload_id" FROM "docbox_serverless_presigned_multipart_uploads"
+        WHERE "task_id" = $1"#,
+    )
+    .bind(task_id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Finds the IDs of the multipart uploads for the presigned upload tasks
+/// `task_ids`, tasks that are not multipart uploads are not included
+pub async fn find_presigned_tasks_multipart_upload_ids(
+    db: &DbPool,
+    task_ids: &[PresignedUploadTaskId],
+) -> DbResult<HashMap<PresignedUploadTaskId, String>> {
+    let rows: Vec<(PresignedUploadTaskId, String)> = sqlx::query_as(
+        r#"SELECT "task_id", "upload_id" FROM "docbox_serverless_presigned_multipart_uploads"
+        WHERE "task_id" = ANY($1)"#,
+    )
+    .bind(task_ids)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().collect())
+}
+
+/// Stores the `checksum` that the upload for the presigned upload task `task_id`
+/// must match
+pub async fn set_presigned_task_checksum(
+    db: &DbPool,
+    task_id: PresignedUploadTaskId,
+    checksum: &UploadChecksum,
+) -> DbResult<()> {
+    sqlx::query(
+        r#"INSERT INTO "docbox_serverless_presigned_task_checksums" ("task_id", "algorithm", "value")
+        VALUES ($1, $2, $3)
+        ON CONFLICT ("task_id") DO UPDATE
+        SET "algorithm" = EXCLUDED."algorithm", "value" = EXCLUDED."value""#,
+    )
+    .bind(task_id)
+    .bind(checksum.algorithm.as_str())
+    .bind(&checksum.value)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Finds the checksum for the presigned upload task `task_id`, provides [None]
+/// when the task was created without a checksum
+pub async fn find_presigned_task_checksum(
+    db: &DbPool,
+    task_id: PresignedUploadTaskId,
+) -> DbResult<Option<UploadChecksum>> {
+    let row: Option<(String, String)> = sqlx::query_as(
+        r#"SELECT "algorithm", "value" FROM "docbox_serverless_presigned_task_checksums"
+        WHERE "task_id" = $1"#,
+    )
+    .bind(task_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.and_then(|(algorithm, value)| {
+        let algorithm = algorithm.parse::<ChecksumAlgorithm>().ok()?;
+        Some(UploadChecksum { algorithm, value })
+    }))
+}
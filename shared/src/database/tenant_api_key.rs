@@ -0,0 +1,122 @@
+//! Queries for the API keys that are restricted to a single tenant, these are
+//! stored within the root database alongside the tenant they belong to
+
+use crate::checksum::content_hash;
+use chrono::{DateTime, Utc};
+use docbox_database::{DbPool, DbResult, models::tenant::Tenant};
+use serde::Serialize;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// API key that can only act on its own tenant, the key itself is never
+/// stored only its hash
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct TenantApiKey {
+    pub id: Uuid,
+    pub tenant_id: Uuid,
+    pub tenant_env: String,
+    /// Name of the key, unique within the tenant
+    pub name: String,
+    #[serde(skip)]
+    pub key_hash: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Error)]
+pub enum CreateTenantApiKeyError {
+    #[error("an api key with this name already exists for the tenant")]
+    DuplicateName,
+
+    #[error(transparent)]
+    Database(sqlx::Error),
+}
+
+/// Hashes an API `key` for storage and lookup
+pub fn hash_tenant_api_key(key: &str) -> String {
+    content_hash(key.as_bytes())
+}
+
+/// Generates a new random API key
+fn generate_tenant_api_key() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+/// Creates a new API key named `name` for the `tenant`, provides back the
+/// stored key along with the generated key which cannot be retrieved again
+pub async fn create_tenant_api_key(
+    db: &DbPool,
+    tenant: &Tenant,
+    name: String,
+) -> Result<(TenantApiKey, String), CreateTenantApiKeyError> {
+    let key = generate_tenant_api_key();
+    let api_key = TenantApiKey {
+        id: Uuid::new_v4(),
+        tenant_id: tenant.id,
+        tenant_env: tenant.env.clone(),
+        name,
+        key_hash: hash_tenant_api_key(&key),
+        created_at: Utc::now(),
+    };
+
+    sqlx::query(
+        r#"INSERT INTO "docbox_serverless_tenant_api_keys"
+            ("id", "tenant_id", "tenant_env", "name", "key_hash", "created_at")
+        VALUES ($1, $2, $3, $4, $5, $6)"#,
+    )
+    .bind(api_key.id)
+    .bind(api_key.tenant_id)
+    .bind(&api_key.tenant_env)
+    .bind(&api_key.name)
+    .bind(&api_key.key_hash)
+    .bind(api_key.created_at)
+    .execute(db)
+    .await
+    .map_err(|error| match error {
+        sqlx::Error::Database(error) if error.is_unique_violation() => {
+            CreateTenantApiKeyError::DuplicateName
+        }
+        error => CreateTenantApiKeyError::Database(error),
+    })?;
+
+    Ok((api_key, key))
+}
+
+/// Finds the API key with the provided `key_hash`
+pub async fn find_tenant_api_key_by_hash(
+    db: &DbPool,
+    key_hash: &str,
+) -> DbResult<Option<TenantApiKey>> {
+    sqlx::query_as(r#"SELECT * FROM "docbox_serverless_tenant_api_keys" WHERE "key_hash" = $1"#)
+        .bind(key_hash)
+        .fetch_optional(db)
+        .await
+}
+
+/// Finds all the API keys for the `tenant`
+pub async fn tenant_api_keys(db: &DbPool, tenant: &Tenant) -> DbResult<Vec<TenantApiKey>> {
+    sqlx::query_as(
+        r#"SELECT * FROM "docbox_serverless_tenant_api_keys"
+        WHERE "tenant_id" = $1 AND "tenant_env" = $2
+        ORDER BY "created_at" ASC"#,
+    )
+    .bind(tenant.id)
+    .bind(&tenant.env)
+    .fetch_all(db)
+    .await
+}
+
+/// Deletes the API key named `name` from the `tenant`, provides back
+/// whether a key was deleted
+pub async fn delete_tenant_api_key(db: &DbPool, tenant: &Tenant, name: &str) -> DbResult<bool> {
+    let result = sqlx::query(
+        r#"DELETE FROM "docbox_serverless_tenant_api_keys"
+        WHERE "tenant_id" = $1 AND "tenant_env" = $2 AND "name" = $3"#,
+    )
+    .bind(tenant.id)
+    .bind(&tenant.env)
+    .bind(name)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,325 @@
+//! Queries for the trash, files, folders and links are moved to the trash by
+//! setting their "deleted_at" date (Added by the trash migration)
+//!
+//! Only the items that were deleted directly are in the trash, the contents
+//! of a trashed folder remain within the folder and are restored with it
+
+use chrono::{DateTime, Utc};
+use docbox_database::{
+    DbPool, DbResult,
+    models::{
+        file::{File, FileId},
+        folder::{Folder, FolderId},
+        link::{Link, LinkId},
+    },
+};
+
+/// Item that has been moved to the trash
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Trashed<T> {
+    #[sqlx(flatten)]
+    pub item: T,
+    /// When the item was moved to the trash
+    pub deleted_at: DateTime<Utc>,
+    /// ID of the user that moved the item to the trash
+    pub deleted_by: Option<String>,
+}
+
+/// Moves the file `file_id` to the trash, provides back whether the
+/// file was moved (false when the file is already in the trash)
+pub async fn trash_file(
+    db: &DbPool,
+    file_id: FileId,
+    deleted_by: Option<String>,
+) -> DbResult<bool> {
+    let result = sqlx::query(
+        r#"UPDATE "docbox_files" SET "deleted_at" = NOW(), "deleted_by" = $2
+        WHERE "id" = $1 AND "deleted_at" IS NULL"#,
+    )
+    .bind(file_id)
+    .bind(deleted_by)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Restores the file `file_id` from the trash
+pub async fn restore_file(db: &DbPool, file_id: FileId) -> DbResult<()> {
+    sqlx::query(
+        r#"UPDATE "docbox_files" SET "deleted_at" = NULL, "deleted_by" = NULL
+        WHERE "id" = $1"#,
+    )
+    .bind(file_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Finds the file `file_id` within the trash of the document box `scope`
+pub async fn find_trashed_file(
+    db: &DbPool,
+    scope: &str,
+    file_id: FileId,
+) -> DbResult<Option<File>> {
+    sqlx::query_as(
+        r#"SELECT "file".* FROM "docbox_files" "file"
+        INNER JOIN "docbox_folders" "folder" ON "file"."folder_id" = "folder"."id"
+        WHERE "folder"."document_box" = $1 AND "file"."id" = $2
+            AND "file"."deleted_at" IS NOT NULL"#,
+    )
+    .bind(scope)
+    .bind(file_id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Finds the files in the trash of the document box `scope`, most recently
+/// deleted first. When `deleted_before` is provided only files deleted before
+/// that date are included
+pub async fn find_trashed_files(
+    db: &DbPool,
+    scope: &str,
+    deleted_before: Option<DateTime<Utc>>,
+    offset: u64,
+    limit: u64,
+) -> DbResult<Vec<Trashed<File>>> {
+    sqlx::query_as(
+        r#"SELECT "file".* FROM "docbox_files" "file"
+        INNER JOIN "docbox_folders" "folder" ON "file"."folder_id" = "folder"."id"
+        WHERE "folder"."document_box" = $1 AND "file"."deleted_at" IS NOT NULL
+            AND ($2::TIMESTAMPTZ IS NULL OR "file"."deleted_at" < $2)
+        ORDER BY "file"."deleted_at" DESC
+        OFFSET $3 LIMIT $4"#,
+    )
+    .bind(scope)
+    .bind(deleted_before)
+    .bind(offset as i64)
+    .bind(limit as i64)
+    .fetch_all(db)
+    .await
+}
+
+/// Counts the files in the trash of the document box `scope`, the total
+/// for [find_trashed_files]
+pub async fn count_trashed_files(
+    db: &DbPool,
+    scope: &str,
+    deleted_before: Option<DateTime<Utc>>,
+) -> DbResult<i64> {
+    sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM "docbox_files" "file"
+        INNER JOIN "docbox_folders" "folder" ON "file"."folder_id" = "folder"."id"
+        WHERE "folder"."document_box" = $1 AND "file"."deleted_at" IS NOT NULL
+            AND ($2::TIMESTAMPTZ IS NULL OR "file"."deleted_at" < $2)"#,
+    )
+    .bind(scope)
+    .bind(deleted_before)
+    .fetch_one(db)
+    .await
+}
+
+/// Moves the folder `folder_id` to the trash along with its contents, provides
+/// back whether the folder was moved (false when the folder is already in the trash)
+pub async fn trash_folder(
+    db: &DbPool,
+    folder_id: FolderId,
+    deleted_by: Option<String>,
+) -> DbResult<bool> {
+    let result = sqlx::query(
+        r#"UPDATE "docbox_folders" SET "deleted_at" = NOW(), "deleted_by" = $2
+        WHERE "id" = $1 AND "deleted_at" IS NULL"#,
+    )
+    .bind(folder_id)
+    .bind(deleted_by)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Restores the folder `folder_id` from the trash along with its contents
+pub async fn restore_folder(db: &DbPool, folder_id: FolderId) -> DbResult<()> {
+    sqlx::query(
+        r#"UPDATE "docbox_folders" SET "deleted_at" = NULL, "deleted_by" = NULL
+        WHERE "id" = $1"#,
+    )
+    .bind(folder_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Finds the folder `folder_id` within the trash of the document box `scope`
+pub async fn find_trashed_folder(
+    db: &DbPool,
+    scope: &str,
+    folder_id: FolderId,
+) -> DbResult<Option<Folder>> {
+    sqlx::query_as(
+        r#"SELECT * FROM "docbox_folders"
+        WHERE "document_box" = $1 AND "id" = $2 AND "deleted_at" IS NOT NULL"#,
+    )
+    .bind(scope)
+    .bind(folder_id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Finds the folders in the trash of the document box `scope`, most recently
+/// deleted first. When `deleted_before` is provided only folders deleted before
+/// that date are included
+pub async fn find_trashed_folders(
+    db: &DbPool,
+    scope: &str,
+    deleted_before: Option<DateTime<Utc>>,
+    offset: u64,
+    limit: u64,
+) -> DbResult<Vec<Trashed<Folder>>> {
+    sqlx::query_as(
+        r#"SELECT * FROM "docbox_folders"
+        WHERE "document_box" = $1 AND "deleted_at" IS NOT NULL
+            AND ($2::TIMESTAMPTZ IS NULL OR "deleted_at" < $2)
+        ORDER BY "deleted_at" DESC
+        OFFSET $3 LIMIT $4"#,
+    )
+    .bind(scope)
+    .bind(deleted_before)
+    .bind(offset as i64)
+    .bind(limit as i64)
+    .fetch_all(db)
+    .await
+}
+
+/// Counts the folders in the trash of the document box `scope`, the total
+/// for [find_trashed_folders]
+pub async fn count_trashed_folders(
+    db: &DbPool,
+    scope: &str,
+    deleted_before: Option<DateTime<Utc>>,
+) -> DbResult<i64> {
+    sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM "docbox_folders"
+        WHERE "document_box" = $1 AND "deleted_at" IS NOT NULL
+            AND ($2::TIMESTAMPTZ IS NULL OR "deleted_at" < $2)"#,
+    )
+    .bind(scope)
+    .bind(deleted_before)
+    .fetch_one(db)
+    .await
+}
+
+/// Checks whether the folder `folder_id` is in the trash, either directly
+/// or as part of the contents of a trashed folder
+pub async fn is_folder_in_trash(db: &DbPool, folder_id: FolderId) -> DbResult<bool> {
+    sqlx::query_scalar(
+        r#"WITH RECURSIVE "ancestors" AS (
+            SELECT "id", "folder_id", "deleted_at" FROM "docbox_folders" WHERE "id" = $1
+            UNION ALL
+            SELECT "parent"."id", "parent"."folder_id", "parent"."deleted_at"
+            FROM "docbox_folders" "parent"
+            INNER JOIN "ancestors" ON "parent"."id" = "ancestors"."folder_id"
+        )
+        SELECT EXISTS(SELECT 1 FROM "ancestors" WHERE "deleted_at" IS NOT NULL)"#,
+    )
+    .bind(folder_id)
+    .fetch_one(db)
+    .await
+}
+
+/// Moves the link `link_id` to the trash, provides back whether the
+/// link was moved (false when the link is already in the trash)
+pub async fn trash_link(
+    db: &DbPool,
+    link_id: LinkId,
+    deleted_by: Option<String>,
+) -> DbResult<bool> {
+    let result = sqlx::query(
+        r#"UPDATE "docbox_links" SET "deleted_at" = NOW(), "deleted_by" = $2
+        WHERE "id" = $1 AND "deleted_at" IS NULL"#,
+    )
+    .bind(link_id)
+    .bind(deleted_by)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Restores the link `link_id` from the trash
+pub async fn restore_link(db: &DbPool, link_id: LinkId) -> DbResult<()> {
+    sqlx::query(
+        r#"UPDATE "docbox_links" SET "deleted_at" = NULL, "deleted_by" = NULL
+        WHERE "id" = $1"#,
+    )
+    .bind(link_id)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Finds the link `link_id` within the trash of the document box `scope`
+pub async fn find_trashed_link(
+    db: &DbPool,
+    scope: &str,
+    link_id: LinkId,
+) -> DbResult<Option<Link>> {
+    sqlx::query_as(
+        r#"SELECT "link".* FROM "docbox_links" "link"
+        INNER JOIN "docbox_folders" "folder" ON "link"."folder_id" = "folder"."id"
+        WHERE "folder"."document_box" = $1 AND "link"."id" = $2
+            AND "link"."deleted_at" IS NOT NULL"#,
+    )
+    .bind(scope)
+    .bind(link_id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Finds the links in the trash of the document box `scope`, most recently
+/// deleted first. When `deleted_before` is provided only links deleted before
+/// that date are included
+pub async fn find_trashed_links(
+    db: &DbPool,
+    scope: &str,
+    deleted_before: Option<DateTime<Utc>>,
+    offset: u64,
+    limit: u64,
+) -> DbResult<Vec<Trashed<Link>>> {
+    sqlx::query_as(
+        r#"SELECT "link".* FROM "docbox_links" "link"
+        INNER JOIN "docbox_folders" "folder" ON "link"."folder_id" = "folder"."id"
+        WHERE "folder"."document_box" = $1 AND "link"."deleted_at" IS NOT NULL
+            AND ($2::TIMESTAMPTZ IS NULL OR "link"."deleted_at" < $2)
+        ORDER BY "link"."deleted_at" DESC
+        OFFSET $3 LIMIT $4"#,
+    )
+    .bind(scope)
+    .bind(deleted_before)
+    .bind(offset as i64)
+    .bind(limit as i64)
+    .fetch_all(db)
+    .await
+}
+
+/// Counts the links in the trash of the document box `scope`, the total
+/// for [find_trashed_links]
+pub async fn count_trashed_links(
+    db: &DbPool,
+    scope: &str,
+    deleted_before: Option<DateTime<Utc>>,
+) -> DbResult<i64> {
+    sqlx::query_scalar(
+        r#"SELECT COUNT(*) FROM "docbox_links" "link"
+        INNER JOIN "docbox_folders" "folder" ON "link"."folder_id" = "folder"."id"
+        WHERE "folder"."document_box" = $1 AND "link"."deleted_at" IS NOT NULL
+            AND ($2::TIMESTAMPTZ IS NULL OR "link"."deleted_at" < $2)"#,
+    )
+    .bind(scope)
+    .bind(deleted_before)
+    .fetch_one(db)
+    .await
+}
@@ -0,0 +1,127 @@
+//! Helpers for loading the contents of a document box
+
+use docbox_database::{
+    DbPool, DbResult,
+    models::{
+        file::FileWithExtra,
+        folder::{Folder, FolderId, FolderWithExtra, ResolvedFolderWithExtra},
+        link::LinkWithExtra,
+    },
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// All the folders, files and links within a document box (excluding the root folder)
+#[derive(Default)]
+pub struct DocumentBoxContents {
+    pub folders: Vec<FolderWithExtra>,
+    pub files: Vec<FileWithExtra>,
+    pub links: Vec<LinkWithExtra>,
+}
+
+#[derive(Debug, Error)]
+pub enum LoadDocumentBoxContentsError {
+    #[error(transparent)]
+    Database(#[from] docbox_database::DbErr),
+
+    #[error("document box root folder not found")]
+    MissingRoot,
+}
+
+impl DocumentBoxContents {
+    /// Loads the contents of the document box `scope` by walking the
+    /// folder tree starting from the root folder
+    pub async fn load(db: &DbPool, scope: &str) -> Result<Self, LoadDocumentBoxContentsError> {
+        let root = Folder::find_root_with_extra(db, &scope.to_string())
+            .await?
+            .ok_or(LoadDocumentBoxContentsError::MissingRoot)?;
+
+        let contents = Self::load_folder(db, root.id).await?;
+        Ok(contents)
+    }
+
+    /// Loads the contents of the folder `folder_id` and all of its
+    /// descendants (excluding the folder itself)
+    pub async fn load_folder(db: &DbPool, folder_id: FolderId) -> DbResult<Self> {
+        let mut contents = DocumentBoxContents::default();
+        let mut pending_folders: Vec<FolderId> = vec![folder_id];
+
+        while let Some(folder_id) = pending_folders.pop() {
+            let children = ResolvedFolderWithExtra::resolve(db, folder_id).await?;
+
+            pending_folders.extend(children.folders.iter().map(|folder| folder.id));
+
+            contents.folders.extend(children.folders);
+            contents.files.extend(children.files);
+            contents.links.extend(children.links);
+        }
+
+        Ok(contents)
+    }
+
+    /// Total number of folders, files and links
+    pub fn len(&self) -> usize {
+        self.folders.len() + self.files.len() + self.links.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Position within a walk of the folder tree of a document box, allows the
+/// contents of a large document box to be processed in pages across multiple
+/// invocations by serializing the walk between them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DocumentBoxWalk {
+    /// Whether the walk has started from the root folder
+    started: bool,
+    /// Folders that are yet to be visited
+    pending_folders: Vec<FolderId>,
+}
+
+impl DocumentBoxWalk {
+    /// Whether every folder within the document box has been visited
+    pub fn is_complete(&self) -> bool {
+        self.started && self.pending_folders.is_empty()
+    }
+
+    /// Loads the contents of the next folders within the walk of the document box
+    /// `scope`, folders are visited until at least `limit` items have been loaded
+    /// (A single folder with more than `limit` items is loaded in full)
+    pub async fn next_page(
+        &mut self,
+        db: &DbPool,
+        scope: &str,
+        limit: usize,
+    ) -> Result<DocumentBoxContents, LoadDocumentBoxContentsError> {
+        if !self.started {
+            let root = Folder::find_root_with_extra(db, &scope.to_string())
+                .await?
+                .ok_or(LoadDocumentBoxContentsError::MissingRoot)?;
+
+            self.started = true;
+            self.pending_folders.push(root.id);
+        }
+
+        let mut contents = DocumentBoxContents::default();
+
+        while contents.len() < limit {
+            let folder_id = match self.pending_folders.pop() {
+                Some(value) => value,
+                None => break,
+            };
+
+            let children = ResolvedFolderWithExtra::resolve(db, folder_id).await?;
+
+            self.pending_folders
+                .extend(children.folders.iter().map(|folder| folder.id));
+
+            contents.folders.extend(children.folders);
+            contents.files.extend(children.files);
+            contents.links.extend(children.links);
+        }
+
+        Ok(contents)
+    }
+}
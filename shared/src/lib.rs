@@ -0,0 +1,17 @@
+//! Shared logic used across the docbox serverless lambdas
+
+pub mod background_task;
+pub mod checksum;
+pub mod database;
+pub mod document_box;
+pub mod migrations;
+pub mod presigned;
+pub mod processing;
+pub mod reconcile;
+pub mod reprocess;
+pub mod s3;
+pub mod search_index;
+pub mod storage;
+pub mod task_lock;
+pub mod tenant_sweep;
+pub mod trash;
@@ -0,0 +1,150 @@
+//! Migrations for the tables used by the serverless lambdas that are not part
+//! of the docbox database schema, these are applied to the root database and
+//! to each tenant database
+
+use crate::tenant_sweep::{TenantFailure, TenantSweepError, TenantSweepOptions, sweep_tenants};
+use docbox_database::{DatabasePoolCache, DbPool, DbResult};
+use serde::Serialize;
+use sqlx::Executor;
+
+/// Migration for the root or tenant databases
+struct Migration {
+    /// Unique name of the migration
+    name: &'static str,
+    /// SQL statements for the migration
+    sql: &'static str,
+}
+
+/// Migrations for the root database in the order they are applied
+const ROOT_MIGRATIONS: &[Migration] = &[Migration {
+    name: "20261017000100_tenant_api_keys",
+    sql: include_str!("../migrations/root/20261017000100_tenant_api_keys.sql"),
+}];
+
+/// Migrations for the tenant databases in the order they are applied
+const TENANT_MIGRATIONS: &[Migration] = &[
+    Migration {
+        name: "20261017000000_presigned_task_claims",
+        sql: include_str!("../migrations/tenant/20261017000000_presigned_task_claims.sql"),
+    },
+    Migration {
+        name: "20261017000200_presigned_multipart_uploads",
+        sql: include_str!("../migrations/tenant/20261017000200_presigned_multipart_uploads.sql"),
+    },
+    Migration {
+        name: "20261017000300_upload_checksums",
+        sql: include_str!("../migrations/tenant/20261017000300_upload_checksums.sql"),
+    },
+    Migration {
+        name: "20261017000400_trash",
+        sql: include_str!("../migrations/tenant/20261017000400_trash.sql"),
+    },
+];
+
+/// Advisory lock key held while applying migrations, prevents concurrent
+/// migrations from applying the same migration twice
+const MIGRATIONS_LOCK_KEY: i64 = 0x646f_6362_6f78_6d69;
+
+/// Applies any migrations that have not been applied to the root database,
+/// provides back the names of the migrations that were applied
+pub async fn apply_root_migrations(db: &DbPool) -> DbResult<Vec<&'static str>> {
+    apply_migrations(db, ROOT_MIGRATIONS).await
+}
+
+/// Applies any migrations that have not been applied to the tenant database,
+/// provides back the names of the migrations that were applied
+pub async fn apply_tenant_migrations(db: &DbPool) -> DbResult<Vec<&'static str>> {
+    apply_migrations(db, TENANT_MIGRATIONS).await
+}
+
+/// Applies the `migrations` that have not been applied to the database
+///
+/// All the migrations are applied within a single transaction so a failed
+/// migration leaves the database unchanged
+async fn apply_migrations(
+    db: &DbPool,
+    migrations: &'static [Migration],
+) -> DbResult<Vec<&'static str>> {
+    let mut transaction = db.begin().await?;
+
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(MIGRATIONS_LOCK_KEY)
+        .execute(&mut *transaction)
+        .await?;
+
+    sqlx::query(
+        r#"CREATE TABLE IF NOT EXISTS "docbox_serverless_migrations" (
+            "name" VARCHAR NOT NULL PRIMARY KEY,
+            "applied_at" TIMESTAMPTZ NOT NULL
+        )"#,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    let applied: Vec<String> =
+        sqlx::query_scalar(r#"SELECT "name" FROM "docbox_serverless_migrations""#)
+            .fetch_all(&mut *transaction)
+            .await?;
+
+    let mut applied_now = Vec::new();
+
+    for migration in migrations {
+        if applied.iter().any(|name| name == migration.name) {
+            continue;
+        }
+
+        // Executed without arguments so the statements of the migration are run
+        // as a simple query (raw_sql futures are not Send, which handlers require)
+        transaction.execute(migration.sql).await?;
+
+        sqlx::query(
+            r#"INSERT INTO "docbox_serverless_migrations" ("name", "applied_at") VALUES ($1, NOW())"#,
+        )
+        .bind(migration.name)
+        .execute(&mut *transaction)
+        .await?;
+
+        applied_now.push(migration.name);
+    }
+
+    transaction.commit().await?;
+
+    Ok(applied_now)
+}
+
+/// Summary of migrating all tenants
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct MigrateTenantsSummary {
+    /// Number of tenants that were migrated successfully
+    pub tenants_processed: usize,
+    /// Number of tenants that were not migrated as the deadline was reached
+    pub tenants_skipped: usize,
+    /// Number of migrations applied across all tenants
+    pub migrations_applied: usize,
+    /// Tenants that failed to migrate
+    pub failures: Vec<TenantFailure>,
+}
+
+/// Applies the migrations to all tenant databases matching the `options`, the
+/// root database migrations are applied separately by [apply_root_migrations]
+#[tracing::instrument(skip_all)]
+pub async fn migrate_tenants(
+    db_cache: &DatabasePoolCache,
+    options: &TenantSweepOptions,
+) -> Result<MigrateTenantsSummary, TenantSweepError> {
+    let sweep = sweep_tenants(db_cache, options, |_tenant, db| async move {
+        apply_tenant_migrations(&db).await
+    })
+    .await?;
+
+    Ok(MigrateTenantsSummary {
+        tenants_processed: sweep.completed.len(),
+        tenants_skipped: sweep.skipped,
+        migrations_applied: sweep
+            .completed
+            .iter()
+            .map(|(_, applied)| applied.len())
+            .sum(),
+        failures: sweep.failures,
+    })
+}
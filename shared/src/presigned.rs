@@ -0,0 +1,245 @@
+//! Purging of expired presigned upload tasks and their incomplete uploads
+
+use crate::{
+    database::presigned_upload_task::find_presigned_tasks_multipart_upload_ids,
+    s3::{S3StorageFactory, TenantS3Storage},
+    tenant_sweep::{TenantFailure, TenantSweepError, TenantSweepOptions, sweep_tenants},
+};
+use chrono::{DateTime, Utc};
+use docbox_database::{
+    DatabasePoolCache, DbPool, DbResult,
+    models::presigned_upload_task::{
+        PresignedTaskStatus, PresignedUploadTask, PresignedUploadTaskId,
+    },
+};
+use docbox_storage::{StorageLayerFactory, TenantStorageLayer};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// Summary of a presigned task purge
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PurgeExpiredPresignedOutput {
+    /// Number of expired tasks that were deleted (or would be deleted
+    /// when performing a dry run)
+    pub tasks_deleted: usize,
+    /// Number of incomplete uploads that were deleted from storage (or
+    /// would be deleted when performing a dry run)
+    pub objects_deleted: usize,
+    /// Number of incomplete multipart uploads that were aborted (or
+    /// would be aborted when performing a dry run)
+    pub multipart_uploads_aborted: usize,
+    /// Expired tasks that would be purged, only populated for dry runs
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tasks: Vec<ExpiredPresignedTask>,
+}
+
+/// Expired presigned task that would be purged
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiredPresignedTask {
+    pub task_id: PresignedUploadTaskId,
+    pub expires_at: DateTime<Utc>,
+    /// Storage key of the uploaded file
+    pub file_key: String,
+    /// Whether the upload is incomplete and the file would be deleted from storage
+    pub delete_object: bool,
+    /// Whether the upload is an incomplete multipart upload that would be aborted
+    pub abort_multipart: bool,
+}
+
+/// Options for purging the presigned tasks of a single tenant
+#[derive(Debug, Clone, Copy)]
+pub struct PurgeTenantOptions {
+    /// Tasks that expired before this date are purged
+    pub expired_before: DateTime<Utc>,
+    /// Only report the tasks and files that would be purged
+    pub dry_run: bool,
+}
+
+impl Default for PurgeTenantOptions {
+    fn default() -> Self {
+        Self {
+            expired_before: Utc::now(),
+            dry_run: false,
+        }
+    }
+}
+
+/// Options for purging the presigned tasks of all tenants
+#[derive(Debug, Clone, Default)]
+pub struct PurgeExpiredPresignedOptions {
+    /// Options for which tenants to purge
+    pub sweep: TenantSweepOptions,
+    /// Options for purging each tenant
+    pub tenant_options: PurgeTenantOptions,
+}
+
+/// Summary of purging the presigned tasks for all tenants
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PurgeExpiredPresignedSummary {
+    /// Number of tenants that were purged successfully
+    pub tenants_processed: usize,
+    /// Number of tenants that were not purged as the deadline was reached
+    pub tenants_skipped: usize,
+    /// Number of expired tasks that were deleted
+    pub tasks_deleted: usize,
+    /// Number of incomplete uploads that were deleted from storage
+    pub objects_deleted: usize,
+    /// Number of incomplete multipart uploads that were aborted
+    pub multipart_uploads_aborted: usize,
+    /// Tenants that failed to purge
+    pub failures: Vec<TenantFailure>,
+    /// Whether this was a dry run where nothing was deleted
+    pub dry_run: bool,
+    /// Expired tasks that would be purged, only populated for dry runs
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tasks: Vec<TenantExpiredPresignedTask>,
+}
+
+/// Expired presigned task within a specific tenant
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantExpiredPresignedTask {
+    pub tenant_id: Uuid,
+    pub tenant_env: String,
+    #[serde(flatten)]
+    pub task: ExpiredPresignedTask,
+}
+
+/// Purge the presigned tasks for all tenants
+///
+/// Tenants are purged concurrently, a failure to purge one tenant
+/// does not prevent the other tenants from being purged
+#[tracing::instrument(skip_all)]
+pub async fn purge_expired_presigned_tasks(
+    db_cache: &DatabasePoolCache,
+    storage: &StorageLayerFactory,
+    s3: &S3StorageFactory,
+    options: PurgeExpiredPresignedOptions,
+) -> Result<PurgeExpiredPresignedSummary, TenantSweepError> {
+    let tenant_options = options.tenant_options;
+
+    let sweep = sweep_tenants(db_cache, &options.sweep, |tenant, db| async move {
+        let storage = storage.create_storage_layer(&tenant);
+        let s3 = s3.create_s3_storage(&tenant);
+        purge_expired_presigned_tasks_tenant(&db, &storage, &s3, tenant_options).await
+    })
+    .await?;
+
+    let mut summary = PurgeExpiredPresignedSummary {
+        tenants_processed: sweep.completed.len(),
+        tenants_skipped: sweep.skipped,
+        failures: sweep.failures,
+        dry_run: tenant_options.dry_run,
+        ..Default::default()
+    };
+
+    for (tenant, output) in sweep.completed {
+        summary.tasks_deleted += output.tasks_deleted;
+        summary.objects_deleted += output.objects_deleted;
+        summary.multipart_uploads_aborted += output.multipart_uploads_aborted;
+        summary.tasks.extend(
+            output
+                .tasks
+                .into_iter()
+                .map(|task| TenantExpiredPresignedTask {
+                    tenant_id: tenant.id,
+                    tenant_env: tenant.env.clone(),
+                    task,
+                }),
+        );
+    }
+
+    Ok(summary)
+}
+
+/// Purge the presigned tasks for a specific tenant
+pub async fn purge_expired_presigned_tasks_tenant(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    s3: &TenantS3Storage,
+    options: PurgeTenantOptions,
+) -> DbResult<PurgeExpiredPresignedOutput> {
+    let mut output = PurgeExpiredPresignedOutput::default();
+
+    let tasks = PresignedUploadTask::find_expired(db, options.expired_before).await?;
+    if tasks.is_empty() {
+        return Ok(output);
+    }
+
+    // Upload IDs are loaded before deleting the tasks as they are removed along with the task
+    let task_ids: Vec<PresignedUploadTaskId> = tasks.iter().map(|task| task.id).collect();
+    let mut upload_ids = find_presigned_tasks_multipart_upload_ids(db, &task_ids).await?;
+
+    if options.dry_run {
+        output.tasks = tasks
+            .into_iter()
+            .map(|task| {
+                let incomplete = !matches!(task.status, PresignedTaskStatus::Completed { .. });
+
+                ExpiredPresignedTask {
+                    task_id: task.id,
+                    expires_at: task.expires_at,
+                    delete_object: incomplete,
+                    abort_multipart: incomplete && upload_ids.contains_key(&task.id),
+                    file_key: task.file_key,
+                }
+            })
+            .collect();
+        output.tasks_deleted = output.tasks.len();
+        output.objects_deleted = output
+            .tasks
+            .iter()
+            .filter(|task| task.delete_object)
+            .count();
+        output.multipart_uploads_aborted = output
+            .tasks
+            .iter()
+            .filter(|task| task.abort_multipart)
+            .count();
+        return Ok(output);
+    }
+
+    for task in tasks {
+        let upload_id = upload_ids.remove(&task.id);
+
+        // Delete the task itself
+        match PresignedUploadTask::delete(db, task.id).await {
+            Ok(_) => output.tasks_deleted += 1,
+            Err(error) => {
+                tracing::error!(?error, "failed to delete presigned upload task");
+            }
+        }
+
+        // Delete incomplete file uploads
+        match task.status {
+            PresignedTaskStatus::Completed { .. } => {
+                // Upload completed, nothing to revert
+            }
+            PresignedTaskStatus::Failed { .. } | PresignedTaskStatus::Pending => {
+                // Stale multipart uploads must be aborted to remove their uploaded parts
+                if let Some(upload_id) = &upload_id {
+                    match s3.abort_multipart_upload(&task.file_key, upload_id).await {
+                        Ok(_) => output.multipart_uploads_aborted += 1,
+                        Err(error) => {
+                            tracing::error!(
+                                ?error,
+                                "failed to abort expired presigned task multipart upload"
+                            );
+                        }
+                    }
+                }
+
+                match storage.delete_file(&task.file_key).await {
+                    Ok(_) => output.objects_deleted += 1,
+                    Err(error) => {
+                        tracing::error!(
+                            ?error,
+                            "failed to delete expired presigned task file from tenant"
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
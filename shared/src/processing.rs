@@ -0,0 +1,114 @@
+//! Processing of file contents to create the generated files (thumbnails,
+//! pdf, text content) and the text used for the search index
+
+use crate::{
+    checksum::content_hash,
+    database::generated_file::{create_generated_file, delete_generated_file},
+};
+use bytes::Bytes;
+use chrono::Utc;
+use docbox_database::{
+    DbPool,
+    models::{
+        file::FileId,
+        generated_file::{CreateGeneratedFile, GeneratedFile},
+    },
+};
+use docbox_processing::{ProcessingLayer, process_file};
+use docbox_search::models::DocumentPage;
+use docbox_storage::TenantStorageLayer;
+use mime::Mime;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum ProcessStoredFileError {
+    #[error("failed to process file")]
+    Processing,
+
+    #[error("failed to store generated file")]
+    StoreGeneratedFile,
+}
+
+/// Processes the contents of a file stored at `file_key` and stores the
+/// resulting generated files for the file `file_id`, provides back the
+/// pages of text content for the search index
+pub async fn process_stored_file(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    processing: &ProcessingLayer,
+    file_id: FileId,
+    file_key: &str,
+    bytes: Bytes,
+    mime: &Mime,
+) -> Result<Option<Vec<DocumentPage>>, ProcessStoredFileError> {
+    let processing_output = match process_file(&None, processing, bytes, mime).await {
+        Ok(Some(value)) => value,
+        // File type does not produce any generated files
+        Ok(None) => return Ok(None),
+        Err(error) => {
+            tracing::error!(?error, "failed to process file");
+            return Err(ProcessStoredFileError::Processing);
+        }
+    };
+
+    let pages = processing_output
+        .index_metadata
+        .and_then(|index_metadata| index_metadata.pages);
+
+    for upload in processing_output.upload_queue {
+        let generated_id = Uuid::new_v4();
+        let generated_key = format!("{file_key}/generated/{generated_id}");
+        let hash = content_hash(&upload.bytes);
+
+        storage
+            .upload_file(&generated_key, upload.mime.to_string(), upload.bytes)
+            .await
+            .map_err(|error| {
+                tracing::error!(?error, "failed to upload generated file");
+                ProcessStoredFileError::StoreGeneratedFile
+            })?;
+
+        create_generated_file(
+            db,
+            CreateGeneratedFile {
+                id: generated_id,
+                file_id,
+                mime: upload.mime.to_string(),
+                ty: upload.ty,
+                hash,
+                file_key: generated_key,
+                created_at: Utc::now(),
+            },
+        )
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to create generated file");
+            ProcessStoredFileError::StoreGeneratedFile
+        })?;
+    }
+
+    Ok(pages)
+}
+
+/// Deletes the `generated` files of a file after they have been replaced by newly
+/// generated files, objects that fail to delete are removed by storage reconciliation
+pub async fn delete_generated_files(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    generated: Vec<GeneratedFile>,
+) {
+    for generated in generated {
+        if let Err(error) = delete_generated_file(db, generated.id).await {
+            tracing::error!(?error, "failed to delete previous generated file");
+            continue;
+        }
+
+        if let Err(error) = storage.delete_file(&generated.file_key).await {
+            tracing::error!(
+                ?error,
+                "failed to delete previous generated file from storage"
+            );
+        }
+    }
+}
@@ -0,0 +1,301 @@
+//! Reconciliation of tenant storage against the tenant database to find and
+//! remove orphaned objects that are no longer referenced by anything
+
+use crate::{
+    s3::{S3StorageFactory, TenantS3Storage},
+    tenant_sweep::{TenantFailure, TenantSweepError, TenantSweepOptions, sweep_tenants},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use docbox_database::{DatabasePoolCache, DbPool};
+use docbox_storage::{StorageLayerFactory, TenantStorageLayer};
+use futures::TryStreamExt;
+use serde::Serialize;
+use std::collections::HashSet;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Queries for the storage keys referenced by each table that owns objects in
+/// storage. Rows are never filtered so files in the trash keep their objects
+/// until the rows themselves are removed
+const REFERENCED_KEY_QUERIES: &[&str] = &[
+    r#"SELECT "file_key" FROM "docbox_files""#,
+    r#"SELECT "file_key" FROM "docbox_generated_files""#,
+    r#"SELECT "file_key" FROM "docbox_presigned_upload_tasks""#,
+];
+
+#[derive(Debug, Error)]
+pub enum ReconcileStorageError {
+    #[error("failed to list storage objects")]
+    ListObjects,
+
+    #[error("failed to query referenced storage keys")]
+    QueryReferences,
+}
+
+/// Options for reconciling the storage of a single tenant
+#[derive(Debug, Clone, Copy)]
+pub struct ReconcileTenantOptions {
+    /// Objects modified more recently than this are never considered orphaned,
+    /// this prevents removing objects for uploads that are still in progress
+    pub grace_period: TimeDelta,
+    /// Only report the orphaned objects without deleting them, reconciling
+    /// defaults to a dry run
+    pub dry_run: bool,
+}
+
+impl Default for ReconcileTenantOptions {
+    fn default() -> Self {
+        Self {
+            grace_period: TimeDelta::hours(24),
+            dry_run: true,
+        }
+    }
+}
+
+/// Object in storage that is not referenced by the tenant database
+#[derive(Debug, Clone, Serialize)]
+pub struct OrphanedObject {
+    pub key: String,
+    pub last_modified: DateTime<Utc>,
+}
+
+/// Summary of reconciling the storage of a single tenant
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ReconcileStorageOutput {
+    /// Number of objects in storage
+    pub objects_scanned: usize,
+    /// Objects that are not referenced by the tenant database
+    pub orphaned_objects: Vec<OrphanedObject>,
+    /// Number of orphaned objects that were deleted
+    pub objects_deleted: usize,
+}
+
+/// Reconciles the storage of a tenant, objects older than the grace period that
+/// are not referenced by a file (including files in the trash), generated file
+/// or presigned upload are orphaned
+#[tracing::instrument(skip_all)]
+pub async fn reconcile_tenant_storage(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    s3: &TenantS3Storage,
+    options: ReconcileTenantOptions,
+) -> Result<ReconcileStorageOutput, ReconcileStorageError> {
+    // Storage is listed before querying the database so that objects for files
+    // created while reconciling will be referenced by the time they are checked
+    let objects = s3.list_objects().await.map_err(|error| {
+        tracing::error!(?error, "failed to list storage objects");
+        ReconcileStorageError::ListObjects
+    })?;
+
+    let referenced_keys = referenced_storage_keys(db).await?;
+    let cutoff = Utc::now() - options.grace_period;
+
+    let objects_scanned = objects.len();
+    let objects = objects
+        .into_iter()
+        .map(|object| (object.key, object.last_modified));
+    let orphaned_objects = find_orphaned_objects(objects, &referenced_keys, cutoff);
+
+    let mut output = ReconcileStorageOutput {
+        objects_scanned,
+        ..Default::default()
+    };
+
+    if !options.dry_run {
+        for object in &orphaned_objects {
+            match storage.delete_file(&object.key).await {
+                Ok(_) => output.objects_deleted += 1,
+                Err(error) => {
+                    tracing::error!(?error, key = %object.key, "failed to delete orphaned object");
+                }
+            }
+        }
+    }
+
+    output.orphaned_objects = orphaned_objects;
+
+    Ok(output)
+}
+
+/// Finds the objects (key and last modified date) that are not referenced and
+/// were last modified before the `cutoff`, objects without a modified date are
+/// treated as recent
+fn find_orphaned_objects(
+    objects: impl IntoIterator<Item = (String, Option<DateTime<Utc>>)>,
+    referenced_keys: &HashSet<String>,
+    cutoff: DateTime<Utc>,
+) -> Vec<OrphanedObject> {
+    objects
+        .into_iter()
+        .filter(|(key, _)| !referenced_keys.contains(key))
+        .filter_map(|(key, last_modified)| match last_modified {
+            Some(last_modified) if last_modified < cutoff => {
+                Some(OrphanedObject { key, last_modified })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Collects the storage keys of every file (including files in the trash),
+/// generated file and presigned upload within the tenant database
+async fn referenced_storage_keys(db: &DbPool) -> Result<HashSet<String>, ReconcileStorageError> {
+    let mut keys = HashSet::new();
+
+    for query in REFERENCED_KEY_QUERIES {
+        let mut rows = sqlx::query_scalar::<_, String>(query).fetch(db);
+
+        while let Some(key) = rows.try_next().await.map_err(|error| {
+            tracing::error!(?error, query, "failed to query referenced storage keys");
+            ReconcileStorageError::QueryReferences
+        })? {
+            keys.insert(key);
+        }
+    }
+
+    Ok(keys)
+}
+
+/// Options for reconciling the storage of all tenants
+#[derive(Debug, Clone, Default)]
+pub struct ReconcileStorageOptions {
+    /// Options for which tenants to reconcile
+    pub sweep: TenantSweepOptions,
+    /// Options for reconciling each tenant
+    pub tenant_options: ReconcileTenantOptions,
+}
+
+/// Summary of reconciling the storage of all tenants
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ReconcileStorageSummary {
+    /// Number of tenants that were reconciled successfully
+    pub tenants_processed: usize,
+    /// Number of tenants that were not reconciled as the deadline was reached
+    pub tenants_skipped: usize,
+    /// Number of objects in storage across all tenants
+    pub objects_scanned: usize,
+    /// Number of orphaned objects that were deleted
+    pub objects_deleted: usize,
+    /// Tenants that failed to reconcile
+    pub failures: Vec<TenantFailure>,
+    /// Whether this was a dry run where nothing was deleted
+    pub dry_run: bool,
+    /// Orphaned objects that were found
+    pub orphaned_objects: Vec<TenantOrphanedObject>,
+}
+
+/// Orphaned object within a specific tenant
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantOrphanedObject {
+    pub tenant_id: Uuid,
+    pub tenant_env: String,
+    #[serde(flatten)]
+    pub object: OrphanedObject,
+}
+
+/// Reconcile the storage for all tenants
+#[tracing::instrument(skip_all)]
+pub async fn reconcile_storage(
+    db_cache: &DatabasePoolCache,
+    storage: &StorageLayerFactory,
+    s3: &S3StorageFactory,
+    options: ReconcileStorageOptions,
+) -> Result<ReconcileStorageSummary, TenantSweepError> {
+    let tenant_options = options.tenant_options;
+
+    let sweep = sweep_tenants(db_cache, &options.sweep, |tenant, db| async move {
+        let storage = storage.create_storage_layer(&tenant);
+        let s3 = s3.create_s3_storage(&tenant);
+        reconcile_tenant_storage(&db, &storage, &s3, tenant_options).await
+    })
+    .await?;
+
+    let mut summary = ReconcileStorageSummary {
+        tenants_processed: sweep.completed.len(),
+        tenants_skipped: sweep.skipped,
+        failures: sweep.failures,
+        dry_run: tenant_options.dry_run,
+        ..Default::default()
+    };
+
+    for (tenant, output) in sweep.completed {
+        summary.objects_scanned += output.objects_scanned;
+        summary.objects_deleted += output.objects_deleted;
+        summary
+            .orphaned_objects
+            .extend(
+                output
+                    .orphaned_objects
+                    .into_iter()
+                    .map(|object| TenantOrphanedObject {
+                        tenant_id: tenant.id,
+                        tenant_env: tenant.env.clone(),
+                        object,
+                    }),
+            );
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HashSet, TimeDelta, Utc, find_orphaned_objects};
+
+    fn keys(values: &[&str]) -> HashSet<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn referenced_keys_are_never_orphaned() {
+        let cutoff = Utc::now();
+        let old = Some(cutoff - TimeDelta::days(30));
+
+        // Keys for a file in the trash, a generated file and a pending
+        // presigned upload
+        let referenced = keys(&[
+            "box/trashed-file",
+            "box/generated-file",
+            "box/presigned-upload",
+        ]);
+
+        let objects = referenced
+            .iter()
+            .map(|key| (key.clone(), old))
+            .collect::<Vec<_>>();
+
+        assert!(find_orphaned_objects(objects, &referenced, cutoff).is_empty());
+    }
+
+    #[test]
+    fn unreferenced_keys_are_orphaned() {
+        let cutoff = Utc::now();
+        let old = cutoff - TimeDelta::days(30);
+        let referenced = keys(&["box/file"]);
+
+        let objects = vec![
+            ("box/file".to_string(), Some(old)),
+            ("box/orphan".to_string(), Some(old)),
+        ];
+
+        let orphaned = find_orphaned_objects(objects, &referenced, cutoff);
+
+        assert_eq!(orphaned.len(), 1);
+        assert_eq!(orphaned[0].key, "box/orphan");
+        assert_eq!(orphaned[0].last_modified, old);
+    }
+
+    #[test]
+    fn recent_objects_are_not_orphaned() {
+        let cutoff = Utc::now();
+        let referenced = keys(&[]);
+
+        let objects = vec![
+            ("box/recent".to_string(), Some(cutoff + TimeDelta::hours(1))),
+            ("box/at-cutoff".to_string(), Some(cutoff)),
+            ("box/unknown".to_string(), None),
+        ];
+
+        assert!(find_orphaned_objects(objects, &referenced, cutoff).is_empty());
+    }
+}
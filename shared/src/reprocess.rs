@@ -0,0 +1,247 @@
+//! Reprocessing of files that were stored with an unknown (application/octet-stream)
+//! mime type, attempts to determine the real mime type and process the file
+
+use crate::{
+    database::file::set_file_mime,
+    document_box::DocumentBoxWalk,
+    processing::{ProcessStoredFileError, delete_generated_files, process_stored_file},
+    search_index::file_search_data,
+    storage::load_storage_file,
+};
+use bytes::Bytes;
+use docbox_core::utils::file::get_file_name_ext;
+use docbox_database::{
+    DbPool,
+    models::{
+        file::{File, FileId},
+        generated_file::GeneratedFile,
+    },
+};
+use docbox_processing::ProcessingLayer;
+use docbox_search::TenantSearchIndex;
+use docbox_storage::TenantStorageLayer;
+use mime::Mime;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ReprocessFilesError {
+    #[error("failed to query document box contents")]
+    Database,
+}
+
+#[derive(Debug, Error)]
+enum ReprocessFileError {
+    #[error("file no longer exists")]
+    UnknownFile,
+
+    #[error("failed to query file")]
+    QueryFile,
+
+    #[error("failed to load file from storage")]
+    LoadFile,
+
+    #[error("failed to process file")]
+    Processing,
+
+    #[error("failed to store generated file")]
+    StoreGeneratedFile,
+
+    #[error("failed to update file")]
+    UpdateFile,
+
+    #[error("failed to update search index")]
+    SearchIndex,
+}
+
+/// Number of items to check for each page of reprocessing, kept small as
+/// each application/octet-stream file within the page is processed
+const REPROCESS_PAGE_SIZE: usize = 50;
+
+/// Maximum number of failed files to keep in the output, the output is carried in
+/// every continuation message so it must stay bounded regardless of the number of
+/// files within the document box
+const MAX_REPORTED_FAILURES: usize = 50;
+
+/// Summary of the reprocessing
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReprocessFilesOutput {
+    /// Number of files that were updated to a new mime type and processed
+    pub updated: usize,
+    /// Number of files where the file type could not be determined,
+    /// these files were left unchanged
+    pub unchanged: usize,
+    /// Number of files that failed to reprocess
+    pub failed: usize,
+    /// Sample of the files that failed to reprocess, limited to the
+    /// first [MAX_REPORTED_FAILURES] failures
+    pub failures: Vec<ReprocessFileFailure>,
+}
+
+/// File that failed to reprocess
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReprocessFileFailure {
+    /// ID of the file
+    pub file_id: FileId,
+    /// Name of the file
+    pub name: String,
+    /// Reason the file failed to reprocess
+    pub error: String,
+}
+
+/// Progress of reprocessing the files within a document box, files are reprocessed
+/// in pages so that large document boxes can be reprocessed across multiple invocations
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReprocessFilesProgress {
+    /// Position within the document box
+    pub walk: DocumentBoxWalk,
+    /// Results for the files reprocessed so far
+    pub output: ReprocessFilesOutput,
+}
+
+impl ReprocessFilesProgress {
+    /// Whether every file within the document box has been checked
+    pub fn is_complete(&self) -> bool {
+        self.walk.is_complete()
+    }
+}
+
+/// Reprocess the application/octet-stream files within the next page of the
+/// document box `scope`, advancing the `progress` of the reprocessing
+#[tracing::instrument(skip(db, search, storage, processing, progress))]
+pub async fn reprocess_octet_stream_files_page(
+    db: &DbPool,
+    search: &TenantSearchIndex,
+    storage: &TenantStorageLayer,
+    processing: &ProcessingLayer,
+    scope: &str,
+    progress: &mut ReprocessFilesProgress,
+) -> Result<(), ReprocessFilesError> {
+    let contents = progress
+        .walk
+        .next_page(db, scope, REPROCESS_PAGE_SIZE)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to load document box contents");
+            ReprocessFilesError::Database
+        })?;
+
+    let octet_stream = mime::APPLICATION_OCTET_STREAM.essence_str();
+
+    for file in contents.files {
+        if file.mime != octet_stream {
+            continue;
+        }
+
+        let output = &mut progress.output;
+
+        match reprocess_file(db, search, storage, processing, scope, file.id).await {
+            Ok(Some(mime)) => {
+                tracing::debug!(file_id = %file.id, %mime, "reprocessed file");
+                output.updated += 1;
+            }
+            Ok(None) => output.unchanged += 1,
+            Err(error) => {
+                tracing::error!(?error, file_id = %file.id, "failed to reprocess file");
+                output.failed += 1;
+
+                if output.failures.len() < MAX_REPORTED_FAILURES {
+                    output.failures.push(ReprocessFileFailure {
+                        file_id: file.id,
+                        name: file.name,
+                        error: error.to_string(),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reprocess a specific file, provides back the new file mime if
+/// one could be determined
+async fn reprocess_file(
+    db: &DbPool,
+    search: &TenantSearchIndex,
+    storage: &TenantStorageLayer,
+    processing: &ProcessingLayer,
+    scope: &str,
+    file_id: FileId,
+) -> Result<Option<Mime>, ReprocessFileError> {
+    let file = File::find(db, &scope.to_string(), file_id)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to query file");
+            ReprocessFileError::QueryFile
+        })?
+        .ok_or(ReprocessFileError::UnknownFile)?;
+
+    let bytes = load_storage_file(storage, &file.file_key)
+        .await
+        .map(Bytes::from)
+        .map_err(|_| ReprocessFileError::LoadFile)?;
+
+    let mime = match sniff_mime(&file.name, &bytes) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    // Generated files from any previous attempt are replaced by the newly generated files
+    let previous_generated = GeneratedFile::find_all(db, file.id)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to query generated files");
+            ReprocessFileError::QueryFile
+        })?;
+
+    let pages = process_stored_file(
+        db,
+        storage,
+        processing,
+        file.id,
+        &file.file_key,
+        bytes,
+        &mime,
+    )
+    .await
+    .map_err(|error| match error {
+        ProcessStoredFileError::Processing => ReprocessFileError::Processing,
+        ProcessStoredFileError::StoreGeneratedFile => ReprocessFileError::StoreGeneratedFile,
+    })?;
+
+    let file = set_file_mime(db, file, mime.to_string())
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to update file mime");
+            ReprocessFileError::UpdateFile
+        })?;
+
+    delete_generated_files(db, storage, previous_generated).await;
+
+    // Replace the existing search index data for the file
+    search.delete_data(file.id).await.map_err(|error| {
+        tracing::error!(?error, "failed to remove file search index data");
+        ReprocessFileError::SearchIndex
+    })?;
+
+    search
+        .add_data(vec![file_search_data(scope, &file, pages)])
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to add file search index data");
+            ReprocessFileError::SearchIndex
+        })?;
+
+    Ok(Some(mime))
+}
+
+/// Determines the mime type of a file from its contents, falling back to
+/// the file name extension when the contents aren't a known format
+fn sniff_mime(name: &str, bytes: &[u8]) -> Option<Mime> {
+    let content_mime = infer::get(bytes).and_then(|kind| kind.mime_type().parse::<Mime>().ok());
+
+    content_mime
+        .or_else(|| get_file_name_ext(name).and_then(|ext| mime_guess::from_ext(&ext).first()))
+        .filter(|mime| *mime != mime::APPLICATION_OCTET_STREAM)
+}
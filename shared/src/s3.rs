@@ -0,0 +1,382 @@
+//! Direct S3 access to tenant storage for the operations that the docbox storage
+//! layer does not support, multipart uploads, presigned uploads that must
+//! match a checksum, presigned downloads with response header overrides,
+//! reading ranges of objects and listing stored objects
+
+use crate::checksum::{ChecksumAlgorithm, UploadChecksum};
+use aws_config::SdkConfig;
+use aws_sdk_s3::{
+    Client,
+    presigning::{PresignedRequest, PresigningConfig},
+    types::{CompletedMultipartUpload, CompletedPart},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use docbox_database::models::tenant::Tenant;
+use docbox_storage::FileStream;
+use std::{pin::Pin, time::Duration};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum S3StorageError {
+    #[error("failed to create multipart upload")]
+    CreateUpload(#[source] aws_sdk_s3::Error),
+
+    #[error("storage did not provide a multipart upload id")]
+    MissingUploadId,
+
+    #[error("invalid presigned request expiry")]
+    InvalidExpiry(#[source] aws_sdk_s3::presigning::PresigningConfigError),
+
+    #[error("failed to presign upload")]
+    PresignUpload(#[source] aws_sdk_s3::Error),
+
+    #[error("failed to presign download")]
+    PresignDownload(#[source] aws_sdk_s3::Error),
+
+    #[error("invalid presigned download expiry")]
+    InvalidDownloadExpiry,
+
+    #[error("failed to presign upload part")]
+    PresignPart(#[source] aws_sdk_s3::Error),
+
+    #[error("failed to list multipart upload parts")]
+    ListParts(#[source] aws_sdk_s3::Error),
+
+    #[error("failed to complete multipart upload")]
+    CompleteUpload(#[source] aws_sdk_s3::Error),
+
+    #[error("failed to abort multipart upload")]
+    AbortUpload(#[source] aws_sdk_s3::Error),
+
+    #[error("failed to list objects")]
+    ListObjects(#[source] aws_sdk_s3::Error),
+
+    #[error("failed to get object")]
+    GetObject(#[source] aws_sdk_s3::Error),
+
+    #[error("failed to get object metadata")]
+    HeadObject(#[source] aws_sdk_s3::Error),
+}
+
+/// Factory for creating the S3 storage of each tenant
+#[derive(Clone)]
+pub struct S3StorageFactory {
+    client: Client,
+}
+
+impl S3StorageFactory {
+    /// Create the factory from the AWS configuration, custom endpoints can be
+    /// configured using the standard `AWS_ENDPOINT_URL_S3` variable
+    pub fn from_config(aws_config: &SdkConfig) -> Self {
+        Self {
+            client: Client::new(aws_config),
+        }
+    }
+
+    /// Create the S3 storage for the `tenant` bucket
+    pub fn create_s3_storage(&self, tenant: &Tenant) -> TenantS3Storage {
+        TenantS3Storage {
+            client: self.client.clone(),
+            bucket: tenant.s3_name.clone(),
+        }
+    }
+}
+
+/// Part of a multipart upload that has been uploaded to storage
+#[derive(Debug, Clone)]
+pub struct UploadedPart {
+    pub part_number: i32,
+    pub etag: String,
+    pub size: i64,
+}
+
+/// Overrides for the response headers of a presigned download
+#[derive(Debug, Clone, Default)]
+pub struct PresignedDownloadOptions {
+    /// Content type to serve the file with
+    pub content_type: Option<String>,
+    /// Content disposition to serve the file with
+    pub content_disposition: Option<String>,
+}
+
+/// Object stored within the bucket of a tenant
+#[derive(Debug, Clone)]
+pub struct StoredObject {
+    pub key: String,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Direct S3 access to the bucket of a specific tenant
+#[derive(Clone)]
+pub struct TenantS3Storage {
+    client: Client,
+    bucket: String,
+}
+
+impl TenantS3Storage {
+    /// Creates a presigned request for uploading the file at `key` that expires
+    /// after `expires_in`, the `checksum` is signed into the request so storage
+    /// rejects uploads that do not match it
+    pub async fn create_presigned_upload(
+        &self,
+        key: &str,
+        mime: &str,
+        size: i64,
+        checksum: &UploadChecksum,
+        expires_in: Duration,
+    ) -> Result<PresignedRequest, S3StorageError> {
+        let config =
+            PresigningConfig::expires_in(expires_in).map_err(S3StorageError::InvalidExpiry)?;
+
+        let request = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(mime)
+            .content_length(size);
+
+        let request = match checksum.algorithm {
+            ChecksumAlgorithm::Sha256 => request.checksum_sha256(&checksum.value),
+            ChecksumAlgorithm::Crc32c => request.checksum_crc32_c(&checksum.value),
+            ChecksumAlgorithm::Md5 => request.content_md5(&checksum.value),
+        };
+
+        request
+            .presigned(config)
+            .await
+            .map_err(|error| S3StorageError::PresignUpload(error.into()))
+    }
+
+    /// Creates a presigned request for downloading the file at `key` that expires
+    /// after `expires_in`, the response headers are overridden using `options`
+    pub async fn create_presigned_download(
+        &self,
+        key: &str,
+        expires_in: Duration,
+        options: PresignedDownloadOptions,
+    ) -> Result<(PresignedRequest, DateTime<Utc>), S3StorageError> {
+        let expires_at = TimeDelta::from_std(expires_in)
+            .ok()
+            .and_then(|expires_in| Utc::now().checked_add_signed(expires_in))
+            .ok_or(S3StorageError::InvalidDownloadExpiry)?;
+
+        let config =
+            PresigningConfig::expires_in(expires_in).map_err(S3StorageError::InvalidExpiry)?;
+
+        let request = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_response_content_type(options.content_type)
+            .set_response_content_disposition(options.content_disposition)
+            .presigned(config)
+            .await
+            .map_err(|error| S3StorageError::PresignDownload(error.into()))?;
+
+        Ok((request, expires_at))
+    }
+
+    /// Starts a multipart upload for the file at `key`, provides back
+    /// the ID of the multipart upload
+    pub async fn create_multipart_upload(
+        &self,
+        key: &str,
+        mime: &str,
+    ) -> Result<String, S3StorageError> {
+        let output = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .content_type(mime)
+            .send()
+            .await
+            .map_err(|error| S3StorageError::CreateUpload(error.into()))?;
+
+        output.upload_id.ok_or(S3StorageError::MissingUploadId)
+    }
+
+    /// Creates a presigned request for uploading part `part_number` of the
+    /// multipart upload that expires after `expires_in`
+    pub async fn create_presigned_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<PresignedRequest, S3StorageError> {
+        let config =
+            PresigningConfig::expires_in(expires_in).map_err(S3StorageError::InvalidExpiry)?;
+
+        self.client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .presigned(config)
+            .await
+            .map_err(|error| S3StorageError::PresignPart(error.into()))
+    }
+
+    /// Lists the parts of the multipart upload that have been uploaded
+    pub async fn list_multipart_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Vec<UploadedPart>, S3StorageError> {
+        let parts = self
+            .client
+            .list_parts()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .into_paginator()
+            .items()
+            .send()
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .map_err(|error| S3StorageError::ListParts(error.into()))?;
+
+        Ok(parts
+            .into_iter()
+            .filter_map(|part| {
+                Some(UploadedPart {
+                    part_number: part.part_number?,
+                    etag: part.e_tag?,
+                    size: part.size.unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    /// Completes the multipart upload from the uploaded `parts`, the parts
+    /// must be in ascending part number order
+    pub async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: Vec<UploadedPart>,
+    ) -> Result<(), S3StorageError> {
+        let parts = parts
+            .into_iter()
+            .map(|part| {
+                CompletedPart::builder()
+                    .part_number(part.part_number)
+                    .e_tag(part.etag)
+                    .build()
+            })
+            .collect();
+
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(|error| S3StorageError::CompleteUpload(error.into()))?;
+
+        Ok(())
+    }
+
+    /// Aborts the multipart upload removing any of its uploaded parts
+    pub async fn abort_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<(), S3StorageError> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+            .map_err(|error| S3StorageError::AbortUpload(error.into()))?;
+
+        Ok(())
+    }
+
+    /// Lists every object stored within the bucket
+    pub async fn list_objects(&self) -> Result<Vec<StoredObject>, S3StorageError> {
+        let pages = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .into_paginator()
+            .send()
+            .collect::<Result<Vec<_>, _>>()
+            .await
+            .map_err(|error| S3StorageError::ListObjects(error.into()))?;
+
+        Ok(pages
+            .into_iter()
+            .flat_map(|page| page.contents.unwrap_or_default())
+            .filter_map(|object| {
+                let last_modified = object
+                    .last_modified
+                    .and_then(|value| DateTime::from_timestamp(value.secs(), value.subsec_nanos()));
+
+                Some(StoredObject {
+                    key: object.key?,
+                    last_modified,
+                })
+            })
+            .collect())
+    }
+
+    /// Gets a byte stream for the range of the file at `key` from `start`
+    /// to `end` (Inclusive)
+    pub async fn get_file_range(
+        &self,
+        key: &str,
+        start: u64,
+        end: u64,
+    ) -> Result<FileStream, S3StorageError> {
+        let object = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .range(format!("bytes={start}-{end}"))
+            .send()
+            .await
+            .map_err(|error| S3StorageError::GetObject(error.into()))?;
+
+        let mut body = object.body;
+        let stream = futures::stream::poll_fn(move |cx| {
+            Pin::new(&mut body)
+                .poll_next(cx)
+                .map_err(std::io::Error::other)
+        });
+
+        Ok(FileStream {
+            stream: Box::pin(stream),
+        })
+    }
+
+    /// Gets the size in bytes of the file at `key`
+    pub async fn get_file_size(&self, key: &str) -> Result<u64, S3StorageError> {
+        let output = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|error| S3StorageError::HeadObject(error.into()))?;
+
+        Ok(output
+            .content_length
+            .and_then(|length| u64::try_from(length).ok())
+            .unwrap_or_default())
+    }
+}
@@ -0,0 +1,320 @@
+//! Rebuilding of the search index for a document box from the data stored
+//! in the database and in storage
+
+use crate::{
+    document_box::{DocumentBoxContents, DocumentBoxWalk},
+    storage::load_storage_file,
+};
+use docbox_database::{
+    DbPool,
+    models::{
+        file::{File, FileWithExtra},
+        folder::FolderWithExtra,
+        generated_file::{GeneratedFile, GeneratedFileType},
+        link::LinkWithExtra,
+    },
+};
+use docbox_search::{
+    TenantSearchIndex,
+    models::{DocumentPage, SearchIndexData, SearchIndexType},
+};
+use docbox_storage::TenantStorageLayer;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Number of items to add to the search index at once
+const INDEX_BATCH_SIZE: usize = 100;
+
+/// Number of items to index for each page of a rebuild
+const REBUILD_PAGE_SIZE: usize = 500;
+
+/// Character used to separate pages within the text content of a file
+const PAGE_SEPARATOR: char = '\u{C}';
+
+#[derive(Debug, Error)]
+pub enum RebuildSearchIndexError {
+    #[error("failed to query document box contents")]
+    Database,
+
+    #[error("failed to load file text content")]
+    LoadTextContent,
+
+    #[error("failed to update search index")]
+    SearchIndex,
+}
+
+/// Summary of the items that were indexed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RebuildSearchIndexOutput {
+    /// Number of folders indexed
+    pub folders: usize,
+    /// Number of files indexed
+    pub files: usize,
+    /// Number of links indexed
+    pub links: usize,
+}
+
+/// Progress of rebuilding the search index of a document box, the rebuild is
+/// performed in pages so that large document boxes can be rebuilt across
+/// multiple invocations
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RebuildSearchIndexProgress {
+    /// Whether the existing index data for the document box has been removed
+    #[serde(default)]
+    pub cleared: bool,
+    /// Position within the document box
+    pub walk: DocumentBoxWalk,
+    /// Items indexed so far
+    pub output: RebuildSearchIndexOutput,
+}
+
+impl RebuildSearchIndexProgress {
+    /// Whether every item within the document box has been indexed
+    pub fn is_complete(&self) -> bool {
+        self.walk.is_complete()
+    }
+}
+
+/// Indexes the next page of folders, files and links within the document box
+/// `scope`, advancing the `progress` of the rebuild
+///
+/// The existing index data for the document box is removed before the first
+/// page is indexed so that stale entries (items that no longer exist) don't
+/// survive the rebuild. Items are not searchable until their page is indexed
+#[tracing::instrument(skip(db, search, storage, progress))]
+pub async fn rebuild_document_box_index_page(
+    db: &DbPool,
+    search: &TenantSearchIndex,
+    storage: &TenantStorageLayer,
+    scope: &str,
+    progress: &mut RebuildSearchIndexProgress,
+) -> Result<(), RebuildSearchIndexError> {
+    if !progress.cleared {
+        search.delete_by_scope(scope).await.map_err(|error| {
+            tracing::error!(?error, "failed to clear document box search index");
+            RebuildSearchIndexError::SearchIndex
+        })?;
+
+        progress.cleared = true;
+    }
+
+    let contents = progress
+        .walk
+        .next_page(db, scope, REBUILD_PAGE_SIZE)
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to load document box contents");
+            RebuildSearchIndexError::Database
+        })?;
+
+    let (index_data, output) = contents_index_data(db, storage, scope, contents).await?;
+
+    // Items are indexed by their ID, adding an item that is already
+    // indexed replaces its existing data
+    add_index_data(search, &index_data).await?;
+
+    progress.output.folders += output.folders;
+    progress.output.files += output.files;
+    progress.output.links += output.links;
+
+    Ok(())
+}
+
+/// Adds the folders, files and links in `contents` to the search index of the
+/// document box `scope` without clearing the existing index data, used when
+/// items are restored from the trash
+#[tracing::instrument(skip(db, search, storage, contents))]
+pub async fn index_document_box_contents(
+    db: &DbPool,
+    search: &TenantSearchIndex,
+    storage: &TenantStorageLayer,
+    scope: &str,
+    contents: DocumentBoxContents,
+) -> Result<RebuildSearchIndexOutput, RebuildSearchIndexError> {
+    let (index_data, output) = contents_index_data(db, storage, scope, contents).await?;
+    add_index_data(search, &index_data).await?;
+    Ok(output)
+}
+
+/// Removes the folders, files and links in `contents` from the search
+/// index, used when items are moved to the trash
+pub async fn remove_document_box_contents(
+    search: &TenantSearchIndex,
+    contents: &DocumentBoxContents,
+) -> Result<(), RebuildSearchIndexError> {
+    let item_ids = contents
+        .folders
+        .iter()
+        .map(|folder| folder.id)
+        .chain(contents.files.iter().map(|file| file.id))
+        .chain(contents.links.iter().map(|link| link.id));
+
+    for item_id in item_ids {
+        search.delete_data(item_id).await.map_err(|error| {
+            tracing::error!(?error, %item_id, "failed to remove search index data");
+            RebuildSearchIndexError::SearchIndex
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Creates the search index data for all the items in `contents`
+async fn contents_index_data(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    scope: &str,
+    contents: DocumentBoxContents,
+) -> Result<(Vec<SearchIndexData>, RebuildSearchIndexOutput), RebuildSearchIndexError> {
+    let mut output = RebuildSearchIndexOutput::default();
+    let mut index_data: Vec<SearchIndexData> = Vec::new();
+
+    for folder in contents.folders {
+        index_data.push(folder_index_data(scope, folder));
+        output.folders += 1;
+    }
+
+    for link in contents.links {
+        index_data.push(link_index_data(scope, link));
+        output.links += 1;
+    }
+
+    for file in contents.files {
+        let data = file_index_data(db, storage, scope, file).await?;
+        index_data.push(data);
+        output.files += 1;
+    }
+
+    Ok((index_data, output))
+}
+
+/// Adds the `index_data` to the search index in batches
+async fn add_index_data(
+    search: &TenantSearchIndex,
+    index_data: &[SearchIndexData],
+) -> Result<(), RebuildSearchIndexError> {
+    for chunk in index_data.chunks(INDEX_BATCH_SIZE) {
+        search.add_data(chunk.to_vec()).await.map_err(|error| {
+            tracing::error!(?error, "failed to add search index data");
+            RebuildSearchIndexError::SearchIndex
+        })?;
+    }
+
+    Ok(())
+}
+
+fn folder_index_data(scope: &str, folder: FolderWithExtra) -> SearchIndexData {
+    SearchIndexData {
+        ty: SearchIndexType::Folder,
+        item_id: folder.id,
+        // Folders are indexed within their parent folder
+        folder_id: folder.folder_id.unwrap_or(folder.id),
+        name: folder.name,
+        mime: None,
+        content: None,
+        pages: None,
+        created_at: folder.created_at,
+        created_by: folder.created_by.0.map(|user| user.id),
+        document_box: scope.to_string(),
+    }
+}
+
+fn link_index_data(scope: &str, link: LinkWithExtra) -> SearchIndexData {
+    SearchIndexData {
+        ty: SearchIndexType::Link,
+        item_id: link.id,
+        folder_id: link.folder_id,
+        name: link.name,
+        mime: None,
+        content: Some(link.value),
+        pages: None,
+        created_at: link.created_at,
+        created_by: link.created_by.0.map(|user| user.id),
+        document_box: scope.to_string(),
+    }
+}
+
+async fn file_index_data(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    scope: &str,
+    file: FileWithExtra,
+) -> Result<SearchIndexData, RebuildSearchIndexError> {
+    let pages = load_file_pages(db, storage, scope, &file).await?;
+
+    Ok(SearchIndexData {
+        ty: SearchIndexType::File,
+        item_id: file.id,
+        folder_id: file.folder_id,
+        name: file.name,
+        mime: Some(file.mime),
+        content: None,
+        pages,
+        created_at: file.created_at,
+        created_by: file.created_by.0.map(|user| user.id),
+        document_box: scope.to_string(),
+    })
+}
+
+/// Creates the search index data for a `file` within the document box `scope`
+/// with the provided text `pages`
+pub fn file_search_data(
+    scope: &str,
+    file: &File,
+    pages: Option<Vec<DocumentPage>>,
+) -> SearchIndexData {
+    SearchIndexData {
+        ty: SearchIndexType::File,
+        item_id: file.id,
+        folder_id: file.folder_id,
+        name: file.name.clone(),
+        mime: Some(file.mime.clone()),
+        content: None,
+        pages,
+        created_at: file.created_at,
+        created_by: file.created_by.clone(),
+        document_box: scope.to_string(),
+    }
+}
+
+/// Loads the pages of text content extracted from the file when the
+/// file was processed, files without text content have no pages
+async fn load_file_pages(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    scope: &str,
+    file: &FileWithExtra,
+) -> Result<Option<Vec<DocumentPage>>, RebuildSearchIndexError> {
+    let text_file = GeneratedFile::find(
+        db,
+        &scope.to_string(),
+        file.id,
+        GeneratedFileType::TextContent,
+    )
+    .await
+    .map_err(|error| {
+        tracing::error!(?error, file_id = %file.id, "failed to query file text content");
+        RebuildSearchIndexError::Database
+    })?;
+
+    let text_file = match text_file {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let bytes = load_storage_file(storage, &text_file.file_key)
+        .await
+        .map_err(|_| RebuildSearchIndexError::LoadTextContent)?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    let pages = text
+        .split(PAGE_SEPARATOR)
+        .enumerate()
+        .map(|(page, content)| DocumentPage {
+            page: page as u64,
+            content: content.to_string(),
+        })
+        .collect();
+
+    Ok(Some(pages))
+}
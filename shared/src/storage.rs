@@ -0,0 +1,29 @@
+//! Helpers for accessing tenant storage
+
+use docbox_storage::TenantStorageLayer;
+use futures::TryStreamExt;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("failed to load file from storage")]
+pub struct LoadStorageFileError;
+
+/// Loads the full contents of a file from storage
+pub async fn load_storage_file(
+    storage: &TenantStorageLayer,
+    file_key: &str,
+) -> Result<Vec<u8>, LoadStorageFileError> {
+    let byte_stream = storage.get_file(file_key).await.map_err(|error| {
+        tracing::error!(?error, "failed to get file from storage");
+        LoadStorageFileError
+    })?;
+
+    byte_stream
+        .map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await
+        .map_err(|error| {
+            tracing::error!(?error, "failed to read file from storage");
+            LoadStorageFileError
+        })
+}
@@ -0,0 +1,58 @@
+//! Claims for presigned upload tasks to prevent duplicate S3 notifications
+//! from completing the same upload concurrently, and uploads from being
+//! cancelled while they are being completed
+
+use docbox_database::{DbPool, models::presigned_upload_task::PresignedUploadTaskId};
+
+/// Claims older than this many seconds are treated as abandoned (i.e. the lambda
+/// holding the claim timed out) and can be claimed again, this is longer than
+/// the maximum lambda timeout
+const CLAIM_TIMEOUT_SECONDS: f64 = 20.0 * 60.0;
+
+/// Claim on a presigned upload task, stored as a row in the tenant database so
+/// that no database connection is held while the claim is held
+///
+/// The claim must be released with [PresignedTaskLock::release], a claim that is
+/// never released (i.e. the future holding it was cancelled) expires after the
+/// claim timeout
+pub struct PresignedTaskLock {
+    task_id: PresignedUploadTaskId,
+}
+
+impl PresignedTaskLock {
+    /// Attempts to claim the task, provides back [None] if the task
+    /// is already claimed by another completion
+    pub async fn try_acquire(
+        db: &DbPool,
+        task_id: PresignedUploadTaskId,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        // Existing claims are only replaced once they have expired
+        let claimed: Option<PresignedUploadTaskId> = sqlx::query_scalar(
+            r#"INSERT INTO "docbox_serverless_presigned_task_claims" ("task_id", "claimed_at")
+            VALUES ($1, NOW())
+            ON CONFLICT ("task_id") DO UPDATE SET "claimed_at" = NOW()
+            WHERE "docbox_serverless_presigned_task_claims"."claimed_at" < NOW() - make_interval(secs => $2)
+            RETURNING "task_id""#,
+        )
+        .bind(task_id)
+        .bind(CLAIM_TIMEOUT_SECONDS)
+        .fetch_optional(db)
+        .await?;
+
+        Ok(claimed.map(|task_id| Self { task_id }))
+    }
+
+    /// Release the claim
+    pub async fn release(self, db: &DbPool) {
+        let result = sqlx::query(
+            r#"DELETE FROM "docbox_serverless_presigned_task_claims" WHERE "task_id" = $1"#,
+        )
+        .bind(self.task_id)
+        .execute(db)
+        .await;
+
+        if let Err(error) = result {
+            tracing::error!(?error, "failed to release presigned task claim");
+        }
+    }
+}
@@ -0,0 +1,213 @@
+//! Running an action across all tenants with bounded concurrency, where a
+//! failure for one tenant does not prevent the other tenants from running
+
+use docbox_database::{DatabasePoolCache, DbPool, models::tenant::Tenant};
+use futures::{StreamExt, stream};
+use serde::Serialize;
+use std::{
+    fmt::{Debug, Display},
+    future::Future,
+    time::Instant,
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum TenantSweepError {
+    #[error("failed to connect to database")]
+    ConnectDatabase,
+
+    #[error("failed to query available tenants")]
+    QueryTenants,
+}
+
+/// Options for running an action across tenants
+#[derive(Debug, Clone)]
+pub struct TenantSweepOptions {
+    /// Maximum number of tenants to run concurrently
+    pub concurrency: usize,
+    /// Time after which no more tenants will be started, tenants
+    /// that have already started are allowed to finish until `cancel_at`
+    pub deadline: Option<Instant>,
+    /// Time at which tenants that are still running are cancelled and reported
+    /// as failed, ensures the sweep provides back its results before the caller
+    /// runs out of time
+    pub cancel_at: Option<Instant>,
+    /// Only run for the tenant with this ID
+    pub tenant_id: Option<Uuid>,
+    /// Only run for tenants within this environment
+    pub tenant_env: Option<String>,
+}
+
+impl Default for TenantSweepOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 8,
+            deadline: None,
+            cancel_at: None,
+            tenant_id: None,
+            tenant_env: None,
+        }
+    }
+}
+
+impl TenantSweepOptions {
+    /// Checks if the `tenant` should be included in the sweep
+    fn includes_tenant(&self, tenant: &Tenant) -> bool {
+        self.tenant_id
+            .is_none_or(|tenant_id| tenant.id == tenant_id)
+            && self
+                .tenant_env
+                .as_ref()
+                .is_none_or(|tenant_env| tenant.env.eq(tenant_env))
+    }
+}
+
+/// Tenant that failed during a sweep
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantFailure {
+    pub tenant_id: Uuid,
+    pub tenant_env: String,
+    pub error: String,
+}
+
+/// Result of running an action across tenants
+pub struct TenantSweep<T> {
+    /// Tenants the action completed for along with the action output
+    pub completed: Vec<(Tenant, T)>,
+    /// Number of tenants that were not started as the deadline was reached
+    pub skipped: usize,
+    /// Tenants that failed
+    pub failures: Vec<TenantFailure>,
+}
+
+/// Outcome of running the action for a single tenant
+enum TenantOutcome<T> {
+    Completed(Tenant, T),
+    Skipped,
+    Failed(TenantFailure),
+}
+
+/// Runs `action` for each tenant matching the `options`
+pub async fn sweep_tenants<T, E, F, Fut>(
+    db_cache: &DatabasePoolCache,
+    options: &TenantSweepOptions,
+    action: F,
+) -> Result<TenantSweep<T>, TenantSweepError>
+where
+    F: Fn(Tenant, DbPool) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Debug + Display,
+{
+    let db = db_cache.get_root_pool().await.map_err(|error| {
+        tracing::error!(?error, "failed to connect to root database");
+        TenantSweepError::ConnectDatabase
+    })?;
+
+    let tenants = Tenant::all(&db).await.map_err(|error| {
+        tracing::error!(?error, "failed to query available tenants");
+        TenantSweepError::QueryTenants
+    })?;
+
+    // Early drop the root database pool access
+    drop(db);
+
+    let tenants = tenants
+        .into_iter()
+        .filter(|tenant| options.includes_tenant(tenant));
+
+    let outcomes: Vec<TenantOutcome<T>> = stream::iter(tenants)
+        .map(|tenant| sweep_tenant(db_cache, tenant, options, &action))
+        .buffer_unordered(options.concurrency.max(1))
+        .collect()
+        .await;
+
+    let mut sweep = TenantSweep {
+        completed: Vec::new(),
+        skipped: 0,
+        failures: Vec::new(),
+    };
+
+    for outcome in outcomes {
+        match outcome {
+            TenantOutcome::Completed(tenant, output) => sweep.completed.push((tenant, output)),
+            TenantOutcome::Skipped => sweep.skipped += 1,
+            TenantOutcome::Failed(failure) => sweep.failures.push(failure),
+        }
+    }
+
+    Ok(sweep)
+}
+
+/// Runs the `action` for a single tenant as part of a sweep
+async fn sweep_tenant<T, E, F, Fut>(
+    db_cache: &DatabasePoolCache,
+    tenant: Tenant,
+    options: &TenantSweepOptions,
+    action: &F,
+) -> TenantOutcome<T>
+where
+    F: Fn(Tenant, DbPool) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Debug + Display,
+{
+    if options
+        .deadline
+        .is_some_and(|deadline| Instant::now() >= deadline)
+    {
+        return TenantOutcome::Skipped;
+    }
+
+    let failure = |error: String| {
+        TenantOutcome::Failed(TenantFailure {
+            tenant_id: tenant.id,
+            tenant_env: tenant.env.clone(),
+            error,
+        })
+    };
+
+    // Create the database connection pool
+    let db = match db_cache.get_tenant_pool(&tenant).await {
+        Ok(value) => value,
+        Err(error) => {
+            tracing::error!(
+                ?error,
+                tenant_id = %tenant.id,
+                tenant_env = %tenant.env,
+                "failed to connect to tenant database"
+            );
+            return failure(error.to_string());
+        }
+    };
+
+    let result = match options.cancel_at {
+        Some(cancel_at) => {
+            let cancel_at = tokio::time::Instant::from_std(cancel_at);
+            match tokio::time::timeout_at(cancel_at, action(tenant.clone(), db)).await {
+                Ok(result) => result,
+                Err(_) => {
+                    tracing::warn!(
+                        tenant_id = %tenant.id,
+                        tenant_env = %tenant.env,
+                        "tenant did not finish before the deadline"
+                    );
+                    return failure("tenant did not finish before the deadline".to_string());
+                }
+            }
+        }
+        None => action(tenant.clone(), db).await,
+    };
+
+    match result {
+        Ok(output) => TenantOutcome::Completed(tenant, output),
+        Err(error) => {
+            tracing::error!(
+                ?error,
+                tenant_id = %tenant.id,
+                tenant_env = %tenant.env,
+                "failed to process tenant"
+            );
+            failure(error.to_string())
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Trash for deleted files, folders and links, items remain in the trash
+//! until they are restored or purged once the retention period has passed
+
+use crate::{
+    database::trash::{
+        count_trashed_files, count_trashed_folders, count_trashed_links, find_trashed_files,
+        find_trashed_folders, find_trashed_links,
+    },
+    tenant_sweep::{TenantFailure, TenantSweepError, TenantSweepOptions, sweep_tenants},
+};
+use chrono::{DateTime, TimeDelta, Utc};
+use docbox_core::{
+    events::{EventPublisherFactory, TenantEventPublisher},
+    files::delete_file::delete_file,
+    folders::delete_folder::delete_folder,
+    links::delete_link::delete_link,
+};
+use docbox_database::{DatabasePoolCache, DbPool, DbResult, models::document_box::DocumentBox};
+use docbox_search::{SearchIndexFactory, TenantSearchIndex};
+use docbox_storage::{StorageLayerFactory, TenantStorageLayer};
+use serde::Serialize;
+use std::time::Instant;
+
+/// Default number of days items are kept in the trash before being purged
+pub const DEFAULT_RETENTION_DAYS: i64 = 30;
+
+/// Options for purging the trash of a single tenant
+#[derive(Debug, Clone, Copy)]
+pub struct PurgeTrashTenantOptions {
+    /// Items deleted before this date are purged
+    pub deleted_before: DateTime<Utc>,
+    /// Only report the number of items that would be purged
+    pub dry_run: bool,
+    /// No further document boxes are purged once this time is reached, the
+    /// remaining document boxes are purged on the next run
+    pub deadline: Option<Instant>,
+}
+
+impl Default for PurgeTrashTenantOptions {
+    fn default() -> Self {
+        Self {
+            deleted_before: Utc::now() - TimeDelta::days(DEFAULT_RETENTION_DAYS),
+            dry_run: false,
+            deadline: None,
+        }
+    }
+}
+
+/// Summary of a trash purge
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PurgeTrashOutput {
+    /// Number of folders that were purged (or would be purged
+    /// when performing a dry run)
+    pub folders_purged: usize,
+    /// Number of files that were purged (or would be purged
+    /// when performing a dry run)
+    pub files_purged: usize,
+    /// Number of links that were purged (or would be purged
+    /// when performing a dry run)
+    pub links_purged: usize,
+    /// Whether the deadline was reached before all the document
+    /// boxes were purged
+    pub deadline_reached: bool,
+}
+
+/// Purge the items in the trash of a specific tenant that were
+/// deleted before the retention period
+pub async fn purge_trash_tenant(
+    db: &DbPool,
+    storage: &TenantStorageLayer,
+    search: &TenantSearchIndex,
+    events: &TenantEventPublisher,
+    options: PurgeTrashTenantOptions,
+) -> DbResult<PurgeTrashOutput> {
+    const PAGE_SIZE: u64 = 100;
+
+    let mut output = PurgeTrashOutput::default();
+    let mut offset = 0;
+
+    loop {
+        let document_boxes = DocumentBox::query(db, offset, PAGE_SIZE).await?;
+
+        let is_last_page = (document_boxes.len() as u64) < PAGE_SIZE;
+        offset += document_boxes.len() as u64;
+
+        for document_box in document_boxes {
+            if options
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                tracing::warn!(
+                    "deadline reached, remaining document boxes will be purged next run"
+                );
+                output.deadline_reached = true;
+                return Ok(output);
+            }
+
+            let scope = document_box.scope;
+            let deleted_before = Some(options.deleted_before);
+
+            if options.dry_run {
+                output.folders_purged +=
+                    count_trashed_folders(db, &scope, deleted_before).await? as usize;
+                output.files_purged +=
+                    count_trashed_files(db, &scope, deleted_before).await? as usize;
+                output.links_purged +=
+                    count_trashed_links(db, &scope, deleted_before).await? as usize;
+                continue;
+            }
+
+            // Purged items leave the trash so each page is queried from the start,
+            // skipping past the items that failed to purge. Files and links are
+            // purged before folders as they may be within one of the trashed folders
+            let mut failed = 0;
+            loop {
+                let files =
+                    find_trashed_files(db, &scope, deleted_before, failed, PAGE_SIZE).await?;
+                let is_last_items = (files.len() as u64) < PAGE_SIZE;
+
+                for file in files {
+                    match delete_file(db, storage, search, events, file.item, scope.clone()).await {
+                        Ok(_) => output.files_purged += 1,
+                        Err(error) => {
+                            tracing::error!(?error, "failed to purge trashed file");
+                            failed += 1;
+                        }
+                    }
+                }
+
+                if is_last_items {
+                    break;
+                }
+            }
+
+            let mut failed = 0;
+            loop {
+                let links =
+                    find_trashed_links(db, &scope, deleted_before, failed, PAGE_SIZE).await?;
+                let is_last_items = (links.len() as u64) < PAGE_SIZE;
+
+                for link in links {
+                    match delete_link(db, search, events, link.item, scope.clone()).await {
+                        Ok(_) => output.links_purged += 1,
+                        Err(error) => {
+                            tracing::error!(?error, "failed to purge trashed link");
+                            failed += 1;
+                        }
+                    }
+                }
+
+                if is_last_items {
+                    break;
+                }
+            }
+
+            let mut failed = 0;
+            loop {
+                let folders =
+                    find_trashed_folders(db, &scope, deleted_before, failed, PAGE_SIZE).await?;
+                let is_last_items = (folders.len() as u64) < PAGE_SIZE;
+
+                for folder in folders {
+                    match delete_folder(db, storage, search, events, folder.item).await {
+                        Ok(_) => output.folders_purged += 1,
+                        Err(error) => {
+                            tracing::error!(?error, "failed to purge trashed folder");
+                            failed += 1;
+                        }
+                    }
+                }
+
+                if is_last_items {
+                    break;
+                }
+            }
+        }
+
+        if is_last_page {
+            break;
+        }
+    }
+
+    Ok(output)
+}
+
+/// Options for purging the trash of all tenants
+#[derive(Debug, Clone, Default)]
+pub struct PurgeTrashOptions {
+    /// Options for which tenants to purge
+    pub sweep: TenantSweepOptions,
+    /// Options for purging each tenant
+    pub tenant_options: PurgeTrashTenantOptions,
+}
+
+/// Summary of purging the trash for all tenants
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PurgeTrashSummary {
+    /// Number of tenants that were purged successfully
+    pub tenants_processed: usize,
+    /// Number of tenants that were not purged as the deadline was reached
+    pub tenants_skipped: usize,
+    /// Number of tenants that were only partially purged as the deadline was reached
+    pub tenants_incomplete: usize,
+    /// Number of folders that were purged
+    pub folders_purged: usize,
+    /// Number of files that were purged
+    pub files_purged: usize,
+    /// Number of links that were purged
+    pub links_purged: usize,
+    /// Tenants that failed to purge
+    pub failures: Vec<TenantFailure>,
+    /// Whether this was a dry run where nothing was purged
+    pub dry_run: bool,
+}
+
+/// Purge the trash for all tenants
+///
+/// Tenants are purged concurrently, a failure to purge one tenant
+/// does not prevent the other tenants from being purged
+#[tracing::instrument(skip_all)]
+pub async fn purge_trash(
+    db_cache: &DatabasePoolCache,
+    storage: &StorageLayerFactory,
+    search: &SearchIndexFactory,
+    events: &EventPublisherFactory,
+    options: PurgeTrashOptions,
+) -> Result<PurgeTrashSummary, TenantSweepError> {
+    let tenant_options = options.tenant_options;
+
+    let sweep = sweep_tenants(db_cache, &options.sweep, |tenant, db| async move {
+        let storage = storage.create_storage_layer(&tenant);
+        let search = search.create_search_index(&tenant);
+        let events = events.create_event_publisher(&tenant);
+        purge_trash_tenant(&db, &storage, &search, &events, tenant_options).await
+    })
+    .await?;
+
+    let mut summary = PurgeTrashSummary {
+        tenants_processed: sweep.completed.len(),
+        tenants_skipped: sweep.skipped,
+        failures: sweep.failures,
+        dry_run: tenant_options.dry_run,
+        ..Default::default()
+    };
+
+    for (_, output) in sweep.completed {
+        if output.deadline_reached {
+            summary.tenants_incomplete += 1;
+        }
+
+        summary.folders_purged += output.folders_purged;
+        summary.files_purged += output.files_purged;
+        summary.links_purged += output.links_purged;
+    }
+
+    Ok(summary)
+}